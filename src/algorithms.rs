@@ -1,13 +1,19 @@
+use std::sync::Arc;
+
 use base64ct::{Base64, Encoding};
-use rsa::{
-    signature::{SignatureEncoding, Signer},
-    RsaPrivateKey,
-};
+use rsa::{signature::Verifier, RsaPublicKey};
 use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::c14n;
+use crate::error::Error;
+use crate::signer::HashAlgorithm;
+use crate::signer::Signer;
 
 #[derive(Clone)]
 pub enum CanonicalizationAlgorithm {
     ExclusiveXMLCanonicalization,
+    InclusiveXMLCanonicalization,
     #[cfg(test)]
     NoOp,
 }
@@ -18,6 +24,9 @@ impl CanonicalizationAlgorithm {
             CanonicalizationAlgorithm::ExclusiveXMLCanonicalization => {
                 "http://www.w3.org/2001/10/xml-exc-c14n#"
             }
+            CanonicalizationAlgorithm::InclusiveXMLCanonicalization => {
+                "http://www.w3.org/TR/2001/REC-xml-c14n-20010315"
+            }
             #[cfg(test)]
             CanonicalizationAlgorithm::NoOp => "noop-c14n",
         }
@@ -25,18 +34,48 @@ impl CanonicalizationAlgorithm {
 }
 
 impl CanonicalizationAlgorithm {
-    pub fn run(&self, payload: String) -> String {
+    pub fn from_uri(uri: &str) -> Option<Self> {
+        match uri {
+            "http://www.w3.org/2001/10/xml-exc-c14n#" => {
+                Some(CanonicalizationAlgorithm::ExclusiveXMLCanonicalization)
+            }
+            "http://www.w3.org/TR/2001/REC-xml-c14n-20010315" => {
+                Some(CanonicalizationAlgorithm::InclusiveXMLCanonicalization)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl CanonicalizationAlgorithm {
+    pub fn run(&self, payload: String) -> Result<String, Error> {
+        self.run_in_context(payload, &[])
+    }
+}
+
+impl CanonicalizationAlgorithm {
+    pub fn run_in_context(
+        &self,
+        payload: String,
+        namespaces: &[(String, String)],
+    ) -> Result<String, Error> {
         match &self {
-            CanonicalizationAlgorithm::ExclusiveXMLCanonicalization => payload,
+            CanonicalizationAlgorithm::ExclusiveXMLCanonicalization => {
+                c14n::canonicalize(&payload, namespaces, true)
+            }
+            CanonicalizationAlgorithm::InclusiveXMLCanonicalization => {
+                c14n::canonicalize(&payload, namespaces, false)
+            }
             #[cfg(test)]
-            CanonicalizationAlgorithm::NoOp => payload,
+            CanonicalizationAlgorithm::NoOp => Ok(payload),
         }
     }
 }
 
 #[derive(Clone)]
 pub enum SignatureAlgorithm {
-    RsaSha1(RsaPrivateKey),
+    RsaSha1(Arc<dyn Signer>),
+    RsaSha256(Arc<dyn Signer>),
     #[cfg(test)]
     Echo(String),
 }
@@ -45,6 +84,7 @@ impl SignatureAlgorithm {
     pub fn as_str(&self) -> &str {
         match &self {
             SignatureAlgorithm::RsaSha1(_) => "http://www.w3.org/2000/09/xmldsig#rsa-sha1",
+            SignatureAlgorithm::RsaSha256(_) => "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256",
             #[cfg(test)]
             SignatureAlgorithm::Echo(_) => "echo-signature",
         }
@@ -52,14 +92,62 @@ impl SignatureAlgorithm {
 }
 
 impl SignatureAlgorithm {
-    pub fn run(&self, payload: String) -> String {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping, signer: Arc<dyn Signer>) -> Result<Self, Error> {
+        match yaml.get("algoritmo_assinatura") {
+            Some(serde_yaml::Value::String(it)) => match it.as_str() {
+                "rsa-sha1" => Ok(SignatureAlgorithm::RsaSha1(signer)),
+                "rsa-sha256" => Ok(SignatureAlgorithm::RsaSha256(signer)),
+                _ => Err(Error::input("algoritmo_assinatura", "invalid value")),
+            },
+            Some(_) => Err(Error::input("algoritmo_assinatura", "invalid value")),
+            None => Ok(SignatureAlgorithm::RsaSha1(signer)),
+        }
+    }
+}
+
+impl SignatureAlgorithm {
+    pub fn run(&self, payload: String) -> Result<String, Error> {
         match &self {
-            SignatureAlgorithm::RsaSha1(private_key) => {
-                let signing_key = rsa::pkcs1v15::SigningKey::<Sha1>::new(private_key.to_owned());
-                Base64::encode_string(&signing_key.sign(payload.as_bytes()).to_bytes())
-            }
+            SignatureAlgorithm::RsaSha1(signer) => Ok(Base64::encode_string(
+                &signer.sign(HashAlgorithm::Sha1, payload.as_bytes())?,
+            )),
+            SignatureAlgorithm::RsaSha256(signer) => Ok(Base64::encode_string(
+                &signer.sign(HashAlgorithm::Sha256, payload.as_bytes())?,
+            )),
             #[cfg(test)]
-            SignatureAlgorithm::Echo(s) => s.to_owned(),
+            SignatureAlgorithm::Echo(s) => Ok(s.to_owned()),
+        }
+    }
+}
+
+impl SignatureAlgorithm {
+    pub fn verify(
+        uri: &str,
+        public_key: &RsaPublicKey,
+        payload: String,
+        signature_value: &str,
+    ) -> Result<bool, Error> {
+        let signature_value = Base64::decode_vec(signature_value)
+            .map_err(|_| Error::signing("bad signature value encoding"))?;
+        let signature_value = rsa::pkcs1v15::Signature::try_from(signature_value.as_slice())
+            .map_err(|e| Error::signing("bad signature value").with_source(e))?;
+        match uri {
+            "http://www.w3.org/2000/09/xmldsig#rsa-sha1" => {
+                let verifying_key = rsa::pkcs1v15::VerifyingKey::<Sha1>::new(public_key.to_owned());
+                Ok(verifying_key
+                    .verify(payload.as_bytes(), &signature_value)
+                    .is_ok())
+            }
+            "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256" => {
+                let verifying_key =
+                    rsa::pkcs1v15::VerifyingKey::<Sha256>::new(public_key.to_owned());
+                Ok(verifying_key
+                    .verify(payload.as_bytes(), &signature_value)
+                    .is_ok())
+            }
+            _ => Err(Error::signing(format!(
+                "unsupported signature algorithm: {uri}"
+            ))),
         }
     }
 }
@@ -67,6 +155,7 @@ impl SignatureAlgorithm {
 #[derive(Clone)]
 pub enum DigestAlgorithm {
     Sha1,
+    Sha256,
     #[cfg(test)]
     Echo(String),
 }
@@ -75,18 +164,167 @@ impl DigestAlgorithm {
     pub fn as_str(&self) -> &str {
         match &self {
             DigestAlgorithm::Sha1 => "http://www.w3.org/2000/09/xmldsig#sha1",
+            DigestAlgorithm::Sha256 => "http://www.w3.org/2001/04/xmlenc#sha256",
             #[cfg(test)]
             DigestAlgorithm::Echo(_) => "echo-digest",
         }
     }
 }
 
+impl DigestAlgorithm {
+    pub fn from_uri(uri: &str) -> Option<Self> {
+        match uri {
+            "http://www.w3.org/2000/09/xmldsig#sha1" => Some(DigestAlgorithm::Sha1),
+            "http://www.w3.org/2001/04/xmlenc#sha256" => Some(DigestAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+}
+
+impl DigestAlgorithm {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping) -> Result<Self, Error> {
+        match yaml.get("algoritmo_digest") {
+            Some(serde_yaml::Value::String(it)) => match it.as_str() {
+                "sha1" => Ok(DigestAlgorithm::Sha1),
+                "sha256" => Ok(DigestAlgorithm::Sha256),
+                _ => Err(Error::input("algoritmo_digest", "invalid value")),
+            },
+            Some(_) => Err(Error::input("algoritmo_digest", "invalid value")),
+            None => Ok(DigestAlgorithm::Sha1),
+        }
+    }
+}
+
 impl DigestAlgorithm {
     pub fn run(&self, payload: String) -> String {
         match &self {
             DigestAlgorithm::Sha1 => Base64::encode_string(&Sha1::digest(payload)),
+            DigestAlgorithm::Sha256 => Base64::encode_string(&Sha256::digest(payload)),
             #[cfg(test)]
             DigestAlgorithm::Echo(s) => s.to_owned(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::DigestAlgorithm;
+    use super::SignatureAlgorithm;
+
+    const CERTIFICATE: &str = include_str!("../tests/fixtures/certificado.cer");
+    const PRIVATE_KEY: &str = include_str!("../tests/fixtures/certificado.key");
+
+    fn private_key() -> Arc<rsa::RsaPrivateKey> {
+        Arc::new(
+            <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_pem(PRIVATE_KEY)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn should_digest_known_answers() {
+        assert_eq!(
+            DigestAlgorithm::Sha1.run(String::from("abc")),
+            String::from("qZk+NkcGgWq6PiVxeFDCbJzQ2J0=")
+        );
+        assert_eq!(
+            DigestAlgorithm::Sha256.run(String::from("abc")),
+            String::from("ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=")
+        );
+        assert_eq!(
+            DigestAlgorithm::Echo(String::from("the_digest")).run(String::from("abc")),
+            String::from("the_digest")
+        );
+    }
+
+    #[test]
+    fn should_sign_known_answers() {
+        let rsa_sha1 = "MLnOHfWM/SWgqOIzty1ov7Bn8fllh8pYKE67BYfexijqWFRccNf9KbkL9seHBxMxY4NfqjRRSKkur7CaOedKATnsYsnuY65oqvQJ8Vs+cDZXi4//2EwIpH/ob72bNCkwW6kyaJwH2TmefpNrKDjWcpL4jNA0yHLlqSXjOtaTJKJ3qyd+6wlvDliGPKYnSx/taqvHu7yax1ZqRy3uGzKVnv+boxhSYRz+T5/7sidAkTpbLmtawtgkihe6mx4i5XI2l41+WTedxuMmxke+uYloC3Ki+DbMdsCxrFssp1KRVC120AuNDi/ESdEpS0XC03ES/ACQd8GlplkyW+7f8Oc8Jg==";
+        let rsa_sha256 = "WEtb6ztC31X2nNEklglypelyzZwd+5YelX1ydCMiMm1T/kLnznq2pLvEKxG2Yp2DJr2lS8qq97jHnYweT0NfHDUSt4wp2hv876Bhyh/+OClLNBV4SiGcMuDifoyxDuOpRaKMGspg50QlADuTILh4Hl8QrDGFTtUlK0OpTlLj54ho6E3Hg5lu5z3xpMeSvO1duCjFH7v7BaaoY9Ja/kmrIZE2bHx44eu2chCO0rdGQviILb0TYxGsLgmmOdwCK3JYOVG7IVtKmyF2CxWqaV4XkOKRbMOsYIE/6egbfeQVnd8WN+3nZTI2fEQL7NOGlZYF6JKLEuJShNnhDTD1r3zpTQ==";
+
+        assert_eq!(
+            SignatureAlgorithm::RsaSha1(private_key())
+                .run(String::from("abc"))
+                .unwrap(),
+            String::from(rsa_sha1)
+        );
+        assert_eq!(
+            SignatureAlgorithm::RsaSha256(private_key())
+                .run(String::from("abc"))
+                .unwrap(),
+            String::from(rsa_sha256)
+        );
+        assert_eq!(
+            SignatureAlgorithm::Echo(String::from("the_signature"))
+                .run(String::from("abc"))
+                .unwrap(),
+            String::from("the_signature")
+        );
+
+        let public_key = crate::certificate::Certificate::from_base64(
+            &crate::utils::trim_x509_certificate(CERTIFICATE),
+        )
+        .unwrap()
+        .public_key()
+        .unwrap();
+
+        assert!(SignatureAlgorithm::verify(
+            SignatureAlgorithm::RsaSha1(private_key()).as_str(),
+            &public_key,
+            String::from("abc"),
+            rsa_sha1,
+        )
+        .unwrap());
+        assert!(SignatureAlgorithm::verify(
+            SignatureAlgorithm::RsaSha256(private_key()).as_str(),
+            &public_key,
+            String::from("abc"),
+            rsa_sha256,
+        )
+        .unwrap());
+        assert!(!SignatureAlgorithm::verify(
+            SignatureAlgorithm::RsaSha256(private_key()).as_str(),
+            &public_key,
+            String::from("abc"),
+            rsa_sha1,
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn should_select_algorithms_from_yaml() {
+        let yaml: serde_yaml::Mapping = serde_yaml::from_str(
+            "
+algoritmo_assinatura: rsa-sha256
+algoritmo_digest: sha256
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            SignatureAlgorithm::from_yaml(&yaml, private_key())
+                .unwrap()
+                .as_str(),
+            "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256"
+        );
+        assert_eq!(
+            DigestAlgorithm::from_yaml(&yaml).unwrap().as_str(),
+            "http://www.w3.org/2001/04/xmlenc#sha256"
+        );
+
+        let yaml = serde_yaml::Mapping::new();
+
+        assert_eq!(
+            SignatureAlgorithm::from_yaml(&yaml, private_key())
+                .unwrap()
+                .as_str(),
+            "http://www.w3.org/2000/09/xmldsig#rsa-sha1"
+        );
+        assert_eq!(
+            DigestAlgorithm::from_yaml(&yaml).unwrap().as_str(),
+            "http://www.w3.org/2000/09/xmldsig#sha1"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use nfse_bh_rust::credentials::Credentials;
+use nfse_bh_rust::pedido_cancelamento::ConfirmacaoCancelamento;
+use nfse_bh_rust::pedido_cancelamento::PedidoCancelamento;
+use nfse_bh_rust::resposta::Resposta;
+use nfse_bh_rust::signature::SignatureVerification;
+use nfse_bh_rust::utils::request_wrapper;
+use nfse_bh_rust::utils::webservice_request;
+use nfse_bh_rust::utils::xml_events_to_xml_string;
+
+fn main() -> Result<(), String> {
+    let stdin = std::io::stdin();
+    let args = std::env::args().collect::<Vec<String>>();
+
+    let default_yaml_file_name = String::from("input.yml");
+    let input_contents = args.get(1).unwrap_or(&default_yaml_file_name);
+
+    let input_contents =
+        std::fs::read_to_string(input_contents).expect("unable to read input file");
+    let input_contents = serde_yaml::from_str(&input_contents);
+
+    let input_contents = match &input_contents {
+        Ok(yaml) => match yaml {
+            serde_yaml::Value::Mapping(it) => Ok(it),
+            _ => Err("bad yaml input"),
+        },
+        Err(_) => Err("bad yaml input"),
+    }?;
+
+    let production = match input_contents.get("producao") {
+        Some(serde_yaml::Value::Bool(it)) => Ok(it),
+        _ => Err("bad yaml input: producao"),
+    }?;
+
+    let credentials =
+        Credentials::from_yaml(input_contents, &|prompt| rpassword::prompt_password(prompt))?;
+
+    print!(
+        "Digite o número da NFS-e a cancelar no ambiente de {}: ",
+        if *production { "PRODUÇÃO" } else { "teste" }
+    );
+    std::io::Write::flush(&mut std::io::stdout()).unwrap();
+    let mut numero = String::new();
+    stdin.read_line(&mut numero).unwrap();
+    numero.pop(); // remove \n
+
+    print!("Digite o código de cancelamento (1 erro na emissão, 2 serviço não prestado, 3 erro de assinatura, 4 duplicidade da nota, 5 erro de processamento): ");
+    std::io::Write::flush(&mut std::io::stdout()).unwrap();
+    let mut codigo_cancelamento = String::new();
+    stdin.read_line(&mut codigo_cancelamento).unwrap();
+    codigo_cancelamento.pop(); // remove \n
+
+    let mut pedido = PedidoCancelamento::from_yaml(input_contents)?
+        .set_numero(numero.clone())
+        .set_codigo_cancelamento(codigo_cancelamento)?;
+
+    let (signature, warnings) =
+        credentials.xml_signature(input_contents, pedido.get_cnpj(), &|prompt| {
+            rpassword::prompt_password(prompt)
+        })?;
+
+    warnings
+        .iter()
+        .for_each(|warning| println!("warning: {warning}"));
+
+    pedido.sign(signature)?;
+
+    let cancelar_nfse_envio = xml_events_to_xml_string(&pedido.cancelar_nfse_envio_xml_events());
+
+    if SignatureVerification::from_xml_string(&cancelar_nfse_envio)?
+        .iter()
+        .any(|verification| !verification.is_valid())
+    {
+        return Err(String::from("generated signatures are not valid"));
+    }
+
+    let request_data = request_wrapper("CancelarNfse", &cancelar_nfse_envio);
+
+    print!(
+        "Digite SIM para confirmar o cancelamento da NFS-e {} em ambiente de {}: ",
+        numero,
+        if *production { "PRODUÇÃO" } else { "teste" }
+    );
+    std::io::Write::flush(&mut std::io::stdout()).unwrap();
+    let mut confirm = String::new();
+    stdin.read_line(&mut confirm).unwrap();
+    confirm.pop(); // remove \n
+
+    if &confirm != "SIM" {
+        return Err(String::from("confirmation failed"));
+    }
+
+    let req = webservice_request(
+        *production,
+        credentials.certificado_pem_file(),
+        credentials.client_certificate()?,
+        "http://ws.bhiss.pbh.gov.br/CancelarNfse",
+        request_data,
+    );
+
+    let (status_code, data) = req.run()?;
+
+    let resposta = Resposta::from_soap(status_code, &String::from_utf8_lossy(&data))?;
+
+    let confirmacao = ConfirmacaoCancelamento::from_resposta(&resposta)?;
+
+    if !confirmacao.sucesso {
+        return Err(format!(
+            "cancelamento não confirmado pela prefeitura em {}",
+            confirmacao.data_hora
+        ));
+    }
+
+    println!(
+        "NFS-e {} cancelada com sucesso em {}",
+        numero, confirmacao.data_hora
+    );
+
+    Ok(())
+}
@@ -1,10 +1,21 @@
+use nfse_bh_rust::credentials::Credentials;
 use nfse_bh_rust::curl::Request;
 use nfse_bh_rust::curl::RequestMethod;
+use nfse_bh_rust::error::Error;
 use nfse_bh_rust::lote_rps::LoteRps;
 use nfse_bh_rust::nfse::Nfse;
+use nfse_bh_rust::numeracao::NumeracaoRps;
 use nfse_bh_rust::protocolo::Protocolo;
+use nfse_bh_rust::protocolo::SituacaoLoteRps;
+use nfse_bh_rust::resposta::Resposta;
+use nfse_bh_rust::signature::SignatureVerification;
+use nfse_bh_rust::utils::request_wrapper;
+use nfse_bh_rust::utils::webservice_request;
 use nfse_bh_rust::utils::xml_events_to_xml_string;
 
+const TENTATIVAS_SITUACAO: u32 = 12;
+const INTERVALO_SITUACAO: std::time::Duration = std::time::Duration::from_secs(10);
+
 fn main() -> Result<(), String> {
     let stdin = std::io::stdin();
     let args = std::env::args().collect::<Vec<String>>();
@@ -13,7 +24,7 @@ fn main() -> Result<(), String> {
     let input_contents = args.get(1).unwrap_or(&default_yaml_file_name);
 
     let input_contents =
-        std::fs::read_to_string(&input_contents).expect("unable to read input file");
+        std::fs::read_to_string(input_contents).expect("unable to read input file");
     let input_contents = serde_yaml::from_str(&input_contents);
 
     let input_contents = match &input_contents {
@@ -24,27 +35,30 @@ fn main() -> Result<(), String> {
         Err(_) => Err("bad yaml input"),
     }?;
 
-    let lote_rps = LoteRps::from_yaml(input_contents)
-        .unwrap()
-        .get_rpses()
-        .map(|rps| (rps.uniquely_identify(), rps.nome_arquivo.clone()))
-        .collect::<Vec<_>>();
+    // only looks up the numbers given by send, never allocates new ones
+    let numeracao = NumeracaoRps::from_yaml(input_contents)?;
+
+    let lote_rps = LoteRps::from_yaml(input_contents, &|cnpj, serie, nome_arquivo| {
+        numeracao
+            .consultar(cnpj, serie, nome_arquivo)?
+            .ok_or_else(|| {
+                Error::input(
+                    "nome_arquivo",
+                    &format!("no RPS number was allocated to {nome_arquivo}, send it first"),
+                )
+            })
+    })?;
 
     let production = match input_contents.get("producao") {
-        Some(it) => match it {
-            serde_yaml::Value::Bool(it) => Ok(it),
-            _ => Err("bad yaml input: producao"),
-        },
-        None => Err("bad yaml input: producao"),
+        Some(serde_yaml::Value::Bool(it)) => Ok(it),
+        _ => Err("bad yaml input: producao"),
     }?;
 
-    let certificado_pem_file = match input_contents.get("certificado_pem") {
-        Some(it) => match it {
-            serde_yaml::Value::String(it) => Ok(it),
-            _ => Err("bad yaml input: certificado_pem"),
-        },
-        None => Err("bad yaml input: certificado_pem"),
-    }?;
+    let credentials =
+        Credentials::from_yaml(input_contents, &|prompt| rpassword::prompt_password(prompt))?;
+
+    let certificado_pem_file = credentials.certificado_pem_file();
+    let client_certificate = credentials.client_certificate()?;
 
     print!(
         "Digite o número de protocolo no ambiente de {}: ",
@@ -55,133 +69,127 @@ fn main() -> Result<(), String> {
     stdin.read_line(&mut protocolo).unwrap();
     protocolo.pop(); // remove \n
 
-    let protocolo = Protocolo::from_yaml(&input_contents)
-        .unwrap()
-        .set_protocolo(protocolo);
+    let protocolo = Protocolo::from_yaml(input_contents)?.set_protocolo(protocolo);
+
+    let content = xml_events_to_xml_string(&protocolo.situacao_lote_rps_xml_events());
+
+    for tentativa in 1..=TENTATIVAS_SITUACAO {
+        let (status_code, data) = webservice_request(
+            *production,
+            certificado_pem_file.clone(),
+            client_certificate.clone(),
+            "http://ws.bhiss.pbh.gov.br/ConsultarSituacaoLoteRps",
+            request_wrapper("ConsultarSituacaoLoteRps", &content),
+        )
+        .run()?;
+
+        let resposta = Resposta::from_soap(status_code, &String::from_utf8_lossy(&data))?;
+        let situacao = SituacaoLoteRps::from_resposta(&resposta)?;
+
+        if situacao.is_final() {
+            break;
+        }
+
+        if tentativa == TENTATIVAS_SITUACAO {
+            return Err(format!("lote ainda não processado: {situacao:?}"));
+        }
+
+        println!("Lote ainda não processado ({situacao:?}), aguardando...");
+        std::thread::sleep(INTERVALO_SITUACAO);
+    }
 
     let content = xml_events_to_xml_string(&protocolo.protocolo_xml_events());
 
-    let request_data = format!(
-        r##"<soapenv:Envelope xmlns:soapenv="http://schemas.xmlsoap.org/soap/envelope/" xmlns:ws="http://ws.bhiss.pbh.gov.br"><soapenv:Body><ws:ConsultarLoteRpsRequest><nfseCabecMsg><![CDATA[<?xml version="1.0" encoding="UTF-8"?><cabecalho xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><versaoDados>1.00</versaoDados></cabecalho>]]></nfseCabecMsg><nfseDadosMsg><![CDATA[<?xml version="1.0" encoding="UTF-8"?>{content}]]></nfseDadosMsg></ws:ConsultarLoteRpsRequest></soapenv:Body></soapenv:Envelope>"##
+    let req = webservice_request(
+        *production,
+        certificado_pem_file.clone(),
+        client_certificate.clone(),
+        "http://ws.bhiss.pbh.gov.br/ConsultarLoteRpsEnvio",
+        request_wrapper("ConsultarLoteRps", &content),
     );
 
-    let req = Request::new()
-        .set_certificate_path(Some(certificado_pem_file.to_string()))
-        .set_url(if *production {
-            String::from("https://bhissdigitalws.pbh.gov.br/bhiss-ws/nfse")
-        } else {
-            String::from("https://bhisshomologaws.pbh.gov.br/bhiss-ws/nfse")
-        })
-        .set_header(
-            String::from("Accept"),
-            Some(String::from("application/xml")),
-        )
-        .set_header(String::from("Content-Type"), Some(String::from("text/xml")))
-        .set_header(
-            String::from("SOAPAction"),
-            Some(String::from(
-                "http://ws.bhiss.pbh.gov.br/ConsultarLoteRpsEnvio",
-            )),
-        )
-        .set_method(RequestMethod::POST(request_data));
+    let (status_code, data) = req.run()?;
 
-    let (status_code, data) = req.run().unwrap();
+    let resposta = Resposta::from_soap(status_code, &String::from_utf8_lossy(&data))?;
 
-    let data = String::from_utf8(data).unwrap();
+    let (nfses, xmls): (Vec<_>, Vec<_>) = Nfse::from_resposta(&resposta)?.into_iter().unzip();
 
-    if status_code != 200 {
-        return Err(format!(
-            "error in request (status {}), {}",
-            status_code, data
-        ));
-    }
+    let dir_name = format!(
+        "output-{}-{}",
+        protocolo.protocolo,
+        chrono::Utc::now().format("%Y-%m-%d-%H-%M")
+    );
 
-    let data = data
-        .split_once("<outputXML>")
-        .ok_or(String::from("expected outputXML tag"))?
-        .1
-        .split_once("</outputXML>")
-        .ok_or(String::from("expected outputXML tag"))?
-        .0;
-
-    let data = data
-        .replace("&quot;", "\"")
-        .replace("&lt;", "<")
-        .replace("&gt;", ">");
-
-    let nfses = data
-        .strip_prefix("<?xml version='1.0' encoding='UTF-8'?><ConsultarLoteRpsResposta xmlns=\"http://www.abrasf.org.br/nfse.xsd\"><ListaNfse>")
-        .ok_or(String::from("bad xml prefix"))?
-        .strip_suffix("</ListaNfse></ConsultarLoteRpsResposta>")
-        .ok_or(String::from("bad xml suffix"))?
-        .split("<CompNfse")
-        .skip(1)
-        .map(|nf| {
-            let xml = format!("<?xml version='1.0' encoding='UTF-8'?><CompNfse{nf}");
-            let nfse = Nfse::from_xml_string(&xml).unwrap();
-            let ui = nfse.uniquely_identify();
-            (ui, xml)
-        })
-        .collect::<Vec<(String, String)>>();
-
-    let dir_name = format!("output-{}-{}", protocolo.protocolo, chrono::Utc::now().format("%Y-%m-%d-%H-%M"));
-
-    std::fs::create_dir(&dir_name).unwrap();
-
-    nfses.iter().for_each(|(ui, xml)| {
-        let nome_arquivo = lote_rps.iter().find(|rps| *ui == rps.0).unwrap().1.clone();
-        let mut xml_file =
-            std::fs::File::create_new(&format!("{dir_name}/{nome_arquivo}_NFS.xml")).unwrap();
-        std::io::Write::write_all(&mut xml_file, xml.as_bytes()).unwrap();
-
-        let chave_acesso: Result<String, String> = 'a: {
-            let chave = match xml
-                .split_once("<OutrasInformacoes>Chave de acesso no Ambiente de Dados Nacional: ")
-            {
-                Some(s) => s.1,
-                None => {
-                    break 'a Err(String::from("can not find chave_acesso"));
-                }
-            };
+    std::fs::create_dir(&dir_name).map_err(|e| format!("unable to create {dir_name}: {e}"))?;
 
-            let chave = match chave.split_once(".</OutrasInformacoes>") {
-                Some(s) => s.0,
-                None => {
-                    break 'a Err(String::from("can not find chave_acesso"));
-                }
-            };
+    let nomes_arquivos = lote_rps.nomes_arquivos_das_nfses(&nfses);
 
-            if chave.len() != 50 {
-                panic!("bad size for chave_acesso");
-            }
+    let sem_rps = nfses
+        .iter()
+        .zip(&nomes_arquivos)
+        .filter(|(_, nome_arquivo)| nome_arquivo.is_none())
+        .map(|(nfse, _)| nfse.numero())
+        .collect::<Vec<_>>();
 
-            Ok(chave.to_string())
+    if !sem_rps.is_empty() {
+        println!(
+            "NFS-e sem RPS correspondente no arquivo de entrada: {}",
+            sem_rps.join(", ")
+        );
+    }
+
+    for ((nfse, xml), nome_arquivo) in nfses.iter().zip(&xmls).zip(nomes_arquivos) {
+        let nome_arquivo = match nome_arquivo {
+            Some(it) => it.to_owned(),
+            None => format!("NFSE_{}", nfse.numero()),
         };
 
-        match chave_acesso {
-            Ok(chave) => {
+        let gravado = std::fs::File::create_new(format!("{dir_name}/{nome_arquivo}_NFS.xml"))
+            .and_then(|mut xml_file| std::io::Write::write_all(&mut xml_file, xml.as_bytes()));
+
+        if let Err(e) = gravado {
+            println!("skipping {nome_arquivo}: unable to write xml: {e}");
+            continue;
+        }
+
+        match SignatureVerification::from_xml_string(xml) {
+            Ok(verifications) if verifications.iter().all(|v| v.is_valid()) => {}
+            Ok(_) => println!("invalid signature for {nome_arquivo}"),
+            Err(e) => println!("could not verify signature for {nome_arquivo}: {e}"),
+        }
+
+        match nfse.chave_acesso() {
+            Some(chave) => {
                 let req = Request::new()
-                    .set_certificate_path(Some(certificado_pem_file.to_string()))
+                    .set_certificate_path(certificado_pem_file.clone())
+                    .set_client_certificate(client_certificate.clone())
                     .set_url(format!(
                         "https://sefin.nfse.gov.br/sefinnacional/danfse/{chave}"
                     ))
                     .set_method(RequestMethod::GET);
 
-                let (status_code, data) = req.run().unwrap();
-
-                if status_code == 200 {
-                    let mut pdf_file =
-                        std::fs::File::create_new(&format!("{dir_name}/{nome_arquivo}_NFS.pdf")).unwrap();
-                    std::io::Write::write_all(&mut pdf_file, &data).unwrap();
-                } else {
-                    println!("skipping pdf for {nome_arquivo}: {status_code}");
+                match req.run() {
+                    Ok((200, data)) => {
+                        if let Err(e) =
+                            std::fs::File::create_new(format!("{dir_name}/{nome_arquivo}_NFS.pdf"))
+                                .and_then(|mut pdf_file| {
+                                    std::io::Write::write_all(&mut pdf_file, &data)
+                                })
+                        {
+                            println!("skipping pdf for {nome_arquivo}: {e}");
+                        }
+                    }
+                    Ok((status_code, _)) => {
+                        println!("skipping pdf for {nome_arquivo}: {status_code}")
+                    }
+                    Err(e) => println!("skipping pdf for {nome_arquivo}: {e}"),
                 }
             }
-            Err(e) => {
-                println!("skipping pdf for {nome_arquivo}: {e}");
+            None => {
+                println!("skipping pdf for {nome_arquivo}: can not find chave_acesso");
             }
         }
-    });
+    }
 
     Ok(())
 }
@@ -1,23 +1,27 @@
-use nfse_bh_rust::algorithms::CanonicalizationAlgorithm;
-use nfse_bh_rust::algorithms::DigestAlgorithm;
-use nfse_bh_rust::algorithms::SignatureAlgorithm;
-use nfse_bh_rust::curl::Request;
-use nfse_bh_rust::curl::RequestMethod;
+use nfse_bh_rust::credentials::Credentials;
 use nfse_bh_rust::lote_rps::LoteRps;
-use nfse_bh_rust::signature::XmlSignature;
-use nfse_bh_rust::utils::recepcionar_lote_rps_request_wrapper;
-use nfse_bh_rust::utils::trim_x509_certificate;
-use nfse_bh_rust::utils::xml_events_to_xml_string;
+use nfse_bh_rust::lote_rps::ResultadoLote;
+use nfse_bh_rust::lote_rps::RetornoLote;
+use nfse_bh_rust::nfse::Nfse;
+use nfse_bh_rust::numeracao::NumeracaoRps;
+use nfse_bh_rust::utils::webservice_request;
 
 fn main() -> Result<(), String> {
     let stdin = std::io::stdin();
     let args = std::env::args().collect::<Vec<String>>();
 
+    // --sincrono emits through GerarNfse and gets the notes back in the same request
+    let sincrono = args.iter().any(|arg| arg == "--sincrono");
+    let args = args
+        .into_iter()
+        .filter(|arg| arg != "--sincrono")
+        .collect::<Vec<String>>();
+
     let default_yaml_file_name = String::from("input.yml");
     let input_contents = args.get(1).unwrap_or(&default_yaml_file_name);
 
     let input_contents =
-        std::fs::read_to_string(&input_contents).expect("unable to read input file");
+        std::fs::read_to_string(input_contents).expect("unable to read input file");
     let input_contents = serde_yaml::from_str(&input_contents);
 
     let input_contents = match &input_contents {
@@ -29,68 +33,42 @@ fn main() -> Result<(), String> {
     }?;
 
     let production = match input_contents.get("producao") {
-        Some(it) => match it {
-            serde_yaml::Value::Bool(it) => Ok(it),
-            _ => Err("bad yaml input: producao"),
-        },
-        None => Err("bad yaml input: producao"),
-    }?;
-
-    let private_key = match input_contents.get("certificado_key") {
-        Some(it) => match it {
-            serde_yaml::Value::String(it) => {
-                let key_contents = std::fs::read_to_string(it)
-                    .map_err(|_| "could not read file at certificado_key")?;
-                <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_pem(&key_contents)
-                    .map_err(|_| "file at certificado_key is not valid private key")
-            }
-            _ => Err("bad yaml input: certificado_key"),
-        },
-        None => Err("bad yaml input: certificado_key"),
+        Some(serde_yaml::Value::Bool(it)) => Ok(it),
+        _ => Err("bad yaml input: producao"),
     }?;
 
-    let certificate = match input_contents.get("certificado_cer") {
-        Some(it) => match it {
-            serde_yaml::Value::String(it) => {
-                let cer_contents = std::fs::read_to_string(it)
-                    .map_err(|_| "could not read file at certificado_cer")?;
-                Ok(trim_x509_certificate(&cer_contents))
-            }
-            _ => Err("bad yaml input: certificado_cer"),
-        },
-        None => Err("bad yaml input: certificado_cer"),
-    }?;
+    let credentials =
+        Credentials::from_yaml(input_contents, &|prompt| rpassword::prompt_password(prompt))?;
 
-    let certificado_pem_file = match input_contents.get("certificado_pem") {
-        Some(it) => match it {
-            serde_yaml::Value::String(it) => Ok(it),
-            _ => Err("bad yaml input: certificado_pem"),
-        },
-        None => Err("bad yaml input: certificado_pem"),
-    }?;
+    let numeracao = NumeracaoRps::from_yaml(input_contents)?;
 
-    let signature = XmlSignature::new(
-        CanonicalizationAlgorithm::ExclusiveXMLCanonicalization,
-        SignatureAlgorithm::RsaSha1(private_key),
-        DigestAlgorithm::Sha1,
-        certificate,
-    );
+    let mut lote_rps = LoteRps::from_yaml(input_contents, &|cnpj, serie, nome_arquivo| {
+        numeracao.reservar(cnpj, serie, nome_arquivo)
+    })?;
 
-    let mut lote_rps = LoteRps::from_yaml(input_contents).unwrap();
+    let (signature, warnings) =
+        credentials.xml_signature(input_contents, lote_rps.get_cnpj(), &|prompt| {
+            rpassword::prompt_password(prompt)
+        })?;
 
-    lote_rps
-        .get_rpses()
-        .for_each(|rps| rps.sign(signature.clone()));
+    warnings
+        .iter()
+        .for_each(|warning| println!("warning: {warning}"));
 
-    lote_rps.sign(signature);
+    for rps in lote_rps.get_rpses() {
+        rps.sign(signature.clone())?;
+    }
 
-    let request_data = recepcionar_lote_rps_request_wrapper(&xml_events_to_xml_string(
-        &lote_rps.enviar_lote_rps_envio_events(),
-    ));
+    // the webservice limits the notes and bytes of each lote, so large inputs go in several
+    let mut lotes = lote_rps.dividir()?;
 
     print!(
-        "Digite SIM para confirmar a emissão de {} notas fiscais em ambiente de {}: ",
-        lote_rps.get_rpses().len(),
+        "Digite SIM para confirmar a emissão de {} notas fiscais em {} lote(s) em ambiente de {}: ",
+        lotes
+            .iter_mut()
+            .map(|lote_rps| lote_rps.get_rpses().count())
+            .sum::<usize>(),
+        lotes.len(),
         if *production { "PRODUÇÃO" } else { "teste" }
     );
     std::io::Write::flush(&mut std::io::stdout()).unwrap();
@@ -102,46 +80,100 @@ fn main() -> Result<(), String> {
         return Err(String::from("confirmation failed"));
     }
 
-    let req = Request::new()
-        .set_certificate_path(Some(certificado_pem_file.to_string()))
-        .set_url(if *production {
-            String::from("https://bhissdigitalws.pbh.gov.br/bhiss-ws/nfse")
-        } else {
-            String::from("https://bhisshomologaws.pbh.gov.br/bhiss-ws/nfse")
-        })
-        .set_header(
-            String::from("Accept"),
-            Some(String::from("application/xml")),
-        )
-        .set_header(String::from("Content-Type"), Some(String::from("text/xml")))
-        .set_header(
-            String::from("SOAPAction"),
-            Some(String::from(
-                "http://ws.bhiss.pbh.gov.br/RecepcionarLoteRps",
-            )),
-        )
-        .set_method(RequestMethod::POST(request_data));
-
-    let (status_code, data) = req.run().unwrap();
-
-    let data = String::from_utf8(data).unwrap();
-
-    if status_code != 200 {
+    let dir_name = format!("output-{}", chrono::Utc::now().format("%Y-%m-%d-%H-%M"));
+
+    if sincrono {
+        std::fs::create_dir(&dir_name).map_err(|e| format!("unable to create {dir_name}: {e}"))?;
+    }
+
+    // lote numbers are only allocated once the emission is confirmed
+    let resultados = LoteRps::enviar_lotes(
+        lotes,
+        &signature,
+        &numeracao,
+        sincrono,
+        &|soap_action, request_data| {
+            webservice_request(
+                *production,
+                credentials.certificado_pem_file(),
+                credentials.client_certificate()?,
+                soap_action,
+                request_data,
+            )
+            .run()
+        },
+    )?;
+
+    let mut resumo = Vec::new();
+
+    for ResultadoLote {
+        numero_lote,
+        nomes_arquivos,
+        resultado,
+    } in resultados
+    {
+        let resultado = match resultado {
+            Ok(RetornoLote::Protocolo(protocolo)) => {
+                println!("Lote {numero_lote} enviado com sucesso! Protocolo: {protocolo}");
+                Ok(format!("protocolo {protocolo}"))
+            }
+            Ok(RetornoLote::Nfses(nfses)) => {
+                gravar_nfses(&dir_name, &nfses).map(|_| format!("{} NFS-e emitidas", nfses.len()))
+            }
+            Err(e) => Err(e.to_string()),
+        };
+
+        if let Err(e) = &resultado {
+            println!("Lote {numero_lote} não enviado: {e}");
+        }
+
+        resumo.push((numero_lote, nomes_arquivos, resultado));
+    }
+
+    println!("\nResumo:");
+    for (numero_lote, nomes_arquivos, resultado) in &resumo {
+        let resultado = match resultado {
+            Ok(it) => it.clone(),
+            Err(e) => format!("falhou: {e}"),
+        };
+        for nome_arquivo in nomes_arquivos {
+            println!("{nome_arquivo}: lote {numero_lote}, {resultado}");
+        }
+    }
+
+    let falhas = resumo
+        .iter()
+        .filter(|(_, _, resultado)| resultado.is_err())
+        .count();
+
+    if falhas > 0 {
         return Err(format!(
-            "error in request (status {}), {}",
-            status_code, data
+            "{falhas} de {} lote(s) não foram enviados",
+            resumo.len()
         ));
     }
 
-    let protocolo = data
-        .split_once("Protocolo&gt;")
-        .unwrap()
-        .1
-        .split_once("&lt;/Protocolo")
-        .unwrap()
-        .0;
+    Ok(())
+}
+
+// the xml of each note emitted by GerarNfse, named after the nome_arquivo of its rps
+fn gravar_nfses(dir_name: &str, nfses: &[(Nfse, String, Option<String>)]) -> Result<(), String> {
+    for (nfse, xml, nome_arquivo) in nfses {
+        let nome_arquivo = match nome_arquivo {
+            Some(it) => it.to_owned(),
+            None => {
+                println!("NFS-e {} sem RPS correspondente no lote", nfse.numero());
+                format!("NFSE_{}", nfse.numero())
+            }
+        };
+
+        let mut xml_file = std::fs::File::create_new(format!("{dir_name}/{nome_arquivo}_NFS.xml"))
+            .map_err(|e| format!("unable to write {nome_arquivo}_NFS.xml: {e}"))?;
+        std::io::Write::write_all(&mut xml_file, xml.as_bytes())
+            .map_err(|e| format!("unable to write {nome_arquivo}_NFS.xml: {e}"))?;
 
-    println!("Enviado com sucesso! Protocolo: {}", protocolo);
+        println!("NFS-e {} emitida para {nome_arquivo}", nfse.numero());
+    }
 
     Ok(())
 }
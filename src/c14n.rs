@@ -0,0 +1,328 @@
+use quick_xml::escape::unescape;
+use quick_xml::events::BytesStart;
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+
+use crate::error::Error;
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+pub type Namespaces = Vec<(String, String)>;
+
+pub fn exclusive_xml_canonicalization(xml: &str) -> Result<String, Error> {
+    canonicalize(xml, &[], true)
+}
+
+pub fn inclusive_xml_canonicalization(xml: &str) -> Result<String, Error> {
+    canonicalize(xml, &[], false)
+}
+
+// `inherited` holds the namespaces in scope at the parent of the document element, for when the
+// payload is an element cut out of a larger document.
+pub fn canonicalize(
+    xml: &str,
+    inherited: &[(String, String)],
+    exclusive: bool,
+) -> Result<String, Error> {
+    let xml = normalize_line_endings(xml);
+
+    let mut reader = Reader::from_str(&xml);
+    reader.expand_empty_elements(true);
+
+    let mut output = String::new();
+
+    // namespaces declared in the input and namespaces rendered in the output, per open element
+    let mut declared: Vec<Namespaces> = vec![inherited.to_vec()];
+    let mut rendered: Vec<Namespaces> = vec![Vec::new()];
+
+    let mut after_document_element = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let start_tag = canonical_start_tag(&e, &mut declared, &mut rendered, exclusive)?;
+                output.push_str(&start_tag);
+            }
+            Ok(Event::End(e)) => {
+                let name = utf8(e.name().as_ref())?.to_owned();
+                output.push_str(&format!("</{name}>"));
+                declared.pop();
+                rendered.pop();
+                if declared.len() == 1 {
+                    after_document_element = true;
+                }
+            }
+            Ok(Event::Text(e)) => {
+                if declared.len() > 1 {
+                    let text = unescape(utf8(&e)?)?;
+                    output.push_str(&escape_text(&text));
+                }
+            }
+            Ok(Event::CData(e)) => {
+                if declared.len() > 1 {
+                    output.push_str(&escape_text(utf8(&e)?));
+                }
+            }
+            Ok(Event::PI(e)) => {
+                let content = utf8(&e)?;
+                let (target, data) = match content.split_once(char::is_whitespace) {
+                    Some((target, data)) => (target, data.trim_start()),
+                    None => (content, ""),
+                };
+                let pi = if data.is_empty() {
+                    format!("<?{target}?>")
+                } else {
+                    format!("<?{target} {data}?>")
+                };
+                if declared.len() > 1 {
+                    output.push_str(&pi);
+                } else if after_document_element {
+                    output.push('\n');
+                    output.push_str(&pi);
+                } else {
+                    output.push_str(&pi);
+                    output.push('\n');
+                }
+            }
+            Ok(Event::Comment(_)) | Ok(Event::Decl(_)) | Ok(Event::DocType(_)) => {}
+            Ok(Event::Eof) => break,
+            Ok(Event::Empty(_)) => return Err(Error::xml("unexpected empty element")),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if !after_document_element {
+        return Err(Error::xml("missing document element"));
+    }
+
+    Ok(output)
+}
+
+fn canonical_start_tag(
+    e: &BytesStart,
+    declared: &mut Vec<Namespaces>,
+    rendered: &mut Vec<Namespaces>,
+    exclusive: bool,
+) -> Result<String, Error> {
+    let name = utf8(e.name().as_ref())?.to_owned();
+
+    let mut namespaces = Vec::new();
+    let mut attributes = Vec::new();
+
+    for attr in e.attributes() {
+        let attr = attr?;
+        let key = utf8(attr.key.as_ref())?.to_owned();
+        let value = normalize_attribute_value(utf8(&attr.value)?);
+        let value = unescape(&value)?.into_owned();
+        if key == "xmlns" {
+            namespaces.push((String::new(), value));
+        } else if let Some(prefix) = key.strip_prefix("xmlns:") {
+            namespaces.push((prefix.to_owned(), value));
+        } else {
+            attributes.push((key, value));
+        }
+    }
+
+    declared.push(namespaces);
+
+    // exclusive canonicalization only renders the namespaces visibly utilized by the element,
+    // while inclusive canonicalization renders every namespace in scope
+    let mut candidates = Vec::new();
+    if exclusive {
+        candidates.push(prefix_of(&name).unwrap_or(""));
+        attributes
+            .iter()
+            .filter_map(|(key, _)| prefix_of(key))
+            .for_each(|prefix| candidates.push(prefix));
+    } else {
+        declared
+            .iter()
+            .flatten()
+            .for_each(|(prefix, _)| candidates.push(prefix.as_str()));
+    }
+    candidates.sort();
+    candidates.dedup();
+
+    let mut to_render = Vec::new();
+    for prefix in candidates {
+        if prefix == "xml" {
+            continue;
+        }
+        let uri = match lookup(declared, prefix) {
+            Some(uri) => uri,
+            None if prefix.is_empty() => "",
+            None => return Err(Error::xml(format!("undeclared namespace prefix: {prefix}"))),
+        };
+        if lookup(rendered, prefix).unwrap_or("") != uri {
+            to_render.push((prefix.to_owned(), uri.to_owned()));
+        }
+    }
+
+    let mut sorted_attributes = Vec::new();
+    for (key, value) in attributes {
+        let (uri, local_name) = match key.split_once(':') {
+            Some(("xml", local_name)) => (XML_NAMESPACE, local_name),
+            Some((prefix, local_name)) => match lookup(declared, prefix) {
+                Some(uri) => (uri, local_name),
+                None => return Err(Error::xml(format!("undeclared namespace prefix: {prefix}"))),
+            },
+            None => ("", key.as_str()),
+        };
+        let sort_key = (uri.to_owned(), local_name.to_owned());
+        sorted_attributes.push((sort_key, key.clone(), value));
+    }
+    sorted_attributes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut start_tag = format!("<{name}");
+    for (prefix, uri) in &to_render {
+        if prefix.is_empty() {
+            start_tag.push_str(&format!(" xmlns=\"{}\"", escape_attribute(uri)));
+        } else {
+            start_tag.push_str(&format!(" xmlns:{prefix}=\"{}\"", escape_attribute(uri)));
+        }
+    }
+    for (_, key, value) in &sorted_attributes {
+        start_tag.push_str(&format!(" {key}=\"{}\"", escape_attribute(value)));
+    }
+    start_tag.push('>');
+
+    rendered.push(to_render);
+
+    Ok(start_tag)
+}
+
+fn lookup<'a>(stack: &'a [Namespaces], prefix: &str) -> Option<&'a str> {
+    stack.iter().rev().find_map(|namespaces| {
+        namespaces
+            .iter()
+            .find(|(p, _)| p == prefix)
+            .map(|(_, uri)| uri.as_str())
+    })
+}
+
+fn prefix_of(name: &str) -> Option<&str> {
+    name.split_once(':').map(|(prefix, _)| prefix)
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str, Error> {
+    Ok(std::str::from_utf8(bytes)?)
+}
+
+fn normalize_line_endings(xml: &str) -> String {
+    xml.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+fn normalize_attribute_value(value: &str) -> String {
+    value.replace(['\t', '\n'], " ")
+}
+
+fn escape_text(text: &str) -> String {
+    text.chars().fold(String::new(), |mut result, c| {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '\r' => result.push_str("&#xD;"),
+            _ => result.push(c),
+        }
+        result
+    })
+}
+
+fn escape_attribute(value: &str) -> String {
+    value.chars().fold(String::new(), |mut result, c| {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '"' => result.push_str("&quot;"),
+            '\t' => result.push_str("&#x9;"),
+            '\n' => result.push_str("&#xA;"),
+            '\r' => result.push_str("&#xD;"),
+            _ => result.push(c),
+        }
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::canonicalize;
+    use super::exclusive_xml_canonicalization;
+    use super::inclusive_xml_canonicalization;
+
+    // Examples from https://www.w3.org/TR/xml-c14n#Examples, rendered as exclusive
+    // canonicalization without comments. Internal DTD subsets are left out because
+    // default attributes are not supported.
+
+    #[test]
+    fn should_canonicalize_pis_comments_and_outside_of_document_element() {
+        let input = "<?xml version=\"1.0\"?>\n\n<?xml-stylesheet   href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n\n<!DOCTYPE doc SYSTEM \"doc.dtd\">\n\n<doc>Hello, world!<!-- Comment 1 --></doc>\n\n<?pi-without-data     ?>\n\n<!-- Comment 2 -->\n\n<!-- Comment 3 -->";
+        let expected = "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n<doc>Hello, world!</doc>\n<?pi-without-data?>";
+        assert_eq!(exclusive_xml_canonicalization(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn should_canonicalize_whitespace_in_document_content() {
+        let input = "<doc>\n   <clean>   </clean>\n   <dirty>   A   B   </dirty>\n   <mixed>\n      A\n      <clean>   </clean>\n      B\n      <dirty>   A   B   </dirty>\n      C\n   </mixed>\n</doc>";
+        assert_eq!(exclusive_xml_canonicalization(input).unwrap(), input);
+    }
+
+    #[test]
+    fn should_canonicalize_start_and_end_tags() {
+        let input = "<doc>\n   <e1   />\n   <e2   ></e2>\n   <e3   name = \"elem3\"   id=\"elem3\"   />\n   <e4   name=\"elem4\"   id=\"elem4\"   ></e4>\n   <e5 a:attr=\"out\" b:attr=\"sorted\" attr2=\"all\" attr=\"I'm\"\n      xmlns:b=\"http://www.ietf.org\"\n      xmlns:a=\"http://www.w3.org\"\n      xmlns=\"http://example.org\"/>\n   <e6 xmlns=\"\" xmlns:a=\"http://www.w3.org\">\n      <e7 xmlns=\"http://www.ietf.org\">\n         <e8 xmlns=\"\" xmlns:a=\"http://www.w3.org\">\n            <e9 xmlns=\"\" xmlns:a=\"http://www.ietf.org\"/>\n         </e8>\n      </e7>\n   </e6>\n</doc>";
+        let expected = "<doc>\n   <e1></e1>\n   <e2></e2>\n   <e3 id=\"elem3\" name=\"elem3\"></e3>\n   <e4 id=\"elem4\" name=\"elem4\"></e4>\n   <e5 xmlns=\"http://example.org\" xmlns:a=\"http://www.w3.org\" xmlns:b=\"http://www.ietf.org\" attr=\"I'm\" attr2=\"all\" b:attr=\"sorted\" a:attr=\"out\"></e5>\n   <e6>\n      <e7 xmlns=\"http://www.ietf.org\">\n         <e8 xmlns=\"\">\n            <e9></e9>\n         </e8>\n      </e7>\n   </e6>\n</doc>";
+        assert_eq!(exclusive_xml_canonicalization(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn should_canonicalize_character_modifications_and_character_references() {
+        let input = "<doc>\n   <text>First line&#x0d;&#10;Second line</text>\n   <value>&#x32;</value>\n   <compute><![CDATA[value>\"0\" && value<\"10\" ?\"valid\":\"error\"]]></compute>\n   <compute expr='value>\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"'>valid</compute>\n   <norm attr=' &apos;   &#x20;&#13;&#xa;&#9;   &apos; '/>\n</doc>";
+        let expected = "<doc>\n   <text>First line&#xD;\nSecond line</text>\n   <value>2</value>\n   <compute>value&gt;\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"</compute>\n   <compute expr=\"value>&quot;0&quot; &amp;&amp; value&lt;&quot;10&quot; ?&quot;valid&quot;:&quot;error&quot;\">valid</compute>\n   <norm attr=\" '    &#xD;&#xA;&#x9;   ' \"></norm>\n</doc>";
+        assert_eq!(exclusive_xml_canonicalization(input).unwrap(), expected);
+    }
+
+    // Example from https://www.w3.org/TR/xml-exc-c14n/#sec-Enveloping
+
+    #[test]
+    fn should_push_namespace_declarations_down_to_visibly_utilizing_elements() {
+        let input = "<n0:local xmlns:n0=\"foo:bar\" xmlns:n3=\"ftp://example.org\"><n1:elem2 xmlns:n1=\"http://example.net\" xml:lang=\"en\">\n      <n3:stuff xmlns:n3=\"ftp://example.org\"/>\n  </n1:elem2></n0:local>";
+        let expected = "<n0:local xmlns:n0=\"foo:bar\"><n1:elem2 xmlns:n1=\"http://example.net\" xml:lang=\"en\">\n      <n3:stuff xmlns:n3=\"ftp://example.org\"></n3:stuff>\n  </n1:elem2></n0:local>";
+        assert_eq!(exclusive_xml_canonicalization(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn should_canonicalize_document_subset_with_inherited_namespaces() {
+        let input = "<n1:elem2 xmlns:n1=\"http://example.net\" xml:lang=\"en\">\n      <n3:stuff xmlns:n3=\"ftp://example.org\"/>\n  </n1:elem2>";
+        let inherited = vec![
+            (String::from("n1"), String::from("http://example.com")),
+            (String::from("n2"), String::from("http://foo.example")),
+        ];
+        assert_eq!(
+            canonicalize(input, &inherited, true).unwrap(),
+            "<n1:elem2 xmlns:n1=\"http://example.net\" xml:lang=\"en\">\n      <n3:stuff xmlns:n3=\"ftp://example.org\"></n3:stuff>\n  </n1:elem2>"
+        );
+        assert_eq!(
+            canonicalize(input, &inherited, false).unwrap(),
+            "<n1:elem2 xmlns:n1=\"http://example.net\" xmlns:n2=\"http://foo.example\" xml:lang=\"en\">\n      <n3:stuff xmlns:n3=\"ftp://example.org\"></n3:stuff>\n  </n1:elem2>"
+        );
+    }
+
+    #[test]
+    fn should_keep_namespaces_in_scope_with_inclusive_canonicalization() {
+        let input = "<doc xmlns=\"http://example.org\" xmlns:a=\"http://www.w3.org\"><e1 xmlns:a=\"http://www.w3.org\"/><e2 xmlns=\"\"/></doc>";
+        let expected = "<doc xmlns=\"http://example.org\" xmlns:a=\"http://www.w3.org\"><e1></e1><e2 xmlns=\"\"></e2></doc>";
+        assert_eq!(inclusive_xml_canonicalization(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn should_canonicalize_pretty_printed_rps() {
+        let input = "<?xml version='1.0' encoding='UTF-8'?>\n<InfRps versao='1.00' Id='1234'\n        xmlns='http://www.abrasf.org.br/nfse.xsd'>\n  <Discriminacao>Servi&#231;o &quot;A&quot; &amp; B</Discriminacao>\n  <Complemento/>\n</InfRps>\n";
+        let expected = "<InfRps xmlns=\"http://www.abrasf.org.br/nfse.xsd\" Id=\"1234\" versao=\"1.00\">\n  <Discriminacao>Serviço \"A\" &amp; B</Discriminacao>\n  <Complemento></Complemento>\n</InfRps>";
+        assert_eq!(exclusive_xml_canonicalization(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn should_reject_undeclared_prefix() {
+        assert!(exclusive_xml_canonicalization("<a:doc></a:doc>").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use base64ct::{Base64, Encoding};
+use chrono::{DateTime, Utc};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::signature::Verifier;
+use rsa::RsaPublicKey;
+use sha2::Sha256;
+use x509_cert::der::asn1::Ia5StringRef;
+use x509_cert::der::asn1::ObjectIdentifier;
+use x509_cert::der::asn1::OctetStringRef;
+use x509_cert::der::asn1::PrintableStringRef;
+use x509_cert::der::asn1::Utf8StringRef;
+use x509_cert::der::oid::AssociatedOid;
+use x509_cert::der::Any;
+use x509_cert::der::Tag;
+use x509_cert::der::Tagged;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::pkix::SubjectAltName;
+
+use crate::error::Error;
+use crate::signer::HashAlgorithm;
+use crate::signer::Signer;
+
+// ICP-Brasil otherName carrying the CNPJ of a legal entity certificate
+const ICP_BRASIL_CNPJ: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.76.1.3.3");
+
+const EXPIRATION_WARNING_DAYS: i64 = 30;
+
+pub struct Certificate {
+    certificate: x509_cert::Certificate,
+}
+
+impl Certificate {
+    pub fn from_base64(certificate: &str) -> Result<Self, Error> {
+        let certificate = certificate
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>();
+        let der = Base64::decode_vec(&certificate)
+            .map_err(|_| Error::certificate("bad certificate encoding"))?;
+        let certificate = x509_cert::Certificate::from_der(&der)
+            .map_err(|e| Error::certificate("bad certificate").with_source(e))?;
+        Ok(Self { certificate })
+    }
+}
+
+impl Certificate {
+    pub fn public_key(&self) -> Result<RsaPublicKey, Error> {
+        let spki = self
+            .certificate
+            .tbs_certificate
+            .subject_public_key_info
+            .to_der()
+            .map_err(|e| Error::certificate("bad certificate public key").with_source(e))?;
+        RsaPublicKey::from_public_key_der(&spki)
+            .map_err(|e| Error::certificate("bad certificate public key").with_source(e))
+    }
+}
+
+impl Certificate {
+    pub fn not_before(&self) -> DateTime<Utc> {
+        let validity = &self.certificate.tbs_certificate.validity;
+        DateTime::<Utc>::from(validity.not_before.to_system_time())
+    }
+}
+
+impl Certificate {
+    pub fn not_after(&self) -> DateTime<Utc> {
+        let validity = &self.certificate.tbs_certificate.validity;
+        DateTime::<Utc>::from(validity.not_after.to_system_time())
+    }
+}
+
+impl Certificate {
+    pub fn cnpj(&self) -> Option<String> {
+        let from_subject_alt_name = self
+            .certificate
+            .tbs_certificate
+            .extensions
+            .iter()
+            .flatten()
+            .filter(|extension| extension.extn_id == SubjectAltName::OID)
+            .filter_map(|extension| SubjectAltName::from_der(extension.extn_value.as_bytes()).ok())
+            .flat_map(|subject_alt_name| subject_alt_name.0)
+            .find_map(|general_name| match general_name {
+                GeneralName::OtherName(other_name) if other_name.type_id == ICP_BRASIL_CNPJ => {
+                    other_name_text(&other_name.value)
+                        .filter(|cnpj| cnpj.len() == 14 && cnpj.bytes().all(|c| c.is_ascii_digit()))
+                }
+                _ => None,
+            });
+
+        // older certificates only carry the CNPJ in the common name, as "RAZAO SOCIAL:CNPJ"
+        from_subject_alt_name.or_else(|| {
+            self.certificate
+                .tbs_certificate
+                .subject
+                .to_string()
+                .split(',')
+                .find_map(|rdn| rdn.trim().strip_prefix("CN="))
+                .and_then(|common_name| common_name.rsplit_once(':'))
+                .map(|(_, cnpj)| only_digits(cnpj))
+                .filter(|cnpj| cnpj.len() == 14)
+        })
+    }
+}
+
+// ICP-Brasil encodes otherName values as OCTET STRING, though some issuers use a string type
+fn other_name_text(value: &Any) -> Option<String> {
+    let text = match value.tag() {
+        Tag::OctetString => {
+            std::str::from_utf8(value.decode_as::<OctetStringRef>().ok()?.as_bytes())
+                .ok()?
+                .to_owned()
+        }
+        Tag::PrintableString => value
+            .decode_as::<PrintableStringRef>()
+            .ok()?
+            .as_str()
+            .to_owned(),
+        Tag::Utf8String => value.decode_as::<Utf8StringRef>().ok()?.as_str().to_owned(),
+        Tag::Ia5String => value.decode_as::<Ia5StringRef>().ok()?.as_str().to_owned(),
+        _ => return None,
+    };
+
+    Some(text.trim().to_owned())
+}
+
+impl Certificate {
+    pub fn preflight(
+        &self,
+        cnpj: &str,
+        signer: &dyn Signer,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<String>, Error> {
+        let mut warnings = Vec::new();
+
+        if now < self.not_before() {
+            return Err(Error::certificate(format!(
+                "certificate is not valid before {}",
+                self.not_before().format("%Y-%m-%d %H:%M:%S")
+            )));
+        }
+
+        if now > self.not_after() {
+            return Err(Error::certificate(format!(
+                "certificate expired on {}",
+                self.not_after().format("%Y-%m-%d %H:%M:%S")
+            )));
+        }
+
+        let days_left = (self.not_after() - now).num_days();
+        if days_left < EXPIRATION_WARNING_DAYS {
+            warnings.push(format!(
+                "certificate expires on {} ({days_left} days left)",
+                self.not_after().format("%Y-%m-%d %H:%M:%S")
+            ));
+        }
+
+        match self.cnpj() {
+            Some(it) if it == only_digits(cnpj) => {}
+            Some(it) => {
+                return Err(Error::certificate(format!(
+                    "certificate belongs to cnpj {it}, but prestador cnpj is {cnpj}"
+                )))
+            }
+            None => return Err(Error::certificate("certificate has no cnpj")),
+        }
+
+        // works for keys that never leave their store: sign a probe and check it against the
+        // certificate public key
+        let probe = b"nfse-bh-rust preflight";
+        let signature = signer.sign(HashAlgorithm::Sha256, probe)?;
+        let signature = rsa::pkcs1v15::Signature::try_from(signature.as_slice())
+            .map_err(|_| Error::certificate("private key does not match certificate"))?;
+        rsa::pkcs1v15::VerifyingKey::<Sha256>::new(self.public_key()?)
+            .verify(probe, &signature)
+            .map_err(|_| Error::certificate("private key does not match certificate"))?;
+
+        Ok(warnings)
+    }
+}
+
+fn only_digits(value: &str) -> String {
+    value.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::utils;
+
+    const CERTIFICATE: &str = include_str!("../tests/fixtures/certificado.cer");
+    const PRIVATE_KEY: &str = include_str!("../tests/fixtures/certificado.key");
+    const OTHER_PRIVATE_KEY: &str = include_str!("../tests/fixtures/outro.key");
+
+    #[test]
+    fn should_inspect_and_check_certificate() {
+        let certificate =
+            super::Certificate::from_base64(&utils::trim_x509_certificate(CERTIFICATE)).unwrap();
+        let private_key =
+            <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_pem(PRIVATE_KEY)
+                .unwrap();
+        let other_private_key =
+            <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_pem(OTHER_PRIVATE_KEY)
+                .unwrap();
+
+        assert_eq!(certificate.cnpj(), Some(String::from("12345678000190")));
+        assert_eq!(
+            certificate.not_after(),
+            Utc.with_ymd_and_hms(2124, 1, 1, 0, 0, 0).unwrap()
+        );
+
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+        assert_eq!(
+            certificate
+                .preflight("12.345.678/0001-90", &private_key, now)
+                .unwrap(),
+            Vec::<String>::new()
+        );
+
+        let now = Utc.with_ymd_and_hms(2123, 12, 22, 0, 0, 0).unwrap();
+        assert_eq!(
+            certificate
+                .preflight("12345678000190", &private_key, now)
+                .unwrap(),
+            vec![String::from(
+                "certificate expires on 2124-01-01 00:00:00 (10 days left)"
+            )]
+        );
+
+        let now = Utc.with_ymd_and_hms(2124, 1, 2, 0, 0, 0).unwrap();
+        assert_eq!(
+            certificate
+                .preflight("12345678000190", &private_key, now)
+                .unwrap_err()
+                .to_string(),
+            "certificate error: certificate expired on 2124-01-01 00:00:00"
+        );
+
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+        assert_eq!(
+            certificate
+                .preflight("98765432000110", &private_key, now)
+                .unwrap_err()
+                .to_string(),
+            "certificate error: certificate belongs to cnpj 12345678000190, but prestador cnpj is 98765432000110"
+        );
+        assert_eq!(
+            certificate
+                .preflight("12345678000190", &other_private_key, now)
+                .unwrap_err()
+                .to_string(),
+            "certificate error: private key does not match certificate"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use quick_xml::events::BytesEnd;
+use quick_xml::events::BytesStart;
+use quick_xml::events::BytesText;
+use quick_xml::events::Event;
+
+use crate::error::Error;
+use crate::nfse::Nfse;
+use crate::resposta::Resposta;
+
+pub struct ConsultaNfse {
+    cnpj: String,
+    inscricao_municipal: String,
+    numero_nfse: Option<String>,
+    periodo_emissao: Option<(String, String)>,
+    cnpj_tomador: Option<String>,
+}
+
+impl ConsultaNfse {
+    fn new(cnpj: String, inscricao_municipal: String) -> Self {
+        Self {
+            cnpj,
+            inscricao_municipal,
+            numero_nfse: None,
+            periodo_emissao: None,
+            cnpj_tomador: None,
+        }
+    }
+}
+
+impl ConsultaNfse {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping) -> Result<Self, Error> {
+        let cnpj = match yaml.get("cnpj") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("cnpj", "invalid value")),
+            },
+            None => Err(Error::input("cnpj", "required")),
+        }?;
+
+        let inscricao_municipal = match yaml.get("inscricao_municipal") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("inscricao_municipal", "invalid value")),
+            },
+            None => Err(Error::input("inscricao_municipal", "required")),
+        }?;
+
+        Ok(Self::new(cnpj, inscricao_municipal))
+    }
+}
+
+impl ConsultaNfse {
+    pub fn set_periodo_emissao(
+        mut self,
+        data_inicial: chrono::NaiveDate,
+        data_final: chrono::NaiveDate,
+    ) -> Result<Self, Error> {
+        if data_final < data_inicial {
+            return Err(Error::input(
+                "periodo_emissao",
+                "data_final is before data_inicial",
+            ));
+        }
+        self.periodo_emissao = Some((
+            data_inicial.format("%Y-%m-%d").to_string(),
+            data_final.format("%Y-%m-%d").to_string(),
+        ));
+        Ok(self)
+    }
+}
+
+impl ConsultaNfse {
+    pub fn set_numero_nfse(mut self, numero_nfse: Option<String>) -> Self {
+        self.numero_nfse = numero_nfse;
+        self
+    }
+}
+
+impl ConsultaNfse {
+    pub fn set_cnpj_tomador(mut self, cnpj_tomador: Option<String>) -> Self {
+        self.cnpj_tomador = cnpj_tomador;
+        self
+    }
+}
+
+impl ConsultaNfse {
+    pub fn consultar_nfse_envio_xml_events(&self) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+
+        let mut elem = BytesStart::new("ConsultarNfseEnvio");
+        elem.push_attribute(("xmlns", "http://www.abrasf.org.br/nfse.xsd"));
+        events.push(Event::Start(elem));
+
+        let elem = BytesStart::new("Prestador");
+        events.push(Event::Start(elem));
+
+        let elem = BytesStart::new("Cnpj");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.cnpj.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("Cnpj");
+        events.push(Event::End(elem));
+
+        let elem = BytesStart::new("InscricaoMunicipal");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.inscricao_municipal.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("InscricaoMunicipal");
+        events.push(Event::End(elem));
+
+        let elem = BytesEnd::new("Prestador");
+        events.push(Event::End(elem));
+
+        if let Some(numero_nfse) = &self.numero_nfse {
+            let elem = BytesStart::new("NumeroNfse");
+            events.push(Event::Start(elem));
+
+            let elem = BytesText::new(numero_nfse.as_str());
+            events.push(Event::Text(elem));
+
+            let elem = BytesEnd::new("NumeroNfse");
+            events.push(Event::End(elem));
+        }
+
+        if let Some((data_inicial, data_final)) = &self.periodo_emissao {
+            let elem = BytesStart::new("PeriodoEmissao");
+            events.push(Event::Start(elem));
+
+            let elem = BytesStart::new("DataInicial");
+            events.push(Event::Start(elem));
+
+            let elem = BytesText::new(data_inicial.as_str());
+            events.push(Event::Text(elem));
+
+            let elem = BytesEnd::new("DataInicial");
+            events.push(Event::End(elem));
+
+            let elem = BytesStart::new("DataFinal");
+            events.push(Event::Start(elem));
+
+            let elem = BytesText::new(data_final.as_str());
+            events.push(Event::Text(elem));
+
+            let elem = BytesEnd::new("DataFinal");
+            events.push(Event::End(elem));
+
+            let elem = BytesEnd::new("PeriodoEmissao");
+            events.push(Event::End(elem));
+        }
+
+        if let Some(cnpj_tomador) = &self.cnpj_tomador {
+            let elem = BytesStart::new("Tomador");
+            events.push(Event::Start(elem));
+
+            let elem = BytesStart::new("CpfCnpj");
+            events.push(Event::Start(elem));
+
+            let elem = BytesStart::new("Cnpj");
+            events.push(Event::Start(elem));
+
+            let elem = BytesText::new(cnpj_tomador.as_str());
+            events.push(Event::Text(elem));
+
+            let elem = BytesEnd::new("Cnpj");
+            events.push(Event::End(elem));
+
+            let elem = BytesEnd::new("CpfCnpj");
+            events.push(Event::End(elem));
+
+            let elem = BytesEnd::new("Tomador");
+            events.push(Event::End(elem));
+        }
+
+        let elem = BytesEnd::new("ConsultarNfseEnvio");
+        events.push(Event::End(elem));
+
+        events
+    }
+}
+
+impl ConsultaNfse {
+    pub fn nfses_from_resposta(resposta: &Resposta) -> Result<Vec<Nfse>, Error> {
+        Ok(Nfse::from_resposta(resposta)?
+            .into_iter()
+            .map(|(nfse, _)| nfse)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resposta::Resposta;
+    use crate::utils;
+
+    use super::ConsultaNfse;
+
+    #[test]
+    fn should_consult_nfse_by_periodo() {
+        let yaml: serde_yaml::Mapping = serde_yaml::from_str(
+            "
+cnpj: cnpj_prestador
+inscricao_municipal: inscricao_municipal_prestador
+",
+        )
+        .unwrap();
+
+        let consulta = ConsultaNfse::from_yaml(&yaml)
+            .unwrap()
+            .set_periodo_emissao(
+                chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+                chrono::NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+            )
+            .unwrap()
+            .set_cnpj_tomador(Some(String::from("cnpj_tomador")));
+
+        assert_eq!(
+            utils::xml_events_to_xml_string(&consulta.consultar_nfse_envio_xml_events()),
+            String::from(
+                r##"<ConsultarNfseEnvio xmlns="http://www.abrasf.org.br/nfse.xsd"><Prestador><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal></Prestador><PeriodoEmissao><DataInicial>2024-03-01</DataInicial><DataFinal>2024-03-31</DataFinal></PeriodoEmissao><Tomador><CpfCnpj><Cnpj>cnpj_tomador</Cnpj></CpfCnpj></Tomador></ConsultarNfseEnvio>"##
+            )
+        );
+
+        assert!(ConsultaNfse::from_yaml(&yaml)
+            .unwrap()
+            .set_periodo_emissao(
+                chrono::NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+                chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            )
+            .is_err());
+
+        let comp_nfse = |numero: &str| {
+            format!(
+                r##"<CompNfse xmlns="http://www.abrasf.org.br/nfse.xsd"><Nfse xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><InfNfse Id="nfse"><Numero>{numero}</Numero><CodigoVerificacao>67890</CodigoVerificacao><DataEmissao>2024-03-05T01:02:03</DataEmissao><NaturezaOperacao>1</NaturezaOperacao><RegimeEspecialTributacao>6</RegimeEspecialTributacao><OptanteSimplesNacional>1</OptanteSimplesNacional><IncentivadorCultural>2</IncentivadorCultural><Competencia>2024-03-01T00:00:00</Competencia><OutrasInformacoes>Teste</OutrasInformacoes><Servico><Valores><ValorServicos>95.31</ValorServicos><IssRetido>2</IssRetido><BaseCalculo>95.31</BaseCalculo><ValorLiquidoNfse>95.31</ValorLiquidoNfse></Valores><ItemListaServico>1.04</ItemListaServico><CodigoTributacaoMunicipio>10400188</CodigoTributacaoMunicipio><Discriminacao>Consultoria</Discriminacao><CodigoMunicipio>3106200</CodigoMunicipio></Servico><PrestadorServico><IdentificacaoPrestador><Cnpj>12345678000190</Cnpj></IdentificacaoPrestador><RazaoSocial>NOME DA EMPRESA</RazaoSocial><Endereco><Endereco>RUA DO PRESTADOR</Endereco><Numero>12</Numero><Bairro>Bairro Um</Bairro><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf><Cep>34567890</Cep></Endereco></PrestadorServico><TomadorServico><IdentificacaoTomador><CpfCnpj><Cnpj>12345678000290</Cnpj></CpfCnpj></IdentificacaoTomador><RazaoSocial>NOME DO TOMADOR</RazaoSocial><Endereco><Endereco>RUA DO TOMADOR</Endereco><Numero>34</Numero><Bairro>Bairro Dois</Bairro><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf><Cep>34567891</Cep></Endereco></TomadorServico></InfNfse></Nfse></CompNfse>"##
+            )
+        };
+
+        let body = format!(
+            r##"<S:Envelope xmlns:S="http://schemas.xmlsoap.org/soap/envelope/"><S:Body><ns2:ConsultarNfseResponse xmlns:ns2="http://ws.bhiss.pbh.gov.br"><outputXML>{}</outputXML></ns2:ConsultarNfseResponse></S:Body></S:Envelope>"##,
+            format!(
+                r##"<?xml version='1.0' encoding='UTF-8'?><ConsultarNfseResposta xmlns="http://www.abrasf.org.br/nfse.xsd"><ListaNfse>{}{}</ListaNfse></ConsultarNfseResposta>"##,
+                comp_nfse("202400000000001"),
+                comp_nfse("202400000000002"),
+            )
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+        );
+
+        let nfses =
+            ConsultaNfse::nfses_from_resposta(&Resposta::from_soap(200, &body).unwrap()).unwrap();
+
+        assert_eq!(
+            nfses.iter().map(|nfse| nfse.numero()).collect::<Vec<_>>(),
+            vec!["202400000000001", "202400000000002"]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use quick_xml::events::BytesEnd;
+use quick_xml::events::BytesStart;
+use quick_xml::events::BytesText;
+use quick_xml::events::Event;
+
+use crate::error::Error;
+use crate::nfse::Nfse;
+use crate::resposta::Resposta;
+use crate::rps::IdentificacaoRps;
+
+pub struct ConsultaNfseRps {
+    identificacao_rps: IdentificacaoRps,
+    cnpj: String,
+    inscricao_municipal: String,
+}
+
+impl ConsultaNfseRps {
+    fn new(identificacao_rps: IdentificacaoRps, cnpj: String, inscricao_municipal: String) -> Self {
+        Self {
+            identificacao_rps,
+            cnpj,
+            inscricao_municipal,
+        }
+    }
+}
+
+impl ConsultaNfseRps {
+    pub fn from_yaml(
+        yaml: &serde_yaml::Mapping,
+        identificacao_rps: IdentificacaoRps,
+    ) -> Result<Self, Error> {
+        let cnpj = match yaml.get("cnpj") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("cnpj", "invalid value")),
+            },
+            None => Err(Error::input("cnpj", "required")),
+        }?;
+
+        let inscricao_municipal = match yaml.get("inscricao_municipal") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("inscricao_municipal", "invalid value")),
+            },
+            None => Err(Error::input("inscricao_municipal", "required")),
+        }?;
+
+        Ok(Self::new(identificacao_rps, cnpj, inscricao_municipal))
+    }
+}
+
+impl ConsultaNfseRps {
+    pub fn consultar_nfse_rps_envio_xml_events(&self) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+
+        let mut elem = BytesStart::new("ConsultarNfseRpsEnvio");
+        elem.push_attribute(("xmlns", "http://www.abrasf.org.br/nfse.xsd"));
+        events.push(Event::Start(elem));
+
+        self.identificacao_rps
+            .xml_events("IdentificacaoRps")
+            .into_iter()
+            .for_each(|e| events.push(e));
+
+        let elem = BytesStart::new("Prestador");
+        events.push(Event::Start(elem));
+
+        let elem = BytesStart::new("Cnpj");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.cnpj.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("Cnpj");
+        events.push(Event::End(elem));
+
+        let elem = BytesStart::new("InscricaoMunicipal");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.inscricao_municipal.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("InscricaoMunicipal");
+        events.push(Event::End(elem));
+
+        let elem = BytesEnd::new("Prestador");
+        events.push(Event::End(elem));
+
+        let elem = BytesEnd::new("ConsultarNfseRpsEnvio");
+        events.push(Event::End(elem));
+
+        events
+    }
+}
+
+impl ConsultaNfseRps {
+    // the note generated from the rps, along with its xml; an unknown rps comes back as E4
+    pub fn nfse_from_resposta(resposta: &Resposta) -> Result<(Nfse, String), Error> {
+        Nfse::from_resposta(resposta)?
+            .into_iter()
+            .next()
+            .ok_or(Error::xml("missing CompNfse in response"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resposta::Resposta;
+    use crate::rps::IdentificacaoRps;
+    use crate::utils;
+
+    use super::ConsultaNfseRps;
+
+    #[test]
+    fn should_consult_nfse_by_rps() {
+        let yaml: serde_yaml::Mapping = serde_yaml::from_str(
+            "
+cnpj: cnpj_prestador
+inscricao_municipal: inscricao_municipal_prestador
+",
+        )
+        .unwrap();
+
+        let consulta = ConsultaNfseRps::from_yaml(
+            &yaml,
+            IdentificacaoRps {
+                numero: String::from("1234"),
+                serie: String::from("1"),
+                tipo: String::from("1"),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            utils::xml_events_to_xml_string(&consulta.consultar_nfse_rps_envio_xml_events()),
+            String::from(
+                r##"<ConsultarNfseRpsEnvio xmlns="http://www.abrasf.org.br/nfse.xsd"><IdentificacaoRps><Numero>1234</Numero><Serie>1</Serie><Tipo>1</Tipo></IdentificacaoRps><Prestador><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal></Prestador></ConsultarNfseRpsEnvio>"##
+            )
+        );
+
+        let comp_nfse = r##"<CompNfse xmlns="http://www.abrasf.org.br/nfse.xsd"><Nfse xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><InfNfse Id="nfse"><Numero>202400000000123</Numero><CodigoVerificacao>67890</CodigoVerificacao><DataEmissao>2020-01-01T01:02:03</DataEmissao><NaturezaOperacao>3</NaturezaOperacao><RegimeEspecialTributacao>6</RegimeEspecialTributacao><OptanteSimplesNacional>1</OptanteSimplesNacional><IncentivadorCultural>2</IncentivadorCultural><Competencia>2020-01-01T00:00:00</Competencia><OutrasInformacoes>Teste</OutrasInformacoes><Servico><Valores><ValorServicos>95.31</ValorServicos><IssRetido>2</IssRetido><BaseCalculo>95.31</BaseCalculo><ValorLiquidoNfse>95.31</ValorLiquidoNfse></Valores><ItemListaServico>1.04</ItemListaServico><CodigoTributacaoMunicipio>10400188</CodigoTributacaoMunicipio><Discriminacao>Consultoria</Discriminacao><CodigoMunicipio>3106200</CodigoMunicipio></Servico><PrestadorServico><IdentificacaoPrestador><Cnpj>12345678000190</Cnpj><InscricaoMunicipal>12345670018</InscricaoMunicipal></IdentificacaoPrestador><RazaoSocial>NOME DA EMPRESA</RazaoSocial><Endereco><Endereco>RUA DO PRESTADOR</Endereco><Numero>12</Numero><Bairro>Bairro Um</Bairro><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf><Cep>34567890</Cep></Endereco></PrestadorServico><TomadorServico><IdentificacaoTomador><CpfCnpj><Cnpj>12345678000290</Cnpj></CpfCnpj></IdentificacaoTomador><RazaoSocial>NOME DO TOMADOR</RazaoSocial><Endereco><Endereco>RUA DO TOMADOR</Endereco><Numero>34</Numero><Bairro>Bairro Dois</Bairro><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf><Cep>34567891</Cep></Endereco></TomadorServico></InfNfse></Nfse></CompNfse>"##;
+
+        let body = format!(
+            r##"<S:Envelope xmlns:S="http://schemas.xmlsoap.org/soap/envelope/"><S:Body><ns2:ConsultarNfsePorRpsResponse xmlns:ns2="http://ws.bhiss.pbh.gov.br"><outputXML>{}</outputXML></ns2:ConsultarNfsePorRpsResponse></S:Body></S:Envelope>"##,
+            format!(
+                r##"<?xml version='1.0' encoding='UTF-8'?><ConsultarNfseRpsResposta xmlns="http://www.abrasf.org.br/nfse.xsd">{comp_nfse}</ConsultarNfseRpsResposta>"##
+            )
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+        );
+
+        let (nfse, xml) =
+            ConsultaNfseRps::nfse_from_resposta(&Resposta::from_soap(200, &body).unwrap()).unwrap();
+
+        assert_eq!(nfse.numero(), "202400000000123");
+        assert_eq!(
+            xml,
+            format!("<?xml version='1.0' encoding='UTF-8'?>{comp_nfse}")
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use crate::algorithms::CanonicalizationAlgorithm;
+use crate::algorithms::DigestAlgorithm;
+use crate::algorithms::SignatureAlgorithm;
+use crate::certificate::Certificate;
+use crate::curl::ClientCertificate;
+use crate::error::Error;
+use crate::pkcs12::Pkcs12;
+use crate::signature::XmlSignature;
+use crate::signer::Signer;
+use crate::utils;
+
+// asks the user for a secret, e.g. rpassword::prompt_password
+pub type Prompt<'a> = &'a dyn Fn(&str) -> std::io::Result<String>;
+
+// certificate and key configured in the input file, either a pfx or files extracted from it
+pub struct Credentials {
+    pkcs12: Option<Pkcs12>,
+    certificado_pem_file: Option<String>,
+}
+
+impl Credentials {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping, prompt: Prompt) -> Result<Self, Error> {
+        let pkcs12 = match yaml.get("certificado_pfx") {
+            Some(serde_yaml::Value::String(it)) => {
+                let senha = prompt("Digite a senha do certificado: ").map_err(|e| {
+                    Error::certificate("could not read certificate password").with_source(e)
+                })?;
+                Ok(Some(Pkcs12::from_file(it, &senha)?))
+            }
+            Some(_) => Err(Error::input("certificado_pfx", "invalid value")),
+            None => Ok(None),
+        }?;
+
+        let certificado_pem_file = match &pkcs12 {
+            Some(_) => Ok(None),
+            None => match yaml.get("certificado_pem") {
+                Some(serde_yaml::Value::String(it)) => Ok(Some(it.clone())),
+                _ => Err(Error::input("certificado_pem", "invalid value")),
+            },
+        }?;
+
+        Ok(Self {
+            pkcs12,
+            certificado_pem_file,
+        })
+    }
+}
+
+impl Credentials {
+    pub fn certificado_pem_file(&self) -> Option<String> {
+        self.certificado_pem_file.clone()
+    }
+}
+
+impl Credentials {
+    pub fn client_certificate(&self) -> Result<Option<ClientCertificate>, Error> {
+        match &self.pkcs12 {
+            Some(pkcs12) => Ok(Some(pkcs12.client_certificate()?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Credentials {
+    pub fn certificate(&self, yaml: &serde_yaml::Mapping) -> Result<String, Error> {
+        match &self.pkcs12 {
+            Some(pkcs12) => Ok(pkcs12.certificate()),
+            None => match yaml.get("certificado_cer") {
+                Some(serde_yaml::Value::String(it)) => {
+                    let cer_contents = std::fs::read_to_string(it).map_err(|e| {
+                        Error::certificate(format!("could not read file at {it}")).with_source(e)
+                    })?;
+                    Ok(utils::trim_x509_certificate(&cer_contents))
+                }
+                _ => Err(Error::input("certificado_cer", "invalid value")),
+            },
+        }
+    }
+}
+
+impl Credentials {
+    pub fn signer(
+        &self,
+        yaml: &serde_yaml::Mapping,
+        prompt: Prompt,
+    ) -> Result<Arc<dyn Signer>, Error> {
+        match (yaml.get("assinador_socket"), yaml.get("assinador_pkcs11")) {
+            #[cfg(unix)]
+            (Some(serde_yaml::Value::String(it)), None) => {
+                Ok(Arc::new(crate::signer::UnixSocketSigner::new(it.clone())))
+            }
+            (None, Some(serde_yaml::Value::Mapping(it))) => {
+                pkcs11_signer(it, prompt).map_err(|e| e.within("assinador_pkcs11"))
+            }
+            (Some(_), None) => Err(Error::input("assinador_socket", "invalid value")),
+            (None, Some(_)) => Err(Error::input("assinador_pkcs11", "invalid value")),
+            (Some(_), Some(_)) => Err(Error::input(
+                "assinador_socket",
+                "cannot be used together with assinador_pkcs11",
+            )),
+            (None, None) => match (&self.pkcs12, yaml.get("certificado_key")) {
+                (Some(pkcs12), _) => Ok(Arc::new(pkcs12.private_key())),
+                (None, Some(serde_yaml::Value::String(it))) => private_key_file(it),
+                (None, _) => Err(Error::input("certificado_key", "invalid value")),
+            },
+        }
+    }
+}
+
+impl Credentials {
+    // signature template for the documents of the given prestador, after checking the certificate
+    // against it; also returns the preflight warnings
+    pub fn xml_signature(
+        &self,
+        yaml: &serde_yaml::Mapping,
+        cnpj: &str,
+        prompt: Prompt,
+    ) -> Result<(XmlSignature, Vec<String>), Error> {
+        let signer = self.signer(yaml, prompt)?;
+        let certificate = self.certificate(yaml)?;
+
+        let warnings = Certificate::from_base64(&certificate)?.preflight(
+            cnpj,
+            signer.as_ref(),
+            chrono::Utc::now(),
+        )?;
+
+        let signature = XmlSignature::new(
+            CanonicalizationAlgorithm::ExclusiveXMLCanonicalization,
+            SignatureAlgorithm::from_yaml(yaml, signer)?,
+            DigestAlgorithm::from_yaml(yaml)?,
+            certificate,
+        );
+
+        Ok((signature, warnings))
+    }
+}
+
+fn private_key_file(path: &str) -> Result<Arc<dyn Signer>, Error> {
+    let key_contents = std::fs::read_to_string(path)
+        .map_err(|e| Error::certificate(format!("could not read file at {path}")).with_source(e))?;
+
+    let private_key =
+        <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_pem(&key_contents)
+            .map_err(|e| {
+                Error::certificate(format!("file at {path} is not a valid private key"))
+                    .with_source(e)
+            })?;
+
+    Ok(Arc::new(private_key))
+}
+
+#[cfg(feature = "pkcs11")]
+fn pkcs11_signer(yaml: &serde_yaml::Mapping, prompt: Prompt) -> Result<Arc<dyn Signer>, Error> {
+    let get = |key: &str| match yaml.get(key) {
+        Some(serde_yaml::Value::String(it)) => Ok(it.clone()),
+        _ => Err(Error::input(key, "required")),
+    };
+
+    let pin = prompt("Digite o PIN do token: ")
+        .map_err(|e| Error::signing("could not read token pin").with_source(e))?;
+
+    Ok(Arc::new(crate::signer::Pkcs11Signer::new(
+        &get("modulo")?,
+        &get("token")?,
+        &get("chave")?,
+        pin,
+    )?))
+}
+
+#[cfg(not(feature = "pkcs11"))]
+fn pkcs11_signer(_: &serde_yaml::Mapping, _: Prompt) -> Result<Arc<dyn Signer>, Error> {
+    Err(Error::signing(
+        "assinador_pkcs11 requires building with the pkcs11 feature",
+    ))
+}
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 
+use crate::error::Error;
+
 struct Collector(Vec<u8>);
 
 impl curl::easy::Handler for Collector {
@@ -14,11 +16,24 @@ pub enum RequestMethod {
     POST(String),
 }
 
+#[derive(Clone)]
+pub struct ClientCertificate {
+    pub certificate_pem: String,
+    pub private_key_pem: String,
+}
+
 pub struct Request {
     method: RequestMethod,
     url: String,
     headers: HashMap<String, String>,
     certificate_path: Option<String>,
+    client_certificate: Option<ClientCertificate>,
+}
+
+impl Default for Request {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Request {
@@ -28,6 +43,7 @@ impl Request {
             url: String::new(),
             headers: HashMap::new(),
             certificate_path: None,
+            client_certificate: None,
         }
     }
 }
@@ -68,7 +84,14 @@ impl Request {
 }
 
 impl Request {
-    pub fn run(self) -> Result<(u32, Vec<u8>), curl::Error> {
+    pub fn set_client_certificate(mut self, client_certificate: Option<ClientCertificate>) -> Self {
+        self.client_certificate = client_certificate;
+        self
+    }
+}
+
+impl Request {
+    pub fn run(self) -> Result<(u32, Vec<u8>), Error> {
         let mut handle = curl::easy::Easy2::new(Collector(Vec::new()));
         let mut list = curl::easy::List::new();
 
@@ -80,6 +103,13 @@ impl Request {
             handle.ssl_cert(cert_path)?;
         }
 
+        if let Some(client_certificate) = &self.client_certificate {
+            handle.ssl_cert_blob(client_certificate.certificate_pem.as_bytes())?;
+            handle.ssl_cert_type("PEM")?;
+            handle.ssl_key_blob(client_certificate.private_key_pem.as_bytes())?;
+            handle.ssl_key_type("PEM")?;
+        }
+
         for (k, v) in &self.headers {
             list.append(&format!("{}: {}", k, v))?;
         }
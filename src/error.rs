@@ -0,0 +1,260 @@
+use std::fmt;
+
+use crate::resposta::MensagemRetorno;
+
+pub type Source = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum Error {
+    // missing or invalid input, field is the path inside the input document (e.g.
+    // notas_fiscais.0.valor_servicos)
+    Input {
+        field: String,
+        message: String,
+    },
+    Xml {
+        message: String,
+        source: Option<Source>,
+    },
+    Certificate {
+        message: String,
+        source: Option<Source>,
+    },
+    Signing {
+        message: String,
+        source: Option<Source>,
+    },
+    Transport {
+        message: String,
+        source: Option<Source>,
+    },
+    // local files kept between runs, such as the rps numbering
+    State {
+        message: String,
+        source: Option<Source>,
+    },
+    // unexpected http status or body
+    Webservice {
+        status_code: u32,
+        body: String,
+    },
+    Fault {
+        code: String,
+        message: String,
+    },
+    // ListaMensagemRetorno of an ABRASF response
+    Rejected {
+        mensagens: Vec<MensagemRetorno>,
+    },
+}
+
+impl Error {
+    pub fn input(field: &str, message: &str) -> Self {
+        Error::Input {
+            field: field.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+
+    pub fn xml(message: impl Into<String>) -> Self {
+        Error::Xml {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn certificate(message: impl Into<String>) -> Self {
+        Error::Certificate {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn signing(message: impl Into<String>) -> Self {
+        Error::Signing {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn transport(message: impl Into<String>) -> Self {
+        Error::Transport {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn state(message: impl Into<String>) -> Self {
+        Error::State {
+            message: message.into(),
+            source: None,
+        }
+    }
+}
+
+impl Error {
+    pub fn with_source(mut self, error: impl Into<Source>) -> Self {
+        match &mut self {
+            Error::Xml { source, .. }
+            | Error::Certificate { source, .. }
+            | Error::Signing { source, .. }
+            | Error::Transport { source, .. }
+            | Error::State { source, .. } => *source = Some(error.into()),
+            Error::Input { .. }
+            | Error::Webservice { .. }
+            | Error::Fault { .. }
+            | Error::Rejected { .. } => {}
+        }
+        self
+    }
+}
+
+impl Error {
+    // prefixes the field path of input errors, used when the input is nested in a larger document
+    pub fn within(self, parent: &str) -> Self {
+        match self {
+            Error::Input { field, message } if field.is_empty() => Error::Input {
+                field: parent.to_owned(),
+                message,
+            },
+            Error::Input { field, message } => Error::Input {
+                field: format!("{parent}.{field}"),
+                message,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kind, message, source) = match self {
+            Error::Input { field, message } => {
+                return write!(f, "bad input: {field}: {message}");
+            }
+            Error::Webservice { status_code, body } => {
+                return write!(f, "error in request (status {status_code}), {body}");
+            }
+            Error::Fault { code, message } => {
+                return write!(f, "soap fault ({code}): {message}");
+            }
+            Error::Rejected { mensagens } => {
+                write!(f, "rejected by webservice")?;
+                for mensagem in mensagens {
+                    writeln!(f)?;
+                    if let Some(rps) = &mensagem.identificacao_rps {
+                        write!(f, "rps {}/{}/{}: ", rps.numero, rps.serie, rps.tipo)?;
+                    }
+                    write!(f, "{}: {}", mensagem.codigo, mensagem.mensagem)?;
+                    if let Some(correcao) = &mensagem.correcao {
+                        write!(f, " ({correcao})")?;
+                    }
+                    if let Some(explicacao) = mensagem.explicacao() {
+                        write!(f, " {explicacao}")?;
+                    }
+                }
+                return Ok(());
+            }
+            Error::Xml { message, source } => ("error in xml", message, source),
+            Error::Certificate { message, source } => ("certificate error", message, source),
+            Error::Signing { message, source } => ("signing error", message, source),
+            Error::Transport { message, source } => ("transport error", message, source),
+            Error::State { message, source } => ("state error", message, source),
+        };
+
+        match source {
+            Some(source) => write!(f, "{kind}: {message}: {source}"),
+            None => write!(f, "{kind}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Xml { source, .. }
+            | Error::Certificate { source, .. }
+            | Error::Signing { source, .. }
+            | Error::Transport { source, .. }
+            | Error::State { source, .. } => source
+                .as_deref()
+                .map(|e| e as &(dyn std::error::Error + 'static)),
+            Error::Input { .. }
+            | Error::Webservice { .. }
+            | Error::Fault { .. }
+            | Error::Rejected { .. } => None,
+        }
+    }
+}
+
+impl From<quick_xml::Error> for Error {
+    fn from(e: quick_xml::Error) -> Self {
+        Error::xml("malformed document").with_source(e)
+    }
+}
+
+impl From<quick_xml::events::attributes::AttrError> for Error {
+    fn from(e: quick_xml::events::attributes::AttrError) -> Self {
+        Error::xml("malformed attribute").with_source(e)
+    }
+}
+
+impl From<quick_xml::escape::EscapeError> for Error {
+    fn from(e: quick_xml::escape::EscapeError) -> Self {
+        Error::xml("malformed escape").with_source(e)
+    }
+}
+
+impl From<std::str::Utf8Error> for Error {
+    fn from(e: std::str::Utf8Error) -> Self {
+        Error::xml("invalid utf-8").with_source(e)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for Error {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        Error::xml("invalid utf-8").with_source(e)
+    }
+}
+
+impl From<curl::Error> for Error {
+    fn from(e: curl::Error) -> Self {
+        Error::transport("request failed").with_source(e)
+    }
+}
+
+impl From<Error> for String {
+    fn from(e: Error) -> Self {
+        e.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+
+    #[test]
+    fn should_describe_errors() {
+        let error = Error::input("valor_servicos", "required").within("notas_fiscais.2");
+        assert!(matches!(
+            &error,
+            Error::Input { field, .. } if field == "notas_fiscais.2.valor_servicos"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "bad input: notas_fiscais.2.valor_servicos: required"
+        );
+
+        let error = crate::c14n::exclusive_xml_canonicalization("<a><b></a>").unwrap_err();
+        assert!(matches!(
+            &error,
+            Error::Xml {
+                source: Some(_),
+                ..
+            }
+        ));
+        assert!(error
+            .to_string()
+            .starts_with("error in xml: malformed document: "));
+    }
+}
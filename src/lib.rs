@@ -1,8 +1,20 @@
 pub mod algorithms;
+pub mod c14n;
+pub mod certificate;
+pub mod consulta_nfse;
+pub mod consulta_nfse_rps;
+pub mod credentials;
 pub mod curl;
+pub mod error;
 pub mod lote_rps;
 pub mod nfse;
+pub mod numeracao;
+pub mod pedido_cancelamento;
+pub mod pkcs12;
 pub mod protocolo;
+pub mod resposta;
 pub mod rps;
 pub mod signature;
+pub mod signer;
 pub mod utils;
+pub mod valor;
@@ -3,14 +3,30 @@ use quick_xml::events::BytesStart;
 use quick_xml::events::BytesText;
 use quick_xml::events::Event;
 
+use crate::error::Error;
+use crate::nfse::Nfse;
+use crate::numeracao::NumeracaoRps;
+use crate::resposta::Resposta;
+use crate::rps::validar_numero;
+use crate::rps::ConfiguracaoRps;
+use crate::rps::Numerador;
 use crate::rps::Rps;
+use crate::signature::SignatureVerification;
 use crate::signature::XmlSignature;
 use crate::utils;
 
+const LIMITE_RPS: usize = 50;
+const LIMITE_BYTES: usize = 500_000;
+// room left in each lote for its own signature, added only when it is signed
+const RESERVA_ASSINATURA: usize = 8_192;
+
 pub struct LoteRps {
     rpses: Vec<Rps>,
     cnpj: String,
     inscricao_municipal: String,
+    numero_lote: Option<String>,
+    limite_rps: usize,
+    limite_bytes: usize,
     signature: Option<XmlSignature>,
 }
 
@@ -20,101 +36,288 @@ impl LoteRps {
             rpses,
             cnpj,
             inscricao_municipal,
+            numero_lote: None,
+            limite_rps: LIMITE_RPS,
+            limite_bytes: LIMITE_BYTES,
             signature: None,
         }
     }
 }
 
 impl LoteRps {
-    pub fn from_yaml(yaml: &serde_yaml::Mapping) -> Result<Self, String> {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping, numerador: Numerador) -> Result<Self, Error> {
         let cnpj = match yaml.get("cnpj") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: cnpj"),
+                _ => Err(Error::input("cnpj", "invalid value")),
             },
-            None => Err("bad yaml input: cnpj"),
+            None => Err(Error::input("cnpj", "required")),
         }?;
 
         let inscricao_municipal = match yaml.get("inscricao_municipal") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: inscricao_municipal"),
+                _ => Err(Error::input("inscricao_municipal", "invalid value")),
             },
-            None => Err("bad yaml input: inscricao_municipal"),
+            None => Err(Error::input("inscricao_municipal", "required")),
         }?;
 
         let codigo_municipio = match yaml.get("codigo_municipio") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: codigo_municipio"),
+                _ => Err(Error::input("codigo_municipio", "invalid value")),
             },
-            None => Err("bad yaml input: codigo_municipio"),
+            None => Err(Error::input("codigo_municipio", "required")),
         }?;
 
-        let rpses = match yaml.get("notas_fiscais") {
-            Some(it) => match it {
-                serde_yaml::Value::Sequence(it) => Ok(it),
-                _ => Err("bad yaml input: notas_fiscais"),
+        let numero_lote = match yaml.get("numero_lote") {
+            Some(serde_yaml::Value::String(it)) => Ok(Some(it.clone())),
+            Some(serde_yaml::Value::Number(it)) if it.is_u64() => Ok(Some(format!("{}", it))),
+            Some(_) => Err(Error::input("numero_lote", "invalid value")),
+            None => Ok(None),
+        }?;
+        if let Some(numero_lote) = &numero_lote {
+            validar_numero("numero_lote", numero_lote)?;
+        }
+
+        let limite = |key: &str, padrao: usize| match yaml.get(key) {
+            Some(serde_yaml::Value::Number(it)) => match it.as_u64() {
+                Some(it) if it > 0 => Ok(it as usize),
+                _ => Err(Error::input(key, "invalid value")),
             },
-            None => Err("bad yaml input: notas_fiscais"),
+            Some(_) => Err(Error::input(key, "invalid value")),
+            None => Ok(padrao),
+        };
+
+        let limite_rps = limite("max_rps_por_lote", LIMITE_RPS)?;
+        let limite_bytes = limite("max_bytes_por_lote", LIMITE_BYTES)?;
+
+        let padrao = ConfiguracaoRps::from_yaml(yaml, &ConfiguracaoRps::default())?;
+
+        let rpses = match yaml.get("notas_fiscais") {
+            Some(serde_yaml::Value::Sequence(it)) => Ok(it),
+            _ => Err(Error::input("notas_fiscais", "invalid value")),
         }?;
 
         let rpses = rpses
             .iter()
             .enumerate()
             .map(|(i, rps)| {
-                match Rps::from_yaml(
+                Rps::from_yaml(
                     rps.clone(),
                     cnpj.clone(),
                     inscricao_municipal.clone(),
                     codigo_municipio.clone(),
-                ) {
-                    Ok(rps) => Ok(rps),
-                    Err(e) => Err(format!("error in notas_fiscais.{i}: {e}")),
-                }
+                    &padrao,
+                    numerador,
+                )
+                .map_err(|e| e.within(&format!("notas_fiscais.{i}")))
             })
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(Self::new(rpses, cnpj, inscricao_municipal))
+        // nome_arquivo names the output files and the numbers allocated to each note
+        for (i, rps) in rpses.iter().enumerate() {
+            if rpses[..i]
+                .iter()
+                .any(|other| other.nome_arquivo == rps.nome_arquivo)
+            {
+                return Err(Error::input(
+                    &format!("notas_fiscais.{i}.nome_arquivo"),
+                    "duplicated",
+                ));
+            }
+        }
+
+        Ok(Self::new(rpses, cnpj, inscricao_municipal)
+            .set_numero_lote(numero_lote)
+            .set_limites(limite_rps, limite_bytes))
+    }
+}
+
+impl LoteRps {
+    pub fn set_limites(mut self, limite_rps: usize, limite_bytes: usize) -> Self {
+        self.limite_rps = limite_rps;
+        self.limite_bytes = limite_bytes;
+        self
+    }
+}
+
+impl LoteRps {
+    // splits the notes, in order, into lotes within limite_rps and limite_bytes; the rps must be
+    // signed before, so their signatures are counted. a numero_lote from the input is used by the
+    // first lote and incremented for the next ones
+    pub fn dividir(self) -> Result<Vec<Self>, Error> {
+        let Self {
+            rpses,
+            cnpj,
+            inscricao_municipal,
+            numero_lote,
+            limite_rps,
+            limite_bytes,
+            ..
+        } = self;
+
+        let vazio = Self::new(Vec::new(), cnpj.clone(), inscricao_municipal.clone())
+            .set_numero_lote(Some(u64::MAX.to_string()));
+        let tamanho_base = utils::xml_events_to_xml_string(&vazio.enviar_lote_rps_envio_events())
+            .len()
+            + RESERVA_ASSINATURA;
+
+        let mut lotes: Vec<(Vec<Rps>, usize)> = Vec::new();
+
+        for (i, rps) in rpses.into_iter().enumerate() {
+            let tamanho = utils::xml_events_to_xml_string(&rps.rps_xml_events(false)).len();
+
+            if tamanho_base + tamanho > limite_bytes {
+                return Err(Error::input(
+                    &format!("notas_fiscais.{i}"),
+                    "larger than max_bytes_por_lote",
+                ));
+            }
+
+            match lotes.last_mut() {
+                Some((atual, tamanho_atual))
+                    if atual.len() < limite_rps && *tamanho_atual + tamanho <= limite_bytes =>
+                {
+                    atual.push(rps);
+                    *tamanho_atual += tamanho;
+                }
+                _ => lotes.push((vec![rps], tamanho_base + tamanho)),
+            }
+        }
+
+        // validated as digits when read
+        let numero_inicial = match &numero_lote {
+            Some(numero) if lotes.len() > 1 => numero.parse::<u64>().ok(),
+            _ => None,
+        };
+
+        lotes
+            .into_iter()
+            .enumerate()
+            .map(|(i, (rpses, _))| {
+                let numero_lote = match numero_inicial {
+                    Some(numero) => {
+                        let numero = numero
+                            .checked_add(i as u64)
+                            .map(|it| it.to_string())
+                            .ok_or(Error::input("numero_lote", "too large to split"))?;
+                        validar_numero("numero_lote", &numero)
+                            .map_err(|_| Error::input("numero_lote", "too large to split"))?;
+                        Some(numero)
+                    }
+                    None => numero_lote.clone(),
+                };
+
+                Ok(Self::new(rpses, cnpj.clone(), inscricao_municipal.clone())
+                    .set_numero_lote(numero_lote)
+                    .set_limites(limite_rps, limite_bytes))
+            })
+            .collect()
+    }
+}
+
+impl LoteRps {
+    pub fn set_numero_lote(mut self, numero_lote: Option<String>) -> Self {
+        self.numero_lote = numero_lote;
+        self
     }
 }
 
 impl LoteRps {
-    pub fn get_rpses(&mut self) -> std::slice::IterMut<Rps> {
+    pub fn get_numero_lote(&self) -> Option<&str> {
+        self.numero_lote.as_deref()
+    }
+}
+
+impl LoteRps {
+    // the Id referenced by the signature, unique among the lotes of the prestador
+    fn id(&self) -> String {
+        format!("lote{}", self.numero_lote.as_deref().unwrap_or_default())
+    }
+}
+
+impl LoteRps {
+    pub fn get_rpses(&mut self) -> std::slice::IterMut<'_, Rps> {
         self.rpses.iter_mut()
     }
 }
 
 impl LoteRps {
-    pub fn sign(&mut self, mut signature: XmlSignature) {
+    // nome_arquivo of the rps that generated each nfse, None when none of the lote did. notes
+    // carry their IdentificacaoRps; older ones without it fall back to tomador, discriminacao and
+    // valor, taking each rps once so identical services never land on the same file
+    pub fn nomes_arquivos_das_nfses(&self, nfses: &[Nfse]) -> Vec<Option<&str>> {
+        let mut usados = vec![false; self.rpses.len()];
+
+        let mut associar = |nfse: &Nfse, legado: bool| {
+            let i = self.rpses.iter().enumerate().position(|(i, rps)| {
+                !usados[i]
+                    && match nfse.identificacao_rps() {
+                        Some(identificacao) => !legado && *identificacao == rps.identificacao(),
+                        None => legado && nfse.uniquely_identify() == rps.uniquely_identify(),
+                    }
+            })?;
+            usados[i] = true;
+            Some(self.rpses[i].nome_arquivo.as_str())
+        };
+
+        // identified notes first, so the fallback never takes an rps that has its own note
+        let identificadas = nfses
+            .iter()
+            .map(|nfse| associar(nfse, false))
+            .collect::<Vec<_>>();
+
+        nfses
+            .iter()
+            .zip(identificadas)
+            .map(|(nfse, nome_arquivo)| nome_arquivo.or_else(|| associar(nfse, true)))
+            .collect()
+    }
+}
+
+impl LoteRps {
+    pub fn get_cnpj(&self) -> &str {
+        self.cnpj.as_str()
+    }
+}
+
+impl LoteRps {
+    pub fn sign(&mut self, mut signature: XmlSignature) -> Result<(), Error> {
+        if self.numero_lote.is_none() {
+            return Err(Error::input("numero_lote", "required"));
+        }
+
         signature.load(
-            String::from("#lote"),
+            format!("#{}", self.id()),
             utils::xml_events_to_xml_string(&self.lote_rps_xml_events()),
         );
 
-        signature.sign();
+        signature.sign()?;
 
         self.signature = Some(signature);
+
+        Ok(())
     }
 }
 
 impl LoteRps {
-    fn lote_rps_xml_events(&self) -> Vec<Event> {
+    fn lote_rps_xml_events(&self) -> Vec<Event<'_>> {
         let mut events = Vec::new();
 
         let mut elem = BytesStart::new("LoteRps");
         elem.push_attribute(("xmlns", "http://www.abrasf.org.br/nfse.xsd"));
-        elem.push_attribute(("Id", "lote"));
+        elem.push_attribute(("Id", self.id().as_str()));
         elem.push_attribute(("versao", "1.00"));
-        events.push(Event::Start(elem));
+        events.push(Event::Start(elem).into_owned());
 
         let elem = BytesStart::new("NumeroLote");
         events.push(Event::Start(elem));
 
-        let elem = BytesText::new("1");
+        let elem = BytesText::new(self.numero_lote.as_deref().unwrap_or_default());
         events.push(Event::Text(elem));
 
         let elem = BytesEnd::new("NumeroLote");
@@ -168,10 +371,23 @@ impl LoteRps {
 }
 
 impl LoteRps {
-    pub fn enviar_lote_rps_envio_events(&self) -> Vec<Event> {
+    pub fn enviar_lote_rps_envio_events(&self) -> Vec<Event<'_>> {
+        self.envio_events("EnviarLoteRpsEnvio")
+    }
+}
+
+impl LoteRps {
+    // same lote, processed synchronously by GerarNfse
+    pub fn gerar_nfse_envio_events(&self) -> Vec<Event<'_>> {
+        self.envio_events("GerarNfseEnvio")
+    }
+}
+
+impl LoteRps {
+    fn envio_events(&self, name: &'static str) -> Vec<Event<'_>> {
         let mut events = Vec::new();
 
-        let mut elem = BytesStart::new("EnviarLoteRpsEnvio");
+        let mut elem = BytesStart::new(name);
         elem.push_attribute(("xmlns", "http://www.abrasf.org.br/nfse.xsd"));
         elem.push_attribute(("versao", "1.00"));
         events.push(Event::Start(elem));
@@ -187,18 +403,179 @@ impl LoteRps {
                 .for_each(|e| events.push(e.to_owned()));
         };
 
-        let elem = BytesEnd::new("EnviarLoteRpsEnvio");
+        let elem = BytesEnd::new(name);
         events.push(Event::End(elem));
 
         events
     }
 }
 
+// sends a request to the webservice given its SOAPAction, returning the status code and the body
+pub type Envio<'a> = &'a dyn Fn(&str, String) -> Result<(u32, Vec<u8>), Error>;
+
+// what the webservice answered to an accepted lote
+#[derive(Debug)]
+pub enum RetornoLote {
+    // RecepcionarLoteRps, the notes are fetched later with the protocolo
+    Protocolo(String),
+    // GerarNfse, each note with its xml and the nome_arquivo of its rps, if one matches
+    Nfses(Vec<(Nfse, String, Option<String>)>),
+}
+
+#[derive(Debug)]
+pub struct ResultadoLote {
+    pub numero_lote: String,
+    pub nomes_arquivos: Vec<String>,
+    pub resultado: Result<RetornoLote, Error>,
+}
+
+impl LoteRps {
+    // numbers and signs every lote given by dividir, then sends them one by one; a failed lote does
+    // not stop the next ones and the numbers of its notes stay reserved for the next run
+    pub fn enviar_lotes(
+        lotes: Vec<Self>,
+        signature: &XmlSignature,
+        numeracao: &NumeracaoRps,
+        sincrono: bool,
+        envio: Envio,
+    ) -> Result<Vec<ResultadoLote>, Error> {
+        let mut preparados = Vec::new();
+        for lote_rps in lotes {
+            preparados.push(lote_rps.preparar(signature, numeracao, sincrono)?);
+        }
+
+        Ok(preparados
+            .into_iter()
+            .map(|(lote_rps, request_data)| {
+                lote_rps.enviar(request_data, numeracao, sincrono, envio)
+            })
+            .collect())
+    }
+}
+
+impl LoteRps {
+    // numbers given in the input are recorded, so they are not allocated again
+    fn preparar(
+        mut self,
+        signature: &XmlSignature,
+        numeracao: &NumeracaoRps,
+        sincrono: bool,
+    ) -> Result<(Self, String), Error> {
+        for rps in &self.rpses {
+            let identificacao = rps.identificacao();
+            numeracao.registrar_rps(&self.cnpj, &identificacao.serie, &identificacao.numero)?;
+        }
+
+        match &self.numero_lote {
+            Some(numero_lote) => numeracao.registrar_lote(&self.cnpj, numero_lote)?,
+            None => self.numero_lote = Some(numeracao.proximo_lote(&self.cnpj)?),
+        }
+
+        self.sign(signature.clone())?;
+
+        let envio = utils::xml_events_to_xml_string(&if sincrono {
+            self.gerar_nfse_envio_events()
+        } else {
+            self.enviar_lote_rps_envio_events()
+        });
+
+        if SignatureVerification::from_xml_string(&envio)?
+            .iter()
+            .any(|verification| !verification.is_valid())
+        {
+            return Err(Error::signing("generated signatures are not valid"));
+        }
+
+        let request_data = if sincrono {
+            utils::request_wrapper("GerarNfse", &envio)
+        } else {
+            utils::recepcionar_lote_rps_request_wrapper(&envio)
+        };
+
+        Ok((self, request_data))
+    }
+}
+
+impl LoteRps {
+    fn enviar(
+        &self,
+        request_data: String,
+        numeracao: &NumeracaoRps,
+        sincrono: bool,
+        envio: Envio,
+    ) -> ResultadoLote {
+        let resultado = (|| -> Result<RetornoLote, Error> {
+            let (status_code, data) = envio(
+                if sincrono {
+                    "http://ws.bhiss.pbh.gov.br/GerarNfse"
+                } else {
+                    "http://ws.bhiss.pbh.gov.br/RecepcionarLoteRps"
+                },
+                request_data,
+            )?;
+
+            let resposta = Resposta::from_soap(status_code, &String::from_utf8_lossy(&data))?;
+
+            let retorno = if sincrono {
+                let (nfses, xmls): (Vec<_>, Vec<_>) =
+                    Nfse::from_resposta(&resposta)?.into_iter().unzip();
+
+                let nomes_arquivos = self
+                    .nomes_arquivos_das_nfses(&nfses)
+                    .into_iter()
+                    .map(|nome_arquivo| nome_arquivo.map(str::to_owned))
+                    .collect::<Vec<_>>();
+
+                RetornoLote::Nfses(
+                    nfses
+                        .into_iter()
+                        .zip(xmls)
+                        .zip(nomes_arquivos)
+                        .map(|((nfse, xml), nome_arquivo)| (nfse, xml, nome_arquivo))
+                        .collect(),
+                )
+            } else {
+                RetornoLote::Protocolo(
+                    resposta
+                        .text("Protocolo")?
+                        .ok_or(Error::xml("missing Protocolo in response"))?,
+                )
+            };
+
+            let notas = self
+                .rpses
+                .iter()
+                .map(|rps| (rps.identificacao().serie, rps.nome_arquivo.as_str()))
+                .collect::<Vec<_>>();
+            numeracao.confirmar(
+                &self.cnpj,
+                &notas
+                    .iter()
+                    .map(|(serie, nome_arquivo)| (serie.as_str(), *nome_arquivo))
+                    .collect::<Vec<_>>(),
+            )?;
+
+            Ok(retorno)
+        })();
+
+        ResultadoLote {
+            numero_lote: self.numero_lote.clone().unwrap_or_default(),
+            nomes_arquivos: self
+                .rpses
+                .iter()
+                .map(|rps| rps.nome_arquivo.clone())
+                .collect(),
+            resultado,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::algorithms::CanonicalizationAlgorithm;
     use crate::algorithms::DigestAlgorithm;
     use crate::algorithms::SignatureAlgorithm;
+    use crate::nfse::Nfse;
     use crate::utils;
 
     #[test]
@@ -215,6 +592,7 @@ mod tests {
 cnpj: cnpj_prestador
 inscricao_municipal: inscricao_municipal_prestador
 codigo_municipio: codigo_municipio_prestador
+numero_lote: 7
 notas_fiscais:
   - id: 1234
     nome_arquivo: ACME_1234
@@ -264,15 +642,451 @@ notas_fiscais:
         )
         .unwrap();
 
-        let mut lote_rps = super::LoteRps::from_yaml(&yaml).unwrap();
+        let mut lote_rps = super::LoteRps::from_yaml(&yaml, &|_, _, _| unreachable!()).unwrap();
 
-        lote_rps.sign(signature);
+        lote_rps.sign(signature).unwrap();
 
         assert_eq!(
             utils::xml_events_to_xml_string(&lote_rps.enviar_lote_rps_envio_events()),
             String::from(
-                r##"<EnviarLoteRpsEnvio xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><LoteRps xmlns="http://www.abrasf.org.br/nfse.xsd" Id="lote" versao="1.00"><NumeroLote>1</NumeroLote><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal><QuantidadeRps>2</QuantidadeRps><ListaRps><Rps versao="1.00"><InfRps Id="1234" versao="1.00"><IdentificacaoRps><Numero>1234</Numero><Serie>1</Serie><Tipo>1</Tipo></IdentificacaoRps><DataEmissao>data_emissao</DataEmissao><NaturezaOperacao>natureza_operacao</NaturezaOperacao><RegimeEspecialTributacao>regime_especial_tributacao</RegimeEspecialTributacao><OptanteSimplesNacional>optante_simples_nacional</OptanteSimplesNacional><IncentivadorCultural>incentivador_cultural</IncentivadorCultural><Status>1</Status><Servico><Valores><ValorServicos>1000.00</ValorServicos><IssRetido>1</IssRetido><ValorIss>20.00</ValorIss><ValorIssRetido>20.00</ValorIssRetido><BaseCalculo>1000.00</BaseCalculo><Aliquota>0.02</Aliquota><ValorLiquidoNfse>980.00</ValorLiquidoNfse></Valores><ItemListaServico>item_lista_servico</ItemListaServico><CodigoTributacaoMunicipio>codigo_tributacao_municipio</CodigoTributacaoMunicipio><Discriminacao>discriminacao</Discriminacao><CodigoMunicipio>codigo_municipio_prestador</CodigoMunicipio></Servico><Prestador><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal></Prestador><Tomador><IdentificacaoTomador><CpfCnpj><Cnpj>cnpj_tomador</Cnpj></CpfCnpj><InscricaoMunicipal>inscricao_municipal_tomador</InscricaoMunicipal></IdentificacaoTomador><RazaoSocial>razao_social_tomador</RazaoSocial><Endereco><Endereco>logradouro_tomador</Endereco><Numero>numero_tomador</Numero><Complemento>complemento_tomador</Complemento><Bairro>bairro_tomador</Bairro><CodigoMunicipio>codigo_municipio_tomador</CodigoMunicipio><Uf>uf_tomador</Uf><Cep>cep_tomador</Cep></Endereco></Tomador></InfRps></Rps><Rps versao="1.00"><InfRps Id="5678" versao="1.00"><IdentificacaoRps><Numero>5678</Numero><Serie>1</Serie><Tipo>1</Tipo></IdentificacaoRps><DataEmissao>data_emissao_2</DataEmissao><NaturezaOperacao>natureza_operacao_2</NaturezaOperacao><RegimeEspecialTributacao>regime_especial_tributacao_2</RegimeEspecialTributacao><OptanteSimplesNacional>optante_simples_nacional_2</OptanteSimplesNacional><IncentivadorCultural>incentivador_cultural_2</IncentivadorCultural><Status>1</Status><Servico><Valores><ValorServicos>800.00</ValorServicos><IssRetido>1</IssRetido><ValorIss>24.00</ValorIss><ValorIssRetido>24.00</ValorIssRetido><BaseCalculo>800.00</BaseCalculo><Aliquota>0.03</Aliquota><ValorLiquidoNfse>776.00</ValorLiquidoNfse></Valores><ItemListaServico>item_lista_servico_2</ItemListaServico><CodigoTributacaoMunicipio>codigo_tributacao_municipio_2</CodigoTributacaoMunicipio><Discriminacao>discriminacao_2</Discriminacao><CodigoMunicipio>codigo_municipio_prestador</CodigoMunicipio></Servico><Prestador><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal></Prestador><Tomador><IdentificacaoTomador><CpfCnpj><Cnpj>cnpj_tomador_2</Cnpj></CpfCnpj><InscricaoMunicipal>inscricao_municipal_tomador_2</InscricaoMunicipal></IdentificacaoTomador><RazaoSocial>razao_social_tomador_2</RazaoSocial><Endereco><Endereco>logradouro_tomador_2</Endereco><Numero>numero_tomador_2</Numero><Complemento>complemento_tomador_2</Complemento><Bairro>bairro_tomador_2</Bairro><CodigoMunicipio>codigo_municipio_tomador_2</CodigoMunicipio><Uf>uf_tomador_2</Uf><Cep>cep_tomador_2</Cep></Endereco></Tomador></InfRps></Rps></ListaRps></LoteRps><Signature xmlns="http://www.w3.org/2000/09/xmldsig#"><SignedInfo><CanonicalizationMethod Algorithm="noop-c14n"></CanonicalizationMethod><SignatureMethod Algorithm="echo-signature"></SignatureMethod><Reference URI="#lote"><Transforms><Transform Algorithm="noop-c14n"></Transform></Transforms><DigestMethod Algorithm="echo-digest"></DigestMethod><DigestValue>the_digest</DigestValue></Reference></SignedInfo><SignatureValue>the_signature</SignatureValue><KeyInfo><X509Data><X509Certificate>the_certificate</X509Certificate></X509Data></KeyInfo></Signature></EnviarLoteRpsEnvio>"##
+                r##"<EnviarLoteRpsEnvio xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><LoteRps xmlns="http://www.abrasf.org.br/nfse.xsd" Id="lote7" versao="1.00"><NumeroLote>7</NumeroLote><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal><QuantidadeRps>2</QuantidadeRps><ListaRps><Rps versao="1.00"><InfRps Id="rps1_1_1234" versao="1.00"><IdentificacaoRps><Numero>1234</Numero><Serie>1</Serie><Tipo>1</Tipo></IdentificacaoRps><DataEmissao>data_emissao</DataEmissao><NaturezaOperacao>natureza_operacao</NaturezaOperacao><RegimeEspecialTributacao>regime_especial_tributacao</RegimeEspecialTributacao><OptanteSimplesNacional>optante_simples_nacional</OptanteSimplesNacional><IncentivadorCultural>incentivador_cultural</IncentivadorCultural><Status>1</Status><Servico><Valores><ValorServicos>1000.00</ValorServicos><IssRetido>1</IssRetido><ValorIss>20.00</ValorIss><ValorIssRetido>20.00</ValorIssRetido><BaseCalculo>1000.00</BaseCalculo><Aliquota>0.02</Aliquota><ValorLiquidoNfse>980.00</ValorLiquidoNfse></Valores><ItemListaServico>item_lista_servico</ItemListaServico><CodigoTributacaoMunicipio>codigo_tributacao_municipio</CodigoTributacaoMunicipio><Discriminacao>discriminacao</Discriminacao><CodigoMunicipio>codigo_municipio_prestador</CodigoMunicipio></Servico><Prestador><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal></Prestador><Tomador><IdentificacaoTomador><CpfCnpj><Cnpj>cnpj_tomador</Cnpj></CpfCnpj><InscricaoMunicipal>inscricao_municipal_tomador</InscricaoMunicipal></IdentificacaoTomador><RazaoSocial>razao_social_tomador</RazaoSocial><Endereco><Endereco>logradouro_tomador</Endereco><Numero>numero_tomador</Numero><Complemento>complemento_tomador</Complemento><Bairro>bairro_tomador</Bairro><CodigoMunicipio>codigo_municipio_tomador</CodigoMunicipio><Uf>uf_tomador</Uf><Cep>cep_tomador</Cep></Endereco></Tomador></InfRps></Rps><Rps versao="1.00"><InfRps Id="rps1_1_5678" versao="1.00"><IdentificacaoRps><Numero>5678</Numero><Serie>1</Serie><Tipo>1</Tipo></IdentificacaoRps><DataEmissao>data_emissao_2</DataEmissao><NaturezaOperacao>natureza_operacao_2</NaturezaOperacao><RegimeEspecialTributacao>regime_especial_tributacao_2</RegimeEspecialTributacao><OptanteSimplesNacional>optante_simples_nacional_2</OptanteSimplesNacional><IncentivadorCultural>incentivador_cultural_2</IncentivadorCultural><Status>1</Status><Servico><Valores><ValorServicos>800.00</ValorServicos><IssRetido>1</IssRetido><ValorIss>24.00</ValorIss><ValorIssRetido>24.00</ValorIssRetido><BaseCalculo>800.00</BaseCalculo><Aliquota>0.03</Aliquota><ValorLiquidoNfse>776.00</ValorLiquidoNfse></Valores><ItemListaServico>item_lista_servico_2</ItemListaServico><CodigoTributacaoMunicipio>codigo_tributacao_municipio_2</CodigoTributacaoMunicipio><Discriminacao>discriminacao_2</Discriminacao><CodigoMunicipio>codigo_municipio_prestador</CodigoMunicipio></Servico><Prestador><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal></Prestador><Tomador><IdentificacaoTomador><CpfCnpj><Cnpj>cnpj_tomador_2</Cnpj></CpfCnpj><InscricaoMunicipal>inscricao_municipal_tomador_2</InscricaoMunicipal></IdentificacaoTomador><RazaoSocial>razao_social_tomador_2</RazaoSocial><Endereco><Endereco>logradouro_tomador_2</Endereco><Numero>numero_tomador_2</Numero><Complemento>complemento_tomador_2</Complemento><Bairro>bairro_tomador_2</Bairro><CodigoMunicipio>codigo_municipio_tomador_2</CodigoMunicipio><Uf>uf_tomador_2</Uf><Cep>cep_tomador_2</Cep></Endereco></Tomador></InfRps></Rps></ListaRps></LoteRps><Signature xmlns="http://www.w3.org/2000/09/xmldsig#"><SignedInfo><CanonicalizationMethod Algorithm="noop-c14n"></CanonicalizationMethod><SignatureMethod Algorithm="echo-signature"></SignatureMethod><Reference URI="#lote7"><Transforms><Transform Algorithm="noop-c14n"></Transform></Transforms><DigestMethod Algorithm="echo-digest"></DigestMethod><DigestValue>the_digest</DigestValue></Reference></SignedInfo><SignatureValue>the_signature</SignatureValue><KeyInfo><X509Data><X509Certificate>the_certificate</X509Certificate></X509Data></KeyInfo></Signature></EnviarLoteRpsEnvio>"##
             )
         );
+
+        assert_eq!(
+            utils::xml_events_to_xml_string(&lote_rps.gerar_nfse_envio_events()),
+            utils::xml_events_to_xml_string(&lote_rps.enviar_lote_rps_envio_events())
+                .replace("EnviarLoteRpsEnvio", "GerarNfseEnvio")
+        );
+    }
+
+    #[test]
+    fn should_sign_rpses_of_several_series_with_the_same_numero() {
+        use crate::signature::SignatureVerification;
+        use rsa::pkcs8::DecodePrivateKey;
+
+        const CERTIFICATE: &str = include_str!("../tests/fixtures/certificado.cer");
+        const PRIVATE_KEY: &str = include_str!("../tests/fixtures/certificado.key");
+
+        let nota = |serie: &str| {
+            format!(
+                "
+  - id: 1
+    nome_arquivo: ACME_{serie}
+    serie: {serie}
+    competencia: data_emissao
+    natureza_operacao: natureza_operacao
+    regime_especial_tributacao: regime_especial_tributacao
+    optante_simples_nacional: optante_simples_nacional
+    incentivador_cultural: incentivador_cultural
+    item_lista_servico: item_lista_servico
+    codigo_tributacao_municipio: codigo_tributacao_municipio
+    discriminacao: discriminacao
+    valor_servicos: 1000.00
+    razao_social: razao_social_tomador"
+            )
+        };
+
+        let yaml: serde_yaml::Mapping = serde_yaml::from_str(&format!(
+            "
+cnpj: cnpj_prestador
+inscricao_municipal: inscricao_municipal_prestador
+codigo_municipio: codigo_municipio_prestador
+numero_lote: 7
+notas_fiscais:{}{}
+",
+            nota("A"),
+            nota("B")
+        ))
+        .unwrap();
+
+        let signature = super::XmlSignature::new(
+            CanonicalizationAlgorithm::ExclusiveXMLCanonicalization,
+            SignatureAlgorithm::RsaSha1(std::sync::Arc::new(
+                rsa::RsaPrivateKey::from_pkcs8_pem(PRIVATE_KEY).unwrap(),
+            )),
+            DigestAlgorithm::Sha1,
+            utils::trim_x509_certificate(CERTIFICATE),
+        );
+
+        let mut lote_rps = super::LoteRps::from_yaml(&yaml, &|_, _, _| unreachable!()).unwrap();
+        for rps in lote_rps.get_rpses() {
+            rps.sign(signature.clone()).unwrap();
+        }
+        lote_rps.sign(signature).unwrap();
+
+        let xml = utils::xml_events_to_xml_string(&lote_rps.enviar_lote_rps_envio_events());
+        assert!(xml.contains(r##"<InfRps Id="rpsA_1_1" versao="1.00">"##));
+        assert!(xml.contains(r##"<InfRps Id="rpsB_1_1" versao="1.00">"##));
+
+        let verifications = SignatureVerification::from_xml_string(&xml).unwrap();
+        assert_eq!(verifications.len(), 3);
+        assert!(verifications
+            .iter()
+            .all(|verification| verification.is_valid()));
+    }
+
+    #[test]
+    fn should_number_notas_without_id() {
+        let yaml = |nome_arquivo: &str| -> serde_yaml::Mapping {
+            serde_yaml::from_str(&format!(
+                "
+cnpj: cnpj_prestador
+inscricao_municipal: inscricao_municipal_prestador
+codigo_municipio: codigo_municipio_prestador
+serie: FIL2
+notas_fiscais:
+  - nome_arquivo: ACME_1
+    competencia: data_emissao
+    natureza_operacao: natureza_operacao
+    regime_especial_tributacao: regime_especial_tributacao
+    optante_simples_nacional: optante_simples_nacional
+    incentivador_cultural: incentivador_cultural
+    item_lista_servico: item_lista_servico
+    codigo_tributacao_municipio: codigo_tributacao_municipio
+    discriminacao: discriminacao
+    valor_servicos: 1000.00
+    razao_social: razao_social_tomador
+  - nome_arquivo: {nome_arquivo}
+    competencia: data_emissao
+    natureza_operacao: natureza_operacao
+    regime_especial_tributacao: regime_especial_tributacao
+    optante_simples_nacional: optante_simples_nacional
+    incentivador_cultural: incentivador_cultural
+    item_lista_servico: item_lista_servico
+    codigo_tributacao_municipio: codigo_tributacao_municipio
+    discriminacao: discriminacao
+    valor_servicos: 1000.00
+    razao_social: razao_social_tomador
+"
+            ))
+            .unwrap()
+        };
+
+        let numerador = |cnpj: &str, serie: &str, nome_arquivo: &str| {
+            Ok(format!("{cnpj}-{serie}-{nome_arquivo}"))
+        };
+
+        let mut lote_rps = super::LoteRps::from_yaml(&yaml("ACME_2"), &numerador).unwrap();
+
+        assert_eq!(
+            lote_rps
+                .get_rpses()
+                .map(|rps| rps.identificacao().numero)
+                .collect::<Vec<_>>(),
+            vec!["cnpj_prestador-FIL2-ACME_1", "cnpj_prestador-FIL2-ACME_2"]
+        );
+
+        assert_eq!(
+            super::LoteRps::from_yaml(&yaml("ACME_1"), &numerador)
+                .err()
+                .map(|e| e.to_string()),
+            Some(String::from(
+                "bad input: notas_fiscais.1.nome_arquivo: duplicated"
+            ))
+        );
+    }
+
+    #[test]
+    fn should_split_lote_by_rps_count_and_size() {
+        let yaml = |limites: &str| -> serde_yaml::Mapping {
+            let nota = |i: usize| {
+                format!(
+                    "
+  - id: {i}
+    nome_arquivo: ACME_{i}
+    competencia: data_emissao
+    natureza_operacao: natureza_operacao
+    regime_especial_tributacao: regime_especial_tributacao
+    optante_simples_nacional: optante_simples_nacional
+    incentivador_cultural: incentivador_cultural
+    item_lista_servico: item_lista_servico
+    codigo_tributacao_municipio: codigo_tributacao_municipio
+    discriminacao: discriminacao
+    valor_servicos: 1000.00
+    razao_social: razao_social_tomador"
+                )
+            };
+
+            serde_yaml::from_str(&format!(
+                "
+cnpj: cnpj_prestador
+inscricao_municipal: inscricao_municipal_prestador
+codigo_municipio: codigo_municipio_prestador
+numero_lote: 7
+{limites}
+notas_fiscais:{}
+",
+                (1..=5).map(nota).collect::<String>()
+            ))
+            .unwrap()
+        };
+
+        let lotes = |limites: &str| {
+            super::LoteRps::from_yaml(&yaml(limites), &|_, _, _| unreachable!())
+                .unwrap()
+                .dividir()
+                .map(|lotes| {
+                    lotes
+                        .into_iter()
+                        .map(|mut lote| {
+                            format!(
+                                "{}: {}",
+                                lote.get_numero_lote().unwrap().to_owned(),
+                                lote.get_rpses()
+                                    .map(|rps| rps.nome_arquivo.clone())
+                                    .collect::<Vec<_>>()
+                                    .join(" ")
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+        };
+
+        let mut invalido = yaml("");
+        invalido.insert("numero_lote".into(), "lote7".into());
+        assert_eq!(
+            super::LoteRps::from_yaml(&invalido, &|_, _, _| unreachable!())
+                .err()
+                .map(|e| e.to_string()),
+            Some(String::from(
+                "bad input: numero_lote: expected up to 15 digits"
+            ))
+        );
+
+        // the numbers of the following lotes must still fit in 15 digits
+        let mut grande = yaml("max_rps_por_lote: 2");
+        grande.insert("numero_lote".into(), "999999999999998".into());
+        assert_eq!(
+            super::LoteRps::from_yaml(&grande, &|_, _, _| unreachable!())
+                .unwrap()
+                .dividir()
+                .err()
+                .map(|e| e.to_string()),
+            Some(String::from("bad input: numero_lote: too large to split"))
+        );
+
+        assert_eq!(
+            lotes("max_rps_por_lote: 2").unwrap(),
+            vec!["7: ACME_1 ACME_2", "8: ACME_3 ACME_4", "9: ACME_5"]
+        );
+
+        // each unsigned note takes a little over 1 KB
+        assert_eq!(
+            lotes("max_bytes_por_lote: 12000").unwrap(),
+            vec!["7: ACME_1 ACME_2 ACME_3", "8: ACME_4 ACME_5"]
+        );
+
+        assert_eq!(
+            lotes("max_bytes_por_lote: 9000")
+                .err()
+                .map(|e| e.to_string()),
+            Some(String::from(
+                "bad input: notas_fiscais.0: larger than max_bytes_por_lote"
+            ))
+        );
+    }
+
+    #[test]
+    fn should_match_nfses_to_notas_by_identificacao_rps() {
+        let nota = |id: &str, nome_arquivo: &str, razao_social: &str| {
+            format!(
+                "
+  - id: {id}
+    nome_arquivo: {nome_arquivo}
+    competencia: data_emissao
+    natureza_operacao: natureza_operacao
+    regime_especial_tributacao: regime_especial_tributacao
+    optante_simples_nacional: optante_simples_nacional
+    incentivador_cultural: incentivador_cultural
+    item_lista_servico: item_lista_servico
+    codigo_tributacao_municipio: codigo_tributacao_municipio
+    discriminacao: Consultoria
+    valor_servicos: 95.31
+    razao_social: {razao_social}"
+            )
+        };
+
+        // the same service twice for the same tomador
+        let yaml: serde_yaml::Mapping = serde_yaml::from_str(&format!(
+            "
+cnpj: cnpj_prestador
+inscricao_municipal: inscricao_municipal_prestador
+codigo_municipio: codigo_municipio_prestador
+notas_fiscais:{}{}{}
+",
+            nota("1", "ACME_1", "NOME DO TOMADOR"),
+            nota("2", "ACME_2", "NOME DO TOMADOR"),
+            nota("3", "ACME_3", "OUTRO TOMADOR"),
+        ))
+        .unwrap();
+
+        let lote_rps = super::LoteRps::from_yaml(&yaml, &|_, _, _| unreachable!()).unwrap();
+
+        let nfse = |numero: &str, identificacao_rps: &str, razao_social: &str| {
+            Nfse::from_xml_string(&format!(
+                r##"<?xml version='1.0' encoding='UTF-8'?><CompNfse xmlns="http://www.abrasf.org.br/nfse.xsd"><Nfse xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><InfNfse Id="nfse"><Numero>{numero}</Numero><CodigoVerificacao>67890</CodigoVerificacao><DataEmissao>2024-03-05T01:02:03</DataEmissao>{identificacao_rps}<NaturezaOperacao>1</NaturezaOperacao><RegimeEspecialTributacao>6</RegimeEspecialTributacao><OptanteSimplesNacional>1</OptanteSimplesNacional><IncentivadorCultural>2</IncentivadorCultural><Competencia>2024-03-01T00:00:00</Competencia><OutrasInformacoes>Teste</OutrasInformacoes><Servico><Valores><ValorServicos>95.31</ValorServicos><IssRetido>2</IssRetido></Valores><ItemListaServico>1.04</ItemListaServico><CodigoTributacaoMunicipio>10400188</CodigoTributacaoMunicipio><Discriminacao>Consultoria</Discriminacao><CodigoMunicipio>3106200</CodigoMunicipio></Servico><PrestadorServico><IdentificacaoPrestador><Cnpj>12345678000190</Cnpj></IdentificacaoPrestador><RazaoSocial>NOME DA EMPRESA</RazaoSocial><Endereco><Endereco>RUA DO PRESTADOR</Endereco><Numero>12</Numero><Bairro>Bairro Um</Bairro><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf><Cep>34567890</Cep></Endereco></PrestadorServico><TomadorServico><RazaoSocial>{razao_social}</RazaoSocial></TomadorServico></InfNfse></Nfse></CompNfse>"##
+            ))
+            .unwrap()
+        };
+        let identificacao = |numero: &str| {
+            format!("<IdentificacaoRps><Numero>{numero}</Numero><Serie>1</Serie><Tipo>1</Tipo></IdentificacaoRps>")
+        };
+
+        let nfses = vec![
+            nfse("101", &identificacao("2"), "NOME DO TOMADOR"),
+            nfse("102", &identificacao("1"), "NOME DO TOMADOR"),
+            // issued before IdentificacaoRps was returned
+            nfse("103", "", "OUTRO TOMADOR"),
+            nfse("104", &identificacao("9"), "NOME DO TOMADOR"),
+            nfse("105", "", "NOME DO TOMADOR"),
+        ];
+
+        assert_eq!(
+            lote_rps.nomes_arquivos_das_nfses(&nfses),
+            vec![Some("ACME_2"), Some("ACME_1"), Some("ACME_3"), None, None]
+        );
+    }
+
+    #[test]
+    fn should_send_each_lote_and_report_its_result() {
+        use crate::curl::{Request, RequestMethod};
+        use crate::error::Error;
+        use httptest::{matchers, responders, Expectation, Server};
+        use rsa::pkcs8::DecodePrivateKey;
+
+        const CERTIFICATE: &str = include_str!("../tests/fixtures/certificado.cer");
+        const PRIVATE_KEY: &str = include_str!("../tests/fixtures/certificado.key");
+
+        let nota = |i: usize| {
+            format!(
+                "
+  - nome_arquivo: ACME_{i}
+    competencia: data_emissao
+    natureza_operacao: natureza_operacao
+    regime_especial_tributacao: regime_especial_tributacao
+    optante_simples_nacional: optante_simples_nacional
+    incentivador_cultural: incentivador_cultural
+    item_lista_servico: item_lista_servico
+    codigo_tributacao_municipio: codigo_tributacao_municipio
+    discriminacao: discriminacao
+    valor_servicos: 1000.00
+    razao_social: razao_social_tomador"
+            )
+        };
+
+        let yaml: serde_yaml::Mapping = serde_yaml::from_str(&format!(
+            "
+cnpj: cnpj_prestador
+inscricao_municipal: inscricao_municipal_prestador
+codigo_municipio: codigo_municipio_prestador
+max_rps_por_lote: 1
+notas_fiscais:{}{}
+",
+            nota(1),
+            nota(2)
+        ))
+        .unwrap();
+
+        let arquivo = std::env::temp_dir().join(format!(
+            "numeracao_lotes_{}_{:?}.yml",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let _ = std::fs::remove_file(&arquivo);
+        let numeracao = super::NumeracaoRps::new(&arquivo);
+
+        let signature = super::XmlSignature::new(
+            CanonicalizationAlgorithm::ExclusiveXMLCanonicalization,
+            SignatureAlgorithm::RsaSha1(std::sync::Arc::new(
+                rsa::RsaPrivateKey::from_pkcs8_pem(PRIVATE_KEY).unwrap(),
+            )),
+            DigestAlgorithm::Sha1,
+            utils::trim_x509_certificate(CERTIFICATE),
+        );
+
+        let mut lote_rps = super::LoteRps::from_yaml(&yaml, &|cnpj, serie, nome_arquivo| {
+            numeracao.reservar(cnpj, serie, nome_arquivo)
+        })
+        .unwrap();
+        for rps in lote_rps.get_rpses() {
+            rps.sign(signature.clone()).unwrap();
+        }
+
+        let envelope = |output: &str| {
+            format!(
+                r##"<S:Envelope xmlns:S="http://schemas.xmlsoap.org/soap/envelope/"><S:Body><ns2:RecepcionarLoteRpsResponse xmlns:ns2="http://ws.bhiss.pbh.gov.br"><outputXML>{}</outputXML></ns2:RecepcionarLoteRpsResponse></S:Body></S:Envelope>"##,
+                output.replace('<', "&lt;").replace('>', "&gt;")
+            )
+        };
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(matchers::all_of![
+                matchers::request::method("POST"),
+                matchers::request::headers(matchers::contains((
+                    "soapaction",
+                    "http://ws.bhiss.pbh.gov.br/RecepcionarLoteRps"
+                ))),
+            ])
+            .times(2)
+            .respond_with(responders::cycle![
+                responders::status_code(200).body(envelope(
+                    r##"<EnviarLoteRpsResposta xmlns="http://www.abrasf.org.br/nfse.xsd"><NumeroLote>1</NumeroLote><Protocolo>P1</Protocolo></EnviarLoteRpsResposta>"##
+                )),
+                responders::status_code(200).body(envelope(
+                    r##"<EnviarLoteRpsResposta xmlns="http://www.abrasf.org.br/nfse.xsd"><ListaMensagemRetorno><MensagemRetorno><Codigo>E160</Codigo><Mensagem>Arquivo em desacordo com o XML Schema.</Mensagem></MensagemRetorno></ListaMensagemRetorno></EnviarLoteRpsResposta>"##
+                )),
+            ]),
+        );
+
+        let resultados = super::LoteRps::enviar_lotes(
+            lote_rps.dividir().unwrap(),
+            &signature,
+            &numeracao,
+            false,
+            &|soap_action, request_data| {
+                Request::new()
+                    .set_url(server.url("/").to_string())
+                    .set_header(String::from("SOAPAction"), Some(soap_action.to_owned()))
+                    .set_method(RequestMethod::POST(request_data))
+                    .run()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            resultados
+                .iter()
+                .map(|resultado| (
+                    resultado.numero_lote.as_str(),
+                    resultado.nomes_arquivos.clone()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("1", vec![String::from("ACME_1")]),
+                ("2", vec![String::from("ACME_2")])
+            ]
+        );
+        assert!(matches!(
+            &resultados[0].resultado,
+            Ok(super::RetornoLote::Protocolo(protocolo)) if protocolo == "P1"
+        ));
+        assert!(matches!(
+            &resultados[1].resultado,
+            Err(Error::Rejected { .. })
+        ));
+
+        // only the accepted lote is confirmed, the rejected note keeps its number for the next run
+        assert_eq!(
+            numeracao.reservar("cnpj_prestador", "1", "ACME_2").unwrap(),
+            "2"
+        );
+        assert_eq!(
+            numeracao.reservar("cnpj_prestador", "1", "ACME_1").unwrap(),
+            "3"
+        );
+
+        std::fs::remove_file(&arquivo).unwrap();
     }
 }
@@ -1,457 +1,541 @@
+use quick_xml::events::BytesStart;
 use quick_xml::events::Event;
-use quick_xml::reader::Reader;
+use quick_xml::name::Namespace;
+use quick_xml::name::ResolveResult;
+use quick_xml::reader::NsReader;
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::resposta::Resposta;
+use crate::rps::IdentificacaoRps;
+use crate::valor::Aliquota;
+use crate::valor::Valor;
 
 #[derive(PartialEq, Debug)]
 pub struct Nfse {
     numero: String,
     codigo_verificacao: String,
     data_emissao: String,
+    identificacao_rps: Option<IdentificacaoRps>,
     competencia: String,
     natureza_operacao: String,
-    regime_especial_tributacao: String,
+    regime_especial_tributacao: Option<String>,
     optante_simples_nacional: String,
     incentivador_cultural: String,
-    outras_informacoes: String,
-    valor_servicos: String,
-    aliquota_iss: Option<String>,
-    valor_iss: Option<String>,
-    codigo_tributacao_municipio: String,
+    outras_informacoes: Option<String>,
+    valor_servicos: Valor,
+    aliquota_iss: Option<Aliquota>,
+    valor_iss: Option<Valor>,
+    iss_retido: String,
+    valor_iss_retido: Option<Valor>,
+    valor_deducoes: Option<Valor>,
+    valor_pis: Option<Valor>,
+    valor_cofins: Option<Valor>,
+    valor_inss: Option<Valor>,
+    valor_ir: Option<Valor>,
+    valor_csll: Option<Valor>,
+    outras_retencoes: Option<Valor>,
+    base_calculo: Option<Valor>,
+    valor_liquido_nfse: Option<Valor>,
+    desconto_incondicionado: Option<Valor>,
+    desconto_condicionado: Option<Valor>,
+    codigo_tributacao_municipio: Option<String>,
     discriminacao: String,
     codigo_municipio: String,
     cnpj_prestador: String,
     inscricao_municipal_prestador: Option<String>,
     razao_social_prestador: String,
-    logradouro_prestador: String,
-    numero_prestador: String,
+    logradouro_prestador: Option<String>,
+    numero_prestador: Option<String>,
     complemento_prestador: Option<String>,
-    bairro_prestador: String,
-    codigo_municipio_prestador: String,
-    uf_prestador: String,
-    cep_prestador: String,
+    bairro_prestador: Option<String>,
+    codigo_municipio_prestador: Option<String>,
+    uf_prestador: Option<String>,
+    cep_prestador: Option<String>,
+    cpf_tomador: Option<String>,
     cnpj_tomador: Option<String>,
     inscricao_municipal_tomador: Option<String>,
-    razao_social_tomador: String,
-    logradouro_tomador: String,
-    numero_tomador: String,
+    razao_social_tomador: Option<String>,
+    logradouro_tomador: Option<String>,
+    numero_tomador: Option<String>,
     complemento_tomador: Option<String>,
-    bairro_tomador: String,
-    codigo_municipio_tomador: String,
-    uf_tomador: String,
+    bairro_tomador: Option<String>,
+    codigo_municipio_tomador: Option<String>,
+    uf_tomador: Option<String>,
     cep_tomador: Option<String>,
+    telefone_tomador: Option<String>,
+    email_tomador: Option<String>,
+    razao_social_intermediario: Option<String>,
+    cpf_intermediario: Option<String>,
+    cnpj_intermediario: Option<String>,
+    inscricao_municipal_intermediario: Option<String>,
+    codigo_obra: Option<String>,
+    art: Option<String>,
+    nfse_substituida: Option<String>,
+    nfse_substituidora: Option<String>,
+}
+
+const NAMESPACE: &str = "http://www.abrasf.org.br/nfse.xsd";
+
+// the elements of a CompNfse read by serde; every one is optional here, so the conversion into
+// Nfse can say which required field is missing
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct CompNfseXml {
+    nfse: NfseXml,
+    nfse_substituicao: NfseSubstituicaoXml,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct NfseXml {
+    inf_nfse: InfNfseXml,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct NfseSubstituicaoXml {
+    substituicao_nfse: SubstituicaoNfseXml,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct SubstituicaoNfseXml {
+    nfse_substituidora: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct InfNfseXml {
+    numero: Option<String>,
+    codigo_verificacao: Option<String>,
+    data_emissao: Option<String>,
+    identificacao_rps: IdentificacaoRpsXml,
+    natureza_operacao: Option<String>,
+    regime_especial_tributacao: Option<String>,
+    optante_simples_nacional: Option<String>,
+    incentivador_cultural: Option<String>,
+    competencia: Option<String>,
+    nfse_substituida: Option<String>,
+    outras_informacoes: Option<String>,
+    servico: ServicoXml,
+    prestador_servico: PrestadorServicoXml,
+    tomador_servico: TomadorServicoXml,
+    intermediario_servico: IntermediarioServicoXml,
+    construcao_civil: ConstrucaoCivilXml,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct IdentificacaoRpsXml {
+    numero: Option<String>,
+    serie: Option<String>,
+    tipo: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct ServicoXml {
+    valores: ValoresXml,
+    codigo_tributacao_municipio: Option<String>,
+    discriminacao: Option<String>,
+    codigo_municipio: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct ValoresXml {
+    valor_servicos: Option<String>,
+    valor_deducoes: Option<String>,
+    valor_pis: Option<String>,
+    valor_cofins: Option<String>,
+    valor_inss: Option<String>,
+    valor_ir: Option<String>,
+    valor_csll: Option<String>,
+    iss_retido: Option<String>,
+    valor_iss: Option<String>,
+    valor_iss_retido: Option<String>,
+    outras_retencoes: Option<String>,
+    base_calculo: Option<String>,
+    aliquota: Option<String>,
+    valor_liquido_nfse: Option<String>,
+    desconto_incondicionado: Option<String>,
+    desconto_condicionado: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct PrestadorServicoXml {
+    identificacao_prestador: IdentificacaoPrestadorXml,
+    razao_social: Option<String>,
+    endereco: EnderecoXml,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct IdentificacaoPrestadorXml {
+    cnpj: Option<String>,
+    inscricao_municipal: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct EnderecoXml {
+    endereco: Option<String>,
+    numero: Option<String>,
+    complemento: Option<String>,
+    bairro: Option<String>,
+    codigo_municipio: Option<String>,
+    uf: Option<String>,
+    cep: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct TomadorServicoXml {
+    identificacao_tomador: IdentificacaoTomadorXml,
+    razao_social: Option<String>,
+    endereco: EnderecoXml,
+    contato: ContatoXml,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct IdentificacaoTomadorXml {
+    cpf_cnpj: CpfCnpjXml,
+    inscricao_municipal: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct CpfCnpjXml {
+    cpf: Option<String>,
+    cnpj: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct ContatoXml {
+    telefone: Option<String>,
+    email: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct IntermediarioServicoXml {
+    razao_social: Option<String>,
+    cpf_cnpj: CpfCnpjXml,
+    inscricao_municipal: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct ConstrucaoCivilXml {
+    codigo_obra: Option<String>,
+    art: Option<String>,
+}
+
+impl Nfse {
+    // the first CompNfse of xml, alone or inside a ListaNfse or any response
+    pub fn from_xml_string(xml: &str) -> Result<Self, Error> {
+        Self::lista_from_xml_string(xml)?
+            .into_iter()
+            .next()
+            .map(|(nfse, _)| nfse)
+            .ok_or(Error::xml("missing CompNfse"))
+    }
 }
 
 impl Nfse {
-    pub fn from_xml_string(xml: &str) -> Result<Self, String> {
-        let inf_nfse = xml
-            .strip_prefix("<?xml version='1.0' encoding='UTF-8'?><CompNfse xmlns=\"http://www.abrasf.org.br/nfse.xsd\"><Nfse xmlns=\"http://www.abrasf.org.br/nfse.xsd\" versao=\"1.00\">")
-            .ok_or(String::from("bad xml prefix"))?
-            .strip_suffix("</Nfse></CompNfse>")
-            .ok_or(String::from("bad xml suffix"))?;
-
-        let mut reader = Reader::from_str(&inf_nfse);
-
-        let mut names = Vec::new();
-
-        let mut numero: Option<String> = None;
-        let mut codigo_verificacao: Option<String> = None;
-        let mut data_emissao: Option<String> = None;
-        let mut competencia: Option<String> = None;
-        let mut natureza_operacao: Option<String> = None;
-        let mut regime_especial_tributacao: Option<String> = None;
-        let mut optante_simples_nacional: Option<String> = None;
-        let mut incentivador_cultural: Option<String> = None;
-        let mut outras_informacoes: Option<String> = None;
-        let mut valor_servicos: Option<String> = None;
-        let mut aliquota_iss: Option<String> = None;
-        let mut valor_iss: Option<String> = None;
-        let mut codigo_tributacao_municipio: Option<String> = None;
-        let mut discriminacao: Option<String> = None;
-        let mut codigo_municipio: Option<String> = None;
-        let mut cnpj_prestador: Option<String> = None;
-        let mut inscricao_municipal_prestador: Option<String> = None;
-        let mut razao_social_prestador: Option<String> = None;
-        let mut logradouro_prestador: Option<String> = None;
-        let mut numero_prestador: Option<String> = None;
-        let mut complemento_prestador: Option<String> = None;
-        let mut bairro_prestador: Option<String> = None;
-        let mut codigo_municipio_prestador: Option<String> = None;
-        let mut uf_prestador: Option<String> = None;
-        let mut cep_prestador: Option<String> = None;
-        let mut cnpj_tomador: Option<String> = None;
-        let mut inscricao_municipal_tomador: Option<String> = None;
-        let mut razao_social_tomador: Option<String> = None;
-        let mut logradouro_tomador: Option<String> = None;
-        let mut numero_tomador: Option<String> = None;
-        let mut complemento_tomador: Option<String> = None;
-        let mut bairro_tomador: Option<String> = None;
-        let mut codigo_municipio_tomador: Option<String> = None;
-        let mut uf_tomador: Option<String> = None;
-        let mut cep_tomador: Option<String> = None;
+    // every CompNfse of xml in document order, such as the ListaNfse of ConsultarLoteRpsResposta,
+    // each along with its own xml: a standalone document that declares the namespaces it uses
+    pub fn lista_from_xml_string(xml: &str) -> Result<Vec<(Self, String)>, Error> {
+        let mut reader = NsReader::from_str(xml);
+
+        // namespace declarations of the open elements, so a CompNfse taken out of a response
+        // keeps the ones it inherited
+        let mut declaracoes: Vec<Vec<(String, String)>> = Vec::new();
+        let mut nfses = Vec::new();
 
         loop {
-            match reader.read_event() {
-                Ok(Event::Start(e)) => {
-                    names.push(e.clone());
-                    Ok(())
-                }
-                Ok(Event::End(e)) => {
-                    match names.pop() {
-                        Some(pop) => {
-                            if pop.name() != e.name() {
-                                return Err(String::from("bad xml"));
-                            };
-                        }
-                        None => {
-                            return Err(String::from("bad xml"));
+            match reader.read_resolved_event()? {
+                (ResolveResult::Bound(Namespace(ns)), Event::Start(e))
+                    if ns == NAMESPACE.as_bytes() && e.local_name().as_ref() == b"CompNfse" =>
+                {
+                    let conteudo = reader.read_to_end(e.name())?;
+
+                    let mut start = e.to_owned();
+                    let mut herdadas = declaracoes.iter().flatten().collect::<Vec<_>>();
+                    herdadas.reverse();
+                    let mut declaradas = Self::declaracoes(&e)?
+                        .into_iter()
+                        .map(|(key, _)| key)
+                        .collect::<Vec<_>>();
+                    for (key, value) in herdadas {
+                        if !declaradas.contains(key) {
+                            start.push_attribute((key.as_str(), value.as_str()));
+                            declaradas.push(key.clone());
                         }
                     }
-                    Ok(())
+
+                    let nome = std::str::from_utf8(e.name().into_inner())?;
+                    let comp_nfse = format!(
+                        "<?xml version='1.0' encoding='UTF-8'?><{}>{}</{nome}>",
+                        std::str::from_utf8(&start)?,
+                        &xml[conteudo],
+                    );
+
+                    let parsed: CompNfseXml = quick_xml::de::from_str(&comp_nfse)
+                        .map_err(|e| Error::xml("malformed CompNfse").with_source(e))?;
+
+                    nfses.push((Self::from_comp_nfse(parsed)?, comp_nfse));
                 }
-                Ok(Event::Text(e)) => {
-                    let mut names_iter = names.iter();
-                    match names_iter.next() {
-                        Some(elem) => match elem.name().as_ref() {
-                            b"InfNfse" => match names_iter.next() {
-                                Some(elem) => match elem.name().as_ref() {
-                                    b"Numero" => {
-                                        numero = Some(String::from_utf8(e.to_vec()).unwrap())
-                                    }
-                                    b"CodigoVerificacao" => {
-                                        codigo_verificacao =
-                                            Some(String::from_utf8(e.to_vec()).unwrap())
-                                    }
-                                    b"DataEmissao" => {
-                                        data_emissao = Some(String::from_utf8(e.to_vec()).unwrap())
-                                    }
-                                    b"Competencia" => {
-                                        competencia = Some(String::from_utf8(e.to_vec()).unwrap())
-                                    }
-                                    b"NaturezaOperacao" => {
-                                        natureza_operacao =
-                                            Some(String::from_utf8(e.to_vec()).unwrap())
-                                    }
-                                    b"RegimeEspecialTributacao" => {
-                                        regime_especial_tributacao =
-                                            Some(String::from_utf8(e.to_vec()).unwrap())
-                                    }
-                                    b"OptanteSimplesNacional" => {
-                                        optante_simples_nacional =
-                                            Some(String::from_utf8(e.to_vec()).unwrap())
-                                    }
-                                    b"IncentivadorCultural" => {
-                                        incentivador_cultural =
-                                            Some(String::from_utf8(e.to_vec()).unwrap())
-                                    }
-                                    b"OutrasInformacoes" => {
-                                        outras_informacoes =
-                                            Some(String::from_utf8(e.to_vec()).unwrap())
-                                    }
-                                    b"Servico" => match names_iter.next() {
-                                        Some(elem) => match elem.name().as_ref() {
-                                            b"CodigoTributacaoMunicipio" => {
-                                                codigo_tributacao_municipio =
-                                                    Some(String::from_utf8(e.to_vec()).unwrap())
-                                            }
-                                            b"Discriminacao" => {
-                                                discriminacao =
-                                                    Some(String::from_utf8(e.to_vec()).unwrap())
-                                            }
-                                            b"CodigoMunicipio" => {
-                                                codigo_municipio =
-                                                    Some(String::from_utf8(e.to_vec()).unwrap())
-                                            }
-                                            b"Valores" => match names_iter.next() {
-                                                Some(elem) => match elem.name().as_ref() {
-                                                    b"ValorServicos" => {
-                                                        valor_servicos = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Aliquota" => {
-                                                        aliquota_iss = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"ValorIss" => {
-                                                        valor_iss = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    _ => {}
-                                                },
-                                                None => {
-                                                    return Err(String::from("bad xml"));
-                                                }
-                                            },
-                                            _ => {}
-                                        },
-                                        None => {
-                                            return Err(String::from("bad xml"));
-                                        }
-                                    },
-                                    b"PrestadorServico" => match names_iter.next() {
-                                        Some(elem) => match elem.name().as_ref() {
-                                            b"RazaoSocial" => {
-                                                razao_social_prestador =
-                                                    Some(String::from_utf8(e.to_vec()).unwrap())
-                                            }
-                                            b"IdentificacaoPrestador" => match names_iter.next() {
-                                                Some(elem) => match elem.name().as_ref() {
-                                                    b"Cnpj" => {
-                                                        cnpj_prestador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"InscricaoMunicipal" => {
-                                                        inscricao_municipal_prestador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    _ => {}
-                                                },
-                                                None => {
-                                                    return Err(String::from("bad xml"));
-                                                }
-                                            },
-                                            b"Endereco" => match names_iter.next() {
-                                                Some(elem) => match elem.name().as_ref() {
-                                                    b"Endereco" => {
-                                                        logradouro_prestador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Numero" => {
-                                                        numero_prestador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Complemento" => {
-                                                        complemento_prestador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Bairro" => {
-                                                        bairro_prestador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"CodigoMunicipio" => {
-                                                        codigo_municipio_prestador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Uf" => {
-                                                        uf_prestador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Cep" => {
-                                                        cep_prestador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    _ => {}
-                                                },
-                                                None => {
-                                                    return Err(String::from("bad xml"));
-                                                }
-                                            },
-                                            _ => {}
-                                        },
-                                        None => {
-                                            return Err(String::from("bad xml"));
-                                        }
-                                    },
-                                    b"TomadorServico" => match names_iter.next() {
-                                        Some(elem) => match elem.name().as_ref() {
-                                            b"RazaoSocial" => {
-                                                razao_social_tomador =
-                                                    Some(String::from_utf8(e.to_vec()).unwrap())
-                                            }
-                                            b"IdentificacaoTomador" => match names_iter.next() {
-                                                Some(elem) => match elem.name().as_ref() {
-                                                    b"CpfCnpj" => match names_iter.next() {
-                                                        Some(elem) => match elem.name().as_ref() {
-                                                            b"Cnpj" => {
-                                                                cnpj_tomador = Some(
-                                                                    String::from_utf8(e.to_vec())
-                                                                        .unwrap(),
-                                                                )
-                                                            }
-                                                            _ => {}
-                                                        },
-                                                        None => {
-                                                            return Err(String::from("bad xml"));
-                                                        }
-                                                    },
-                                                    b"InscricaoMunicipal" => {
-                                                        inscricao_municipal_tomador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    _ => {}
-                                                },
-                                                None => {
-                                                    return Err(String::from("bad xml"));
-                                                }
-                                            },
-                                            b"Endereco" => match names_iter.next() {
-                                                Some(elem) => match elem.name().as_ref() {
-                                                    b"Endereco" => {
-                                                        logradouro_tomador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Numero" => {
-                                                        numero_tomador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Complemento" => {
-                                                        complemento_tomador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Bairro" => {
-                                                        bairro_tomador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"CodigoMunicipio" => {
-                                                        codigo_municipio_tomador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Uf" => {
-                                                        uf_tomador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    b"Cep" => {
-                                                        cep_tomador = Some(
-                                                            String::from_utf8(e.to_vec()).unwrap(),
-                                                        )
-                                                    }
-                                                    _ => {}
-                                                },
-                                                None => {
-                                                    return Err(String::from("bad xml"));
-                                                }
-                                            },
-                                            _ => {}
-                                        },
-                                        None => {
-                                            return Err(String::from("bad xml"));
-                                        }
-                                    },
-                                    _ => {}
-                                },
-                                None => {
-                                    return Err(String::from("bad xml"));
-                                }
-                            },
-                            _ => {}
-                        },
-                        None => {
-                            return Err(String::from("bad xml"));
-                        }
-                    };
-                    Ok(())
+                (_, Event::Start(e)) => declaracoes.push(Self::declaracoes(&e)?),
+                (_, Event::End(_)) => {
+                    declaracoes.pop();
                 }
-                Ok(Event::Eof) => break,
-                Ok(_) => Err(String::from("unexpected xml")),
-                Err(e) => Err(format!("error in xml: {e}")),
-            }?;
+                (_, Event::Eof) => break,
+                _ => {}
+            }
         }
 
-        let numero = numero.ok_or(String::from("missing numero"))?;
-        let codigo_verificacao =
-            codigo_verificacao.ok_or(String::from("missing codigo_verificacao"))?;
-        let data_emissao = data_emissao.ok_or(String::from("missing data_emissao"))?;
-        let competencia = competencia.ok_or(String::from("missing competencia"))?;
-        let natureza_operacao =
-            natureza_operacao.ok_or(String::from("missing natureza_operacao"))?;
-        let regime_especial_tributacao =
-            regime_especial_tributacao.ok_or(String::from("missing regime_especial_tributacao"))?;
-        let optante_simples_nacional =
-            optante_simples_nacional.ok_or(String::from("missing optante_simples_nacional"))?;
-        let incentivador_cultural =
-            incentivador_cultural.ok_or(String::from("missing incentivador_cultural"))?;
-        let outras_informacoes =
-            outras_informacoes.ok_or(String::from("missing outras_informacoes"))?;
-        let valor_servicos = valor_servicos.ok_or(String::from("missing valor_servicos"))?;
-        let codigo_tributacao_municipio = codigo_tributacao_municipio
-            .ok_or(String::from("missing codigo_tributacao_municipio"))?;
-        let discriminacao = discriminacao.ok_or(String::from("missing discriminacao"))?;
-        let codigo_municipio = codigo_municipio.ok_or(String::from("missing codigo_municipio"))?;
-        let cnpj_prestador = cnpj_prestador.ok_or(String::from("missing cnpj_prestador"))?;
-        let razao_social_prestador =
-            razao_social_prestador.ok_or(String::from("missing razao_social_prestador"))?;
-        let logradouro_prestador =
-            logradouro_prestador.ok_or(String::from("missing logradouro_prestador"))?;
-        let numero_prestador = numero_prestador.ok_or(String::from("missing numero_prestador"))?;
-        let bairro_prestador = bairro_prestador.ok_or(String::from("missing bairro_prestador"))?;
-        let codigo_municipio_prestador =
-            codigo_municipio_prestador.ok_or(String::from("missing codigo_municipio_prestador"))?;
-        let uf_prestador = uf_prestador.ok_or(String::from("missing uf_prestador"))?;
-        let cep_prestador = cep_prestador.ok_or(String::from("missing cep_prestador"))?;
-        let razao_social_tomador =
-            razao_social_tomador.ok_or(String::from("missing razao_social_tomador"))?;
-        let logradouro_tomador =
-            logradouro_tomador.ok_or(String::from("missing logradouro_tomador"))?;
-        let numero_tomador = numero_tomador.ok_or(String::from("missing numero_tomador"))?;
-        let bairro_tomador = bairro_tomador.ok_or(String::from("missing bairro_tomador"))?;
-        let codigo_municipio_tomador =
-            codigo_municipio_tomador.ok_or(String::from("missing codigo_municipio_tomador"))?;
-        let uf_tomador = uf_tomador.ok_or(String::from("missing uf_tomador"))?;
+        Ok(nfses)
+    }
+}
+
+impl Nfse {
+    // xmlns and xmlns:prefix attributes of an element
+    fn declaracoes(e: &BytesStart) -> Result<Vec<(String, String)>, Error> {
+        e.attributes()
+            .map(|attr| {
+                let attr = attr?;
+                let key = std::str::from_utf8(attr.key.as_ref())?.to_owned();
+                let value = attr.unescape_value()?.into_owned();
+                Ok((key, value))
+            })
+            .filter(|attr| {
+                attr.as_ref()
+                    .map_or(true, |(key, _)| key == "xmlns" || key.starts_with("xmlns:"))
+            })
+            .collect()
+    }
+}
+
+impl Nfse {
+    fn from_comp_nfse(comp_nfse: CompNfseXml) -> Result<Self, Error> {
+        let inf_nfse = comp_nfse.nfse.inf_nfse;
+        let servico = inf_nfse.servico;
+        let valores = servico.valores;
+        let prestador = inf_nfse.prestador_servico;
+        let tomador = inf_nfse.tomador_servico;
+        let intermediario = inf_nfse.intermediario_servico;
+
+        let numero = inf_nfse.numero.ok_or(Error::xml("missing numero"))?;
+        let codigo_verificacao = inf_nfse
+            .codigo_verificacao
+            .ok_or(Error::xml("missing codigo_verificacao"))?;
+        let data_emissao = inf_nfse
+            .data_emissao
+            .ok_or(Error::xml("missing data_emissao"))?;
+        let identificacao_rps = match inf_nfse.identificacao_rps {
+            IdentificacaoRpsXml {
+                numero: Some(numero),
+                serie: Some(serie),
+                tipo: Some(tipo),
+            } => Some(IdentificacaoRps {
+                numero,
+                serie,
+                tipo,
+            }),
+            IdentificacaoRpsXml {
+                numero: None,
+                serie: None,
+                tipo: None,
+            } => None,
+            _ => return Err(Error::xml("incomplete identificacao_rps")),
+        };
+        let competencia = inf_nfse
+            .competencia
+            .ok_or(Error::xml("missing competencia"))?;
+        let natureza_operacao = inf_nfse
+            .natureza_operacao
+            .ok_or(Error::xml("missing natureza_operacao"))?;
+        let optante_simples_nacional = inf_nfse
+            .optante_simples_nacional
+            .ok_or(Error::xml("missing optante_simples_nacional"))?;
+        let incentivador_cultural = inf_nfse
+            .incentivador_cultural
+            .ok_or(Error::xml("missing incentivador_cultural"))?;
+        let valor = |field: &str, value: Option<String>| {
+            value
+                .map(|it| {
+                    it.parse::<Valor>()
+                        .map_err(|_| Error::xml(format!("invalid {field}")))
+                })
+                .transpose()
+        };
+        let valor_servicos = valor("valor_servicos", valores.valor_servicos)?
+            .ok_or(Error::xml("missing valor_servicos"))?;
+        let aliquota_iss = valores
+            .aliquota
+            .map(|it| {
+                it.parse::<Aliquota>()
+                    .map_err(|_| Error::xml("invalid aliquota_iss"))
+            })
+            .transpose()?;
+        let valor_iss = valor("valor_iss", valores.valor_iss)?;
+        let valor_iss_retido = valor("valor_iss_retido", valores.valor_iss_retido)?;
+        let valor_deducoes = valor("valor_deducoes", valores.valor_deducoes)?;
+        let valor_pis = valor("valor_pis", valores.valor_pis)?;
+        let valor_cofins = valor("valor_cofins", valores.valor_cofins)?;
+        let valor_inss = valor("valor_inss", valores.valor_inss)?;
+        let valor_ir = valor("valor_ir", valores.valor_ir)?;
+        let valor_csll = valor("valor_csll", valores.valor_csll)?;
+        let outras_retencoes = valor("outras_retencoes", valores.outras_retencoes)?;
+        let base_calculo = valor("base_calculo", valores.base_calculo)?;
+        let valor_liquido_nfse = valor("valor_liquido_nfse", valores.valor_liquido_nfse)?;
+        let desconto_incondicionado =
+            valor("desconto_incondicionado", valores.desconto_incondicionado)?;
+        let desconto_condicionado = valor("desconto_condicionado", valores.desconto_condicionado)?;
+        let iss_retido = valores.iss_retido.ok_or(Error::xml("missing iss_retido"))?;
+        let discriminacao = servico
+            .discriminacao
+            .ok_or(Error::xml("missing discriminacao"))?;
+        let codigo_municipio = servico
+            .codigo_municipio
+            .ok_or(Error::xml("missing codigo_municipio"))?;
+        let cnpj_prestador = prestador
+            .identificacao_prestador
+            .cnpj
+            .ok_or(Error::xml("missing cnpj_prestador"))?;
+        let razao_social_prestador = prestador
+            .razao_social
+            .ok_or(Error::xml("missing razao_social_prestador"))?;
 
         Ok(Self {
             numero,
             codigo_verificacao,
             data_emissao,
+            identificacao_rps,
             competencia,
             natureza_operacao,
-            regime_especial_tributacao,
+            regime_especial_tributacao: inf_nfse.regime_especial_tributacao,
             optante_simples_nacional,
             incentivador_cultural,
-            outras_informacoes,
+            outras_informacoes: inf_nfse.outras_informacoes,
             valor_servicos,
             aliquota_iss,
             valor_iss,
-            codigo_tributacao_municipio,
+            iss_retido,
+            valor_iss_retido,
+            valor_deducoes,
+            valor_pis,
+            valor_cofins,
+            valor_inss,
+            valor_ir,
+            valor_csll,
+            outras_retencoes,
+            base_calculo,
+            valor_liquido_nfse,
+            desconto_incondicionado,
+            desconto_condicionado,
+            codigo_tributacao_municipio: servico.codigo_tributacao_municipio,
             discriminacao,
             codigo_municipio,
             cnpj_prestador,
-            inscricao_municipal_prestador,
+            inscricao_municipal_prestador: prestador.identificacao_prestador.inscricao_municipal,
             razao_social_prestador,
-            logradouro_prestador,
-            numero_prestador,
-            complemento_prestador,
-            bairro_prestador,
-            codigo_municipio_prestador,
-            uf_prestador,
-            cep_prestador,
-            cnpj_tomador,
-            inscricao_municipal_tomador,
-            razao_social_tomador,
-            logradouro_tomador,
-            numero_tomador,
-            complemento_tomador,
-            bairro_tomador,
-            codigo_municipio_tomador,
-            uf_tomador,
-            cep_tomador,
+            logradouro_prestador: prestador.endereco.endereco,
+            numero_prestador: prestador.endereco.numero,
+            complemento_prestador: prestador.endereco.complemento,
+            bairro_prestador: prestador.endereco.bairro,
+            codigo_municipio_prestador: prestador.endereco.codigo_municipio,
+            uf_prestador: prestador.endereco.uf,
+            cep_prestador: prestador.endereco.cep,
+            cpf_tomador: tomador.identificacao_tomador.cpf_cnpj.cpf,
+            cnpj_tomador: tomador.identificacao_tomador.cpf_cnpj.cnpj,
+            inscricao_municipal_tomador: tomador.identificacao_tomador.inscricao_municipal,
+            razao_social_tomador: tomador.razao_social,
+            logradouro_tomador: tomador.endereco.endereco,
+            numero_tomador: tomador.endereco.numero,
+            complemento_tomador: tomador.endereco.complemento,
+            bairro_tomador: tomador.endereco.bairro,
+            codigo_municipio_tomador: tomador.endereco.codigo_municipio,
+            uf_tomador: tomador.endereco.uf,
+            cep_tomador: tomador.endereco.cep,
+            telefone_tomador: tomador.contato.telefone,
+            email_tomador: tomador.contato.email,
+            razao_social_intermediario: intermediario.razao_social,
+            cpf_intermediario: intermediario.cpf_cnpj.cpf,
+            cnpj_intermediario: intermediario.cpf_cnpj.cnpj,
+            inscricao_municipal_intermediario: intermediario.inscricao_municipal,
+            codigo_obra: inf_nfse.construcao_civil.codigo_obra,
+            art: inf_nfse.construcao_civil.art,
+            nfse_substituida: inf_nfse.nfse_substituida,
+            nfse_substituidora: comp_nfse
+                .nfse_substituicao
+                .substituicao_nfse
+                .nfse_substituidora,
         })
     }
 }
 
+impl Nfse {
+    // every CompNfse in the response, e.g. the ListaNfse of a lote, along with its own xml
+    pub fn from_resposta(resposta: &Resposta) -> Result<Vec<(Self, String)>, Error> {
+        Self::lista_from_xml_string(resposta.xml())
+    }
+}
+
+impl Nfse {
+    pub fn numero(&self) -> &str {
+        self.numero.as_str()
+    }
+}
+
+impl Nfse {
+    // the rps that generated the note, missing in notes issued before BHISS returned it
+    pub fn identificacao_rps(&self) -> Option<&IdentificacaoRps> {
+        self.identificacao_rps.as_ref()
+    }
+}
+
+impl Nfse {
+    // access key of the note in the Ambiente de Dados Nacional, informed by BHISS in
+    // OutrasInformacoes, which gives its DANFSe; the key ends the sentence or the text
+    pub fn chave_acesso(&self) -> Option<&str> {
+        let (_, chave) = self
+            .outras_informacoes
+            .as_deref()?
+            .split_once("Chave de acesso no Ambiente de Dados Nacional: ")?;
+        let (chave, resto) = (chave.get(..50)?, chave.get(50..)?);
+        (chave.bytes().all(|c| c.is_ascii_alphanumeric())
+            && (resto.is_empty() || resto.starts_with('.')))
+        .then_some(chave)
+    }
+}
+
+impl Nfse {
+    // number of the note this one replaced
+    pub fn nfse_substituida(&self) -> Option<&str> {
+        self.nfse_substituida.as_deref()
+    }
+}
+
+impl Nfse {
+    // number of the note that replaced this one
+    pub fn nfse_substituidora(&self) -> Option<&str> {
+        self.nfse_substituidora.as_deref()
+    }
+}
+
 impl Nfse {
     pub fn uniquely_identify(&self) -> String {
         format!(
             "{}|{}|{}",
-            self.razao_social_tomador.clone(),
+            self.razao_social_tomador.clone().unwrap_or_default(),
             self.discriminacao.clone(),
-            self.valor_servicos.clone()
+            self.valor_servicos
         )
     }
 }
@@ -459,6 +543,7 @@ impl Nfse {
 #[cfg(test)]
 mod tests {
     use crate::nfse::Nfse;
+    use crate::rps::IdentificacaoRps;
 
     #[test]
     fn should_create_nfse_from_xml() {
@@ -470,40 +555,65 @@ mod tests {
                 numero: String::from("12345"),
                 codigo_verificacao: String::from("67890"),
                 data_emissao: String::from("2020-01-01T01:02:03"),
+                identificacao_rps: None,
                 competencia: String::from("2020-01-01T00:00:00"),
                 natureza_operacao: String::from("3"),
-                regime_especial_tributacao: String::from("6"),
+                regime_especial_tributacao: Some(String::from("6")),
                 optante_simples_nacional: String::from("1"),
                 incentivador_cultural: String::from("2"),
-                outras_informacoes: String::from(
+                outras_informacoes: Some(String::from(
                     "NFS-e gerada em ambiente de teste. NÃO TEM VALOR JURÍDICO NEM FISCAL."
-                ),
-                valor_servicos: String::from("95.31"),
-                aliquota_iss: Some(String::from("0.0217")),
-                valor_iss: Some(String::from("2.07")),
-                codigo_tributacao_municipio: String::from("10400188"),
+                )),
+                valor_servicos: "95.31".parse().unwrap(),
+                aliquota_iss: Some("0.0217".parse().unwrap()),
+                valor_iss: Some("2.07".parse().unwrap()),
+                iss_retido: String::from("1"),
+                valor_iss_retido: None,
+                valor_deducoes: None,
+                valor_pis: None,
+                valor_cofins: None,
+                valor_inss: None,
+                valor_ir: None,
+                valor_csll: None,
+                outras_retencoes: None,
+                base_calculo: Some("95.31".parse().unwrap()),
+                valor_liquido_nfse: Some("95.31".parse().unwrap()),
+                desconto_incondicionado: None,
+                desconto_condicionado: None,
+                codigo_tributacao_municipio: Some(String::from("10400188")),
                 discriminacao: String::from("Consultoria em desenvolvimento de software"),
                 codigo_municipio: String::from("3106200"),
                 cnpj_prestador: String::from("12345678000190"),
                 inscricao_municipal_prestador: Some(String::from("12345670018")),
                 razao_social_prestador: String::from("NOME DA EMPRESA"),
-                logradouro_prestador: String::from("RUA DO PRESTADOR"),
-                numero_prestador: String::from("12"),
+                logradouro_prestador: Some(String::from("RUA DO PRESTADOR")),
+                numero_prestador: Some(String::from("12")),
                 complemento_prestador: Some(String::from("SALA 01")),
-                bairro_prestador: String::from("Bairro Um"),
-                codigo_municipio_prestador: String::from("3106200"),
-                uf_prestador: String::from("MG"),
-                cep_prestador: String::from("34567890"),
+                bairro_prestador: Some(String::from("Bairro Um")),
+                codigo_municipio_prestador: Some(String::from("3106200")),
+                uf_prestador: Some(String::from("MG")),
+                cep_prestador: Some(String::from("34567890")),
+                cpf_tomador: None,
                 cnpj_tomador: Some(String::from("12345678000290")),
                 inscricao_municipal_tomador: Some(String::from("12345670019")),
-                razao_social_tomador: String::from("NOME DO TOMADOR"),
-                logradouro_tomador: String::from("RUA DO TOMADOR"),
-                numero_tomador: String::from("34"),
+                razao_social_tomador: Some(String::from("NOME DO TOMADOR")),
+                logradouro_tomador: Some(String::from("RUA DO TOMADOR")),
+                numero_tomador: Some(String::from("34")),
                 complemento_tomador: Some(String::from("SALA 02")),
-                bairro_tomador: String::from("Bairro Dois"),
-                codigo_municipio_tomador: String::from("3106200"),
-                uf_tomador: String::from("MG"),
+                bairro_tomador: Some(String::from("Bairro Dois")),
+                codigo_municipio_tomador: Some(String::from("3106200")),
+                uf_tomador: Some(String::from("MG")),
                 cep_tomador: Some(String::from("34567891")),
+                telefone_tomador: None,
+                email_tomador: None,
+                razao_social_intermediario: None,
+                cpf_intermediario: None,
+                cnpj_intermediario: None,
+                inscricao_municipal_intermediario: None,
+                codigo_obra: None,
+                art: None,
+                nfse_substituida: None,
+                nfse_substituidora: None,
             }
         );
     }
@@ -518,41 +628,293 @@ mod tests {
                 numero: String::from("12345"),
                 codigo_verificacao: String::from("67890"),
                 data_emissao: String::from("2020-01-01T01:02:03"),
+                identificacao_rps: None,
                 competencia: String::from("2020-01-01T00:00:00"),
                 natureza_operacao: String::from("3"),
-                regime_especial_tributacao: String::from("6"),
+                regime_especial_tributacao: Some(String::from("6")),
                 optante_simples_nacional: String::from("1"),
                 incentivador_cultural: String::from("2"),
-                outras_informacoes: String::from(
+                outras_informacoes: Some(String::from(
                     "NFS-e gerada em ambiente de teste. NÃO TEM VALOR JURÍDICO NEM FISCAL."
-                ),
-                valor_servicos: String::from("95.31"),
+                )),
+                valor_servicos: "95.31".parse().unwrap(),
                 aliquota_iss: None,
                 valor_iss: None,
-                codigo_tributacao_municipio: String::from("10400188"),
+                iss_retido: String::from("2"),
+                valor_iss_retido: None,
+                valor_deducoes: None,
+                valor_pis: None,
+                valor_cofins: None,
+                valor_inss: None,
+                valor_ir: None,
+                valor_csll: None,
+                outras_retencoes: None,
+                base_calculo: Some("95.31".parse().unwrap()),
+                valor_liquido_nfse: Some("95.31".parse().unwrap()),
+                desconto_incondicionado: None,
+                desconto_condicionado: None,
+                codigo_tributacao_municipio: Some(String::from("10400188")),
                 discriminacao: String::from("Consultoria em desenvolvimento de software"),
                 codigo_municipio: String::from("3106200"),
                 cnpj_prestador: String::from("12345678000190"),
                 inscricao_municipal_prestador: Some(String::from("12345670018")),
                 razao_social_prestador: String::from("NOME DA EMPRESA"),
-                logradouro_prestador: String::from("RUA DO PRESTADOR"),
-                numero_prestador: String::from("12"),
+                logradouro_prestador: Some(String::from("RUA DO PRESTADOR")),
+                numero_prestador: Some(String::from("12")),
                 complemento_prestador: Some(String::from("SALA 01")),
-                bairro_prestador: String::from("Bairro Um"),
-                codigo_municipio_prestador: String::from("3106200"),
-                uf_prestador: String::from("MG"),
-                cep_prestador: String::from("34567890"),
+                bairro_prestador: Some(String::from("Bairro Um")),
+                codigo_municipio_prestador: Some(String::from("3106200")),
+                uf_prestador: Some(String::from("MG")),
+                cep_prestador: Some(String::from("34567890")),
+                cpf_tomador: None,
                 cnpj_tomador: Some(String::from("12345678000290")),
                 inscricao_municipal_tomador: Some(String::from("12345670019")),
-                razao_social_tomador: String::from("NOME DO TOMADOR"),
-                logradouro_tomador: String::from("RUA DO TOMADOR"),
-                numero_tomador: String::from("34"),
+                razao_social_tomador: Some(String::from("NOME DO TOMADOR")),
+                logradouro_tomador: Some(String::from("RUA DO TOMADOR")),
+                numero_tomador: Some(String::from("34")),
                 complemento_tomador: Some(String::from("SALA 02")),
-                bairro_tomador: String::from("Bairro Dois"),
-                codigo_municipio_tomador: String::from("3106200"),
-                uf_tomador: String::from("MG"),
+                bairro_tomador: Some(String::from("Bairro Dois")),
+                codigo_municipio_tomador: Some(String::from("3106200")),
+                uf_tomador: Some(String::from("MG")),
                 cep_tomador: Some(String::from("34567891")),
+                telefone_tomador: None,
+                email_tomador: None,
+                razao_social_intermediario: None,
+                cpf_intermediario: None,
+                cnpj_intermediario: None,
+                inscricao_municipal_intermediario: None,
+                codigo_obra: None,
+                art: None,
+                nfse_substituida: None,
+                nfse_substituidora: None,
             }
         );
     }
+
+    #[test]
+    fn should_create_nfse_from_minimal_xml() {
+        let example = r##"<CompNfse xmlns="http://www.abrasf.org.br/nfse.xsd"><Nfse><InfNfse><Numero>12349</Numero><CodigoVerificacao>67890</CodigoVerificacao><DataEmissao>2020-01-01T01:02:03</DataEmissao><NaturezaOperacao>1</NaturezaOperacao><OptanteSimplesNacional>2</OptanteSimplesNacional><IncentivadorCultural>2</IncentivadorCultural><Competencia>2020-01-01T00:00:00</Competencia><Servico><Valores><ValorServicos>95.31</ValorServicos><IssRetido>2</IssRetido></Valores><ItemListaServico>1.04</ItemListaServico><Discriminacao>Consultoria</Discriminacao><CodigoMunicipio>3106200</CodigoMunicipio></Servico><PrestadorServico><IdentificacaoPrestador><Cnpj>12345678000190</Cnpj></IdentificacaoPrestador><RazaoSocial>NOME DA EMPRESA</RazaoSocial><Endereco/></PrestadorServico><TomadorServico/><OrgaoGerador><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf></OrgaoGerador></InfNfse></Nfse></CompNfse>"##;
+
+        let nfse = Nfse::from_xml_string(example).unwrap();
+
+        assert_eq!(nfse.numero(), "12349");
+        assert_eq!(nfse.regime_especial_tributacao, None);
+        assert_eq!(nfse.outras_informacoes, None);
+        assert_eq!(nfse.codigo_tributacao_municipio, None);
+        assert_eq!(nfse.logradouro_prestador, None);
+        assert_eq!(nfse.cep_prestador, None);
+        assert_eq!(nfse.razao_social_tomador, None);
+        assert_eq!(nfse.chave_acesso(), None);
+        assert_eq!(nfse.uniquely_identify(), "|Consultoria|95.31");
+
+        let chave_acesso = |outras_informacoes: &str| {
+            Nfse::from_xml_string(&example.replace(
+                "<Servico>",
+                &format!("<OutrasInformacoes>{outras_informacoes}</OutrasInformacoes><Servico>"),
+            ))
+            .unwrap()
+            .chave_acesso()
+            .map(str::to_owned)
+        };
+        let chave = "31062002212345678000190000000000000124030000000101";
+
+        assert_eq!(
+            chave_acesso(&format!(
+                "Chave de acesso no Ambiente de Dados Nacional: {chave}"
+            )),
+            Some(String::from(chave))
+        );
+        assert_eq!(
+            chave_acesso(&format!(
+                "Chave de acesso no Ambiente de Dados Nacional: {chave}. Teste"
+            )),
+            Some(String::from(chave))
+        );
+        assert_eq!(
+            chave_acesso(&format!(
+                "Chave de acesso no Ambiente de Dados Nacional: {chave}1."
+            )),
+            None
+        );
+        assert_eq!(
+            chave_acesso(&format!(
+                "Chave de acesso no Ambiente de Dados Nacional: {chave} Teste"
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn should_create_nfse_from_xml_with_substituicao() {
+        let example = r##"<?xml version='1.0' encoding='UTF-8'?><CompNfse xmlns="http://www.abrasf.org.br/nfse.xsd"><Nfse xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><InfNfse Id="nfse"><Numero>12346</Numero><CodigoVerificacao>67890</CodigoVerificacao><DataEmissao>2020-01-01T01:02:03</DataEmissao><NaturezaOperacao>3</NaturezaOperacao><RegimeEspecialTributacao>6</RegimeEspecialTributacao><OptanteSimplesNacional>1</OptanteSimplesNacional><IncentivadorCultural>2</IncentivadorCultural><Competencia>2020-01-01T00:00:00</Competencia><NfseSubstituida>12345</NfseSubstituida><OutrasInformacoes>Teste</OutrasInformacoes><Servico><Valores><ValorServicos>95.31</ValorServicos><IssRetido>2</IssRetido><BaseCalculo>95.31</BaseCalculo><ValorLiquidoNfse>95.31</ValorLiquidoNfse></Valores><ItemListaServico>1.04</ItemListaServico><CodigoTributacaoMunicipio>10400188</CodigoTributacaoMunicipio><Discriminacao>Consultoria</Discriminacao><CodigoMunicipio>3106200</CodigoMunicipio></Servico><PrestadorServico><IdentificacaoPrestador><Cnpj>12345678000190</Cnpj></IdentificacaoPrestador><RazaoSocial>NOME DA EMPRESA</RazaoSocial><Endereco><Endereco>RUA DO PRESTADOR</Endereco><Numero>12</Numero><Bairro>Bairro Um</Bairro><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf><Cep>34567890</Cep></Endereco></PrestadorServico><TomadorServico><RazaoSocial>NOME DO TOMADOR</RazaoSocial><Endereco><Endereco>RUA DO TOMADOR</Endereco><Numero>34</Numero><Bairro>Bairro Dois</Bairro><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf></Endereco></TomadorServico></InfNfse></Nfse><NfseSubstituicao><SubstituicaoNfse Id="substituicao"><NfseSubstituidora>12347</NfseSubstituidora></SubstituicaoNfse></NfseSubstituicao></CompNfse>"##;
+
+        let nfse = Nfse::from_xml_string(example).unwrap();
+
+        assert_eq!(nfse.nfse_substituida(), Some("12345"));
+        assert_eq!(nfse.nfse_substituidora(), Some("12347"));
+    }
+
+    #[test]
+    fn should_create_nfse_from_xml_with_identificacao_rps() {
+        let example = r##"<?xml version='1.0' encoding='UTF-8'?><CompNfse xmlns="http://www.abrasf.org.br/nfse.xsd"><Nfse xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><InfNfse Id="nfse"><Numero>12347</Numero><CodigoVerificacao>67890</CodigoVerificacao><DataEmissao>2020-01-01T01:02:03</DataEmissao><IdentificacaoRps><Numero>42</Numero><Serie>A1</Serie><Tipo>1</Tipo></IdentificacaoRps><DataEmissaoRps>2020-01-01</DataEmissaoRps><NaturezaOperacao>1</NaturezaOperacao><OptanteSimplesNacional>2</OptanteSimplesNacional><IncentivadorCultural>2</IncentivadorCultural><Competencia>2020-01-01T00:00:00</Competencia><Servico><Valores><ValorServicos>95.31</ValorServicos><IssRetido>2</IssRetido></Valores><ItemListaServico>1.04</ItemListaServico><Discriminacao>Consultoria</Discriminacao><CodigoMunicipio>3106200</CodigoMunicipio></Servico><PrestadorServico><IdentificacaoPrestador><Cnpj>12345678000190</Cnpj></IdentificacaoPrestador><RazaoSocial>NOME DA EMPRESA</RazaoSocial><Endereco/></PrestadorServico><TomadorServico/></InfNfse></Nfse></CompNfse>"##;
+
+        let nfse = Nfse::from_xml_string(example).unwrap();
+
+        assert_eq!(
+            nfse.identificacao_rps(),
+            Some(&IdentificacaoRps {
+                numero: String::from("42"),
+                serie: String::from("A1"),
+                tipo: String::from("1"),
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_nfse_from_xml_with_tomador_pessoa_fisica() {
+        let example = r##"<?xml version='1.0' encoding='UTF-8'?><CompNfse xmlns="http://www.abrasf.org.br/nfse.xsd"><Nfse xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><InfNfse Id="nfse"><Numero>12348</Numero><CodigoVerificacao>67890</CodigoVerificacao><DataEmissao>2020-01-01T01:02:03</DataEmissao><NaturezaOperacao>1</NaturezaOperacao><RegimeEspecialTributacao>6</RegimeEspecialTributacao><OptanteSimplesNacional>1</OptanteSimplesNacional><IncentivadorCultural>2</IncentivadorCultural><Competencia>2020-01-01T00:00:00</Competencia><OutrasInformacoes>Teste</OutrasInformacoes><Servico><Valores><ValorServicos>95.31</ValorServicos><IssRetido>2</IssRetido><BaseCalculo>95.31</BaseCalculo><ValorLiquidoNfse>95.31</ValorLiquidoNfse></Valores><ItemListaServico>1.04</ItemListaServico><CodigoTributacaoMunicipio>10400188</CodigoTributacaoMunicipio><Discriminacao>Consultoria</Discriminacao><CodigoMunicipio>3106200</CodigoMunicipio></Servico><PrestadorServico><IdentificacaoPrestador><Cnpj>12345678000190</Cnpj></IdentificacaoPrestador><RazaoSocial>NOME DA EMPRESA</RazaoSocial><Endereco><Endereco>RUA DO PRESTADOR</Endereco><Numero>12</Numero><Bairro>Bairro Um</Bairro><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf><Cep>34567890</Cep></Endereco></PrestadorServico><TomadorServico><IdentificacaoTomador><CpfCnpj><Cpf>12345678909</Cpf></CpfCnpj></IdentificacaoTomador><RazaoSocial>NOME DO TOMADOR</RazaoSocial><Contato><Telefone>31999999999</Telefone><Email>tomador@example.com</Email></Contato></TomadorServico></InfNfse></Nfse></CompNfse>"##;
+
+        let nfse = Nfse::from_xml_string(example).unwrap();
+
+        assert_eq!(nfse.cpf_tomador, Some(String::from("12345678909")));
+        assert_eq!(nfse.cnpj_tomador, None);
+        assert_eq!(nfse.logradouro_tomador, None);
+        assert_eq!(nfse.telefone_tomador, Some(String::from("31999999999")));
+        assert_eq!(
+            nfse.email_tomador,
+            Some(String::from("tomador@example.com"))
+        );
+    }
+
+    #[test]
+    fn should_create_nfse_from_xml_with_retencoes() {
+        let example = r##"<?xml version='1.0' encoding='UTF-8'?><CompNfse xmlns="http://www.abrasf.org.br/nfse.xsd"><Nfse xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><InfNfse Id="nfse"><Numero>12349</Numero><CodigoVerificacao>67890</CodigoVerificacao><DataEmissao>2020-01-01T01:02:03</DataEmissao><NaturezaOperacao>1</NaturezaOperacao><RegimeEspecialTributacao>6</RegimeEspecialTributacao><OptanteSimplesNacional>1</OptanteSimplesNacional><IncentivadorCultural>2</IncentivadorCultural><Competencia>2020-01-01T00:00:00</Competencia><OutrasInformacoes>Teste</OutrasInformacoes><Servico><Valores><ValorServicos>1000.00</ValorServicos><ValorDeducoes>100.00</ValorDeducoes><ValorPis>6.50</ValorPis><ValorCofins>30.00</ValorCofins><ValorInss>110.00</ValorInss><ValorIr>15.00</ValorIr><ValorCsll>10.00</ValorCsll><IssRetido>1</IssRetido><ValorIss>17.00</ValorIss><ValorIssRetido>17.00</ValorIssRetido><OutrasRetencoes>5.00</OutrasRetencoes><BaseCalculo>850.00</BaseCalculo><Aliquota>0.02</Aliquota><ValorLiquidoNfse>736.50</ValorLiquidoNfse><DescontoIncondicionado>50.00</DescontoIncondicionado><DescontoCondicionado>20.00</DescontoCondicionado></Valores><ItemListaServico>1.04</ItemListaServico><CodigoTributacaoMunicipio>10400188</CodigoTributacaoMunicipio><Discriminacao>Consultoria</Discriminacao><CodigoMunicipio>3106200</CodigoMunicipio></Servico><PrestadorServico><IdentificacaoPrestador><Cnpj>12345678000190</Cnpj></IdentificacaoPrestador><RazaoSocial>NOME DA EMPRESA</RazaoSocial><Endereco><Endereco>RUA DO PRESTADOR</Endereco><Numero>12</Numero><Bairro>Bairro Um</Bairro><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf><Cep>34567890</Cep></Endereco></PrestadorServico><TomadorServico><RazaoSocial>NOME DO TOMADOR</RazaoSocial></TomadorServico></InfNfse></Nfse></CompNfse>"##;
+
+        let nfse = Nfse::from_xml_string(example).unwrap();
+
+        assert_eq!(nfse.iss_retido, "1");
+        assert_eq!(nfse.valor_iss_retido, Some("17.00".parse().unwrap()));
+        assert_eq!(nfse.valor_deducoes, Some("100.00".parse().unwrap()));
+        assert_eq!(nfse.valor_pis, Some("6.50".parse().unwrap()));
+        assert_eq!(nfse.valor_cofins, Some("30.00".parse().unwrap()));
+        assert_eq!(nfse.valor_inss, Some("110.00".parse().unwrap()));
+        assert_eq!(nfse.valor_ir, Some("15.00".parse().unwrap()));
+        assert_eq!(nfse.valor_csll, Some("10.00".parse().unwrap()));
+        assert_eq!(nfse.outras_retencoes, Some("5.00".parse().unwrap()));
+        assert_eq!(nfse.base_calculo, Some("850.00".parse().unwrap()));
+        assert_eq!(nfse.valor_liquido_nfse, Some("736.50".parse().unwrap()));
+        assert_eq!(nfse.desconto_incondicionado, Some("50.00".parse().unwrap()));
+        assert_eq!(nfse.desconto_condicionado, Some("20.00".parse().unwrap()));
+    }
+
+    #[test]
+    fn should_create_nfse_from_xml_with_intermediario_and_construcao_civil() {
+        let example = r##"<?xml version='1.0' encoding='UTF-8'?><CompNfse xmlns="http://www.abrasf.org.br/nfse.xsd"><Nfse xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><InfNfse Id="nfse"><Numero>12350</Numero><CodigoVerificacao>67890</CodigoVerificacao><DataEmissao>2020-01-01T01:02:03</DataEmissao><NaturezaOperacao>1</NaturezaOperacao><RegimeEspecialTributacao>6</RegimeEspecialTributacao><OptanteSimplesNacional>1</OptanteSimplesNacional><IncentivadorCultural>2</IncentivadorCultural><Competencia>2020-01-01T00:00:00</Competencia><OutrasInformacoes>Teste</OutrasInformacoes><Servico><Valores><ValorServicos>95.31</ValorServicos><IssRetido>2</IssRetido><BaseCalculo>95.31</BaseCalculo><ValorLiquidoNfse>95.31</ValorLiquidoNfse></Valores><ItemListaServico>7.02</ItemListaServico><CodigoTributacaoMunicipio>70200188</CodigoTributacaoMunicipio><Discriminacao>Obra</Discriminacao><CodigoMunicipio>3106200</CodigoMunicipio></Servico><PrestadorServico><IdentificacaoPrestador><Cnpj>12345678000190</Cnpj></IdentificacaoPrestador><RazaoSocial>NOME DA EMPRESA</RazaoSocial><Endereco><Endereco>RUA DO PRESTADOR</Endereco><Numero>12</Numero><Bairro>Bairro Um</Bairro><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf><Cep>34567890</Cep></Endereco></PrestadorServico><TomadorServico><RazaoSocial>NOME DO TOMADOR</RazaoSocial></TomadorServico><IntermediarioServico><RazaoSocial>NOME DO INTERMEDIARIO</RazaoSocial><CpfCnpj><Cnpj>12345678000370</Cnpj></CpfCnpj><InscricaoMunicipal>12345670027</InscricaoMunicipal></IntermediarioServico><OrgaoGerador><CodigoMunicipio>3106200</CodigoMunicipio><Uf>MG</Uf></OrgaoGerador><ConstrucaoCivil><CodigoObra>OBRA-1</CodigoObra><Art>ART-2</Art></ConstrucaoCivil></InfNfse></Nfse></CompNfse>"##;
+
+        let nfse = Nfse::from_xml_string(example).unwrap();
+
+        assert_eq!(
+            nfse.razao_social_intermediario,
+            Some(String::from("NOME DO INTERMEDIARIO"))
+        );
+        assert_eq!(nfse.cpf_intermediario, None);
+        assert_eq!(
+            nfse.cnpj_intermediario,
+            Some(String::from("12345678000370"))
+        );
+        assert_eq!(
+            nfse.inscricao_municipal_intermediario,
+            Some(String::from("12345670027"))
+        );
+        assert_eq!(nfse.codigo_obra, Some(String::from("OBRA-1")));
+        assert_eq!(nfse.art, Some(String::from("ART-2")));
+    }
+
+    #[test]
+    fn should_read_every_comp_nfse_regardless_of_prefixes_and_formatting() {
+        let comp_nfse = |numero: &str| {
+            format!(
+                r##"
+  <n:CompNfse>
+    <n:Nfse versao="1.00">
+      <n:InfNfse Id="nfse">
+        <n:Numero>{numero}</n:Numero>
+        <n:CodigoVerificacao>67890</n:CodigoVerificacao>
+        <n:DataEmissao>2024-03-05T01:02:03</n:DataEmissao>
+        <n:IdentificacaoRps><n:Numero>{numero}</n:Numero><n:Serie>1</n:Serie><n:Tipo>1</n:Tipo></n:IdentificacaoRps>
+        <n:NaturezaOperacao>1</n:NaturezaOperacao>
+        <n:RegimeEspecialTributacao>6</n:RegimeEspecialTributacao>
+        <n:OptanteSimplesNacional>1</n:OptanteSimplesNacional>
+        <n:IncentivadorCultural>2</n:IncentivadorCultural>
+        <n:Competencia>2024-03-01T00:00:00</n:Competencia>
+        <n:OutrasInformacoes>Chave de acesso no Ambiente de Dados Nacional: 31062002212345678000190000000000000124030000000{numero}.</n:OutrasInformacoes>
+        <n:Servico>
+          <n:Valores><n:ValorServicos>95.31</n:ValorServicos><n:IssRetido>2</n:IssRetido></n:Valores>
+          <n:ItemListaServico>1.04</n:ItemListaServico>
+          <n:CodigoTributacaoMunicipio>10400188</n:CodigoTributacaoMunicipio>
+          <n:Discriminacao>Consultoria &amp; suporte</n:Discriminacao>
+          <n:CodigoMunicipio>3106200</n:CodigoMunicipio>
+        </n:Servico>
+        <n:PrestadorServico>
+          <n:IdentificacaoPrestador><n:Cnpj>12345678000190</n:Cnpj></n:IdentificacaoPrestador>
+          <n:RazaoSocial>NOME DA EMPRESA</n:RazaoSocial>
+          <n:Endereco><n:Endereco>RUA DO PRESTADOR</n:Endereco><n:Numero>12</n:Numero><n:Bairro>Bairro Um</n:Bairro><n:CodigoMunicipio>3106200</n:CodigoMunicipio><n:Uf>MG</n:Uf><n:Cep>34567890</n:Cep></n:Endereco>
+        </n:PrestadorServico>
+        <n:TomadorServico><n:RazaoSocial>NOME DO TOMADOR</n:RazaoSocial></n:TomadorServico>
+      </n:InfNfse>
+    </n:Nfse>
+  </n:CompNfse>"##
+            )
+        };
+
+        let xml = format!(
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<n:ConsultarLoteRpsResposta xmlns:n="http://www.abrasf.org.br/nfse.xsd">
+ <n:ListaNfse>{}{}
+ </n:ListaNfse>
+</n:ConsultarLoteRpsResposta>"##,
+            comp_nfse("101"),
+            comp_nfse("102")
+        );
+
+        let nfses = Nfse::lista_from_xml_string(&xml).unwrap();
+
+        assert_eq!(
+            nfses
+                .iter()
+                .map(|(nfse, _)| (
+                    nfse.numero(),
+                    nfse.identificacao_rps().map(|it| it.numero.as_str()),
+                    nfse.discriminacao.as_str(),
+                    nfse.chave_acesso(),
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "101",
+                    Some("101"),
+                    "Consultoria & suporte",
+                    Some("31062002212345678000190000000000000124030000000101")
+                ),
+                (
+                    "102",
+                    Some("102"),
+                    "Consultoria & suporte",
+                    Some("31062002212345678000190000000000000124030000000102")
+                ),
+            ]
+        );
+
+        // each xml is a document of its own, with the prefix it inherited declared
+        let (_, xml) = &nfses[0];
+        assert!(xml.starts_with(
+            r##"<?xml version='1.0' encoding='UTF-8'?><n:CompNfse xmlns:n="http://www.abrasf.org.br/nfse.xsd">"##
+        ));
+        assert_eq!(Nfse::from_xml_string(xml).unwrap(), nfses[0].0);
+
+        // elements of another namespace are not notes
+        assert!(Nfse::lista_from_xml_string(&xml.replace("abrasf", "outro"))
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::Error;
+
+const TENTATIVAS_TRAVA: u32 = 100;
+const INTERVALO_TRAVA: std::time::Duration = std::time::Duration::from_millis(100);
+
+// numbers of a serie: the last one allocated, the ones allocated to notes that were not confirmed
+// as sent yet, and the last one sent for each nome_arquivo
+#[derive(Serialize, Deserialize, Default, PartialEq, Debug)]
+struct Serie {
+    ultimo_numero: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pendentes: BTreeMap<String, u64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    emitidos: BTreeMap<String, u64>,
+}
+
+// numbers of a prestador: the last NumeroLote and the rps numbers by serie
+#[derive(Serialize, Deserialize, Default, PartialEq, Debug)]
+struct Prestador {
+    #[serde(default)]
+    ultimo_lote: u64,
+    #[serde(default)]
+    series: BTreeMap<String, Serie>,
+}
+
+// prestadores by cnpj
+type Estado = BTreeMap<String, Prestador>;
+
+// sequential rps numbers per prestador and serie, and lote numbers per prestador, kept in a yaml
+// file between runs; a lock file next to it keeps concurrent runs from allocating the same number
+pub struct NumeracaoRps {
+    arquivo: PathBuf,
+}
+
+impl NumeracaoRps {
+    pub fn new(arquivo: impl Into<PathBuf>) -> Self {
+        Self {
+            arquivo: arquivo.into(),
+        }
+    }
+}
+
+impl NumeracaoRps {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping) -> Result<Self, Error> {
+        let arquivo = match yaml.get("arquivo_numeracao") {
+            Some(serde_yaml::Value::String(it)) => Ok(it.clone()),
+            Some(_) => Err(Error::input("arquivo_numeracao", "invalid value")),
+            None => Ok(String::from("numeracao_rps.yml")),
+        }?;
+
+        Ok(Self::new(arquivo))
+    }
+}
+
+struct Trava {
+    arquivo: PathBuf,
+}
+
+impl Drop for Trava {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.arquivo);
+    }
+}
+
+impl NumeracaoRps {
+    fn arquivo_com_sufixo(&self, sufixo: &str) -> PathBuf {
+        let mut arquivo = self.arquivo.clone().into_os_string();
+        arquivo.push(sufixo);
+        arquivo.into()
+    }
+}
+
+impl NumeracaoRps {
+    fn travar(&self) -> Result<Trava, Error> {
+        let arquivo = self.arquivo_com_sufixo(".lock");
+
+        for _ in 0..TENTATIVAS_TRAVA {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&arquivo)
+            {
+                Ok(_) => return Ok(Trava { arquivo }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    std::thread::sleep(INTERVALO_TRAVA)
+                }
+                Err(e) => {
+                    return Err(Error::state("unable to create lock file").with_source(e));
+                }
+            }
+        }
+
+        Err(Error::state(format!(
+            "{} is held by another run, remove it if there is none",
+            arquivo.display()
+        )))
+    }
+}
+
+impl NumeracaoRps {
+    // the saved state, empty when the file does not exist yet; it is always replaced by a rename,
+    // so reading it without the lock never sees a partial write
+    fn ler(&self) -> Result<Estado, Error> {
+        match std::fs::read_to_string(&self.arquivo) {
+            Ok(it) => serde_yaml::from_str(&it)
+                .map_err(|e| Error::state("malformed numbering file").with_source(e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Estado::default()),
+            Err(e) => Err(Error::state("unable to read numbering file").with_source(e)),
+        }
+    }
+}
+
+impl NumeracaoRps {
+    // runs f over the state while holding the lock, then saves it
+    fn transacao<T>(&self, f: impl FnOnce(&mut Estado) -> T) -> Result<T, Error> {
+        let _trava = self.travar()?;
+
+        let mut estado = self.ler()?;
+
+        let resultado = f(&mut estado);
+
+        // written aside and renamed, so an interrupted run never leaves the file truncated
+        let temporario = self.arquivo_com_sufixo(".tmp");
+        let conteudo = serde_yaml::to_string(&estado)
+            .map_err(|e| Error::state("unable to serialize numbering").with_source(e))?;
+        std::fs::write(&temporario, conteudo)
+            .and_then(|_| std::fs::rename(&temporario, &self.arquivo))
+            .map_err(|e| Error::state("unable to write numbering file").with_source(e))?;
+
+        Ok(resultado)
+    }
+}
+
+impl NumeracaoRps {
+    // the next number of the serie, or the one a previous run allocated to the same nome_arquivo
+    // without confirming it, so a failed batch can be sent again with the same numbers
+    pub fn reservar(&self, cnpj: &str, serie: &str, nome_arquivo: &str) -> Result<String, Error> {
+        self.transacao(|estado| {
+            let serie = estado
+                .entry(cnpj.to_owned())
+                .or_default()
+                .series
+                .entry(serie.to_owned())
+                .or_default();
+
+            let numero = match serie.pendentes.get(nome_arquivo) {
+                Some(numero) => *numero,
+                None => {
+                    serie.ultimo_numero += 1;
+                    serie
+                        .pendentes
+                        .insert(nome_arquivo.to_owned(), serie.ultimo_numero);
+                    serie.ultimo_numero
+                }
+            };
+
+            numero.to_string()
+        })
+    }
+}
+
+impl NumeracaoRps {
+    // a number given as id in the input is never allocated to another note of the serie
+    pub fn registrar_rps(&self, cnpj: &str, serie: &str, numero: &str) -> Result<(), Error> {
+        let numero = numero
+            .parse::<u64>()
+            .map_err(|_| Error::input("id", "expected a number"))?;
+
+        self.transacao(|estado| {
+            let serie = estado
+                .entry(cnpj.to_owned())
+                .or_default()
+                .series
+                .entry(serie.to_owned())
+                .or_default();
+            serie.ultimo_numero = serie.ultimo_numero.max(numero);
+        })
+    }
+}
+
+impl NumeracaoRps {
+    // once the batch is accepted its numbers are not handed out again; notes are given as
+    // (serie, nome_arquivo), the same nome_arquivo may be pending in another serie
+    pub fn confirmar(&self, cnpj: &str, notas: &[(&str, &str)]) -> Result<(), Error> {
+        self.transacao(|estado| {
+            let prestador = estado.entry(cnpj.to_owned()).or_default();
+            for (serie, nome_arquivo) in notas {
+                if let Some(serie) = prestador.series.get_mut(*serie) {
+                    if let Some(numero) = serie.pendentes.remove(*nome_arquivo) {
+                        serie.emitidos.insert((*nome_arquivo).to_owned(), numero);
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl NumeracaoRps {
+    // lotes are never sent again, a failed one gets a new number on the next run
+    pub fn proximo_lote(&self, cnpj: &str) -> Result<String, Error> {
+        self.transacao(|estado| {
+            let prestador = estado.entry(cnpj.to_owned()).or_default();
+            prestador.ultimo_lote += 1;
+            prestador.ultimo_lote.to_string()
+        })
+    }
+}
+
+impl NumeracaoRps {
+    // a NumeroLote given in the input is used as is, the next allocated ones come after it
+    pub fn registrar_lote(&self, cnpj: &str, numero_lote: &str) -> Result<(), Error> {
+        let numero_lote = numero_lote
+            .parse::<u64>()
+            .map_err(|_| Error::input("numero_lote", "expected a number"))?;
+
+        self.transacao(|estado| {
+            let prestador = estado.entry(cnpj.to_owned()).or_default();
+            prestador.ultimo_lote = prestador.ultimo_lote.max(numero_lote);
+        })
+    }
+}
+
+impl NumeracaoRps {
+    // the number last allocated to nome_arquivo, confirmed or not; only reads the file
+    pub fn consultar(
+        &self,
+        cnpj: &str,
+        serie: &str,
+        nome_arquivo: &str,
+    ) -> Result<Option<String>, Error> {
+        let estado = self.ler()?;
+
+        Ok(estado
+            .get(cnpj)
+            .and_then(|prestador| prestador.series.get(serie))
+            .and_then(|serie| {
+                serie
+                    .pendentes
+                    .get(nome_arquivo)
+                    .or(serie.emitidos.get(nome_arquivo))
+            })
+            .map(|numero| numero.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NumeracaoRps;
+
+    #[test]
+    fn should_allocate_sequential_numbers_and_reuse_unconfirmed_ones() {
+        let arquivo = std::env::temp_dir().join(format!(
+            "numeracao_rps_{}_{:?}.yml",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let _ = std::fs::remove_file(&arquivo);
+
+        let numeracao = std::sync::Arc::new(NumeracaoRps::new(&arquivo));
+
+        // looking up never creates the file
+        assert_eq!(numeracao.consultar("cnpj", "1", "nota_1").unwrap(), None);
+        assert!(!arquivo.exists());
+
+        assert_eq!(numeracao.reservar("cnpj", "1", "nota_1").unwrap(), "1");
+        assert_eq!(numeracao.reservar("cnpj", "1", "nota_2").unwrap(), "2");
+        assert_eq!(numeracao.reservar("cnpj", "2", "nota_3").unwrap(), "1");
+        assert_eq!(numeracao.reservar("cnpj", "2", "nota_1").unwrap(), "2");
+        assert_eq!(numeracao.reservar("outro", "1", "nota_1").unwrap(), "1");
+
+        // a failed batch sent again keeps its numbers
+        assert_eq!(numeracao.reservar("cnpj", "1", "nota_2").unwrap(), "2");
+
+        numeracao
+            .confirmar("cnpj", &[("1", "nota_1"), ("1", "nota_2")])
+            .unwrap();
+
+        assert_eq!(
+            numeracao.consultar("cnpj", "1", "nota_2").unwrap(),
+            Some(String::from("2"))
+        );
+        assert_eq!(numeracao.reservar("cnpj", "1", "nota_2").unwrap(), "3");
+
+        // the same nome_arquivo in another serie was not sent
+        assert_eq!(numeracao.reservar("cnpj", "2", "nota_1").unwrap(), "2");
+
+        // ids given in the input are skipped by the next reservations
+        numeracao.registrar_rps("cnpj", "1", "7").unwrap();
+        numeracao.registrar_rps("cnpj", "1", "5").unwrap();
+        assert_eq!(numeracao.reservar("cnpj", "1", "nota_4").unwrap(), "8");
+
+        assert_eq!(numeracao.proximo_lote("cnpj").unwrap(), "1");
+        assert_eq!(numeracao.proximo_lote("cnpj").unwrap(), "2");
+        assert_eq!(numeracao.proximo_lote("outro").unwrap(), "1");
+
+        // numbers from the input are never allocated again
+        numeracao.registrar_lote("cnpj", "10").unwrap();
+        numeracao.registrar_lote("cnpj", "4").unwrap();
+        assert_eq!(numeracao.proximo_lote("cnpj").unwrap(), "11");
+        assert_eq!(
+            numeracao
+                .registrar_lote("cnpj", "lote")
+                .err()
+                .map(|e| e.to_string()),
+            Some(String::from("bad input: numero_lote: expected a number"))
+        );
+
+        // concurrent runs never get the same number
+        let threads = (0..4)
+            .map(|i| {
+                let numeracao = numeracao.clone();
+                std::thread::spawn(move || {
+                    (0..10)
+                        .map(|j| {
+                            numeracao
+                                .reservar("cnpj", "1", &format!("nota_{i}_{j}"))
+                                .unwrap()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut numeros = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .map(|numero| numero.parse::<u64>().unwrap())
+            .collect::<Vec<_>>();
+        numeros.sort();
+
+        assert_eq!(numeros, (9..49).collect::<Vec<_>>());
+
+        std::fs::remove_file(&arquivo).unwrap();
+    }
+}
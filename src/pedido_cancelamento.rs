@@ -0,0 +1,300 @@
+use quick_xml::events::BytesEnd;
+use quick_xml::events::BytesStart;
+use quick_xml::events::BytesText;
+use quick_xml::events::Event;
+
+use crate::error::Error;
+use crate::resposta::Resposta;
+use crate::signature::XmlSignature;
+use crate::utils;
+
+pub struct PedidoCancelamento {
+    numero: String,
+    cnpj: String,
+    inscricao_municipal: String,
+    codigo_municipio: String,
+    codigo_cancelamento: String,
+    signature: Option<XmlSignature>,
+}
+
+impl PedidoCancelamento {
+    fn new(cnpj: String, inscricao_municipal: String, codigo_municipio: String) -> Self {
+        Self {
+            numero: String::new(),
+            cnpj,
+            inscricao_municipal,
+            codigo_municipio,
+            codigo_cancelamento: String::new(),
+            signature: None,
+        }
+    }
+}
+
+impl PedidoCancelamento {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping) -> Result<Self, Error> {
+        let cnpj = match yaml.get("cnpj") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("cnpj", "invalid value")),
+            },
+            None => Err(Error::input("cnpj", "required")),
+        }?;
+
+        let inscricao_municipal = match yaml.get("inscricao_municipal") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("inscricao_municipal", "invalid value")),
+            },
+            None => Err(Error::input("inscricao_municipal", "required")),
+        }?;
+
+        let codigo_municipio = match yaml.get("codigo_municipio") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("codigo_municipio", "invalid value")),
+            },
+            None => Err(Error::input("codigo_municipio", "required")),
+        }?;
+
+        Ok(Self::new(cnpj, inscricao_municipal, codigo_municipio))
+    }
+}
+
+impl PedidoCancelamento {
+    pub fn set_numero(mut self, numero: String) -> Self {
+        self.numero = numero;
+        self
+    }
+}
+
+impl PedidoCancelamento {
+    // 1 erro na emissão, 2 serviço não prestado, 3 erro de assinatura, 4 duplicidade da nota,
+    // 5 erro de processamento
+    pub fn set_codigo_cancelamento(mut self, codigo_cancelamento: String) -> Result<Self, Error> {
+        if !["1", "2", "3", "4", "5"].contains(&codigo_cancelamento.as_str()) {
+            return Err(Error::input(
+                "codigo_cancelamento",
+                "expected a value from 1 to 5",
+            ));
+        }
+        self.codigo_cancelamento = codigo_cancelamento;
+        Ok(self)
+    }
+}
+
+impl PedidoCancelamento {
+    pub fn get_cnpj(&self) -> &str {
+        self.cnpj.as_str()
+    }
+}
+
+impl PedidoCancelamento {
+    fn id(&self) -> String {
+        format!("cancelamento{}", self.numero)
+    }
+}
+
+impl PedidoCancelamento {
+    pub fn sign(&mut self, mut signature: XmlSignature) -> Result<(), Error> {
+        signature.load(
+            format!("#{}", self.id()),
+            utils::xml_events_to_xml_string(&self.inf_pedido_cancelamento_xml_events(true)),
+        );
+
+        signature.sign()?;
+
+        self.signature = Some(signature);
+
+        Ok(())
+    }
+}
+
+impl PedidoCancelamento {
+    fn inf_pedido_cancelamento_xml_events(&self, xmlns: bool) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+
+        let mut elem = BytesStart::new("InfPedidoCancelamento");
+        if xmlns {
+            elem.push_attribute(("xmlns", "http://www.abrasf.org.br/nfse.xsd"));
+        }
+        elem.push_attribute(("Id", self.id().as_str()));
+        events.push(Event::Start(elem));
+
+        let elem = BytesStart::new("IdentificacaoNfse");
+        events.push(Event::Start(elem));
+
+        let elem = BytesStart::new("Numero");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.numero.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("Numero");
+        events.push(Event::End(elem));
+
+        let elem = BytesStart::new("Cnpj");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.cnpj.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("Cnpj");
+        events.push(Event::End(elem));
+
+        let elem = BytesStart::new("InscricaoMunicipal");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.inscricao_municipal.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("InscricaoMunicipal");
+        events.push(Event::End(elem));
+
+        let elem = BytesStart::new("CodigoMunicipio");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.codigo_municipio.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("CodigoMunicipio");
+        events.push(Event::End(elem));
+
+        let elem = BytesEnd::new("IdentificacaoNfse");
+        events.push(Event::End(elem));
+
+        let elem = BytesStart::new("CodigoCancelamento");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.codigo_cancelamento.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("CodigoCancelamento");
+        events.push(Event::End(elem));
+
+        let elem = BytesEnd::new("InfPedidoCancelamento");
+        events.push(Event::End(elem));
+
+        events
+    }
+}
+
+impl PedidoCancelamento {
+    pub fn cancelar_nfse_envio_xml_events(&self) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+
+        let mut elem = BytesStart::new("CancelarNfseEnvio");
+        elem.push_attribute(("xmlns", "http://www.abrasf.org.br/nfse.xsd"));
+        events.push(Event::Start(elem));
+
+        let elem = BytesStart::new("Pedido");
+        events.push(Event::Start(elem));
+
+        self.inf_pedido_cancelamento_xml_events(false)
+            .iter()
+            .for_each(|e| events.push(e.to_owned().into_owned()));
+
+        if let Some(signature) = &self.signature {
+            signature
+                .signature_xml_events()
+                .iter()
+                .for_each(|e| events.push(e.to_owned()));
+        };
+
+        let elem = BytesEnd::new("Pedido");
+        events.push(Event::End(elem));
+
+        let elem = BytesEnd::new("CancelarNfseEnvio");
+        events.push(Event::End(elem));
+
+        events
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct ConfirmacaoCancelamento {
+    pub sucesso: bool,
+    pub data_hora: String,
+}
+
+impl ConfirmacaoCancelamento {
+    pub fn from_resposta(resposta: &Resposta) -> Result<Self, Error> {
+        let sucesso = match resposta.text("Sucesso")?.as_deref() {
+            Some("true") | Some("1") => true,
+            Some("false") | Some("0") => false,
+            Some(it) => return Err(Error::xml(format!("unexpected Sucesso: {it}"))),
+            None => return Err(Error::xml("missing Sucesso in response")),
+        };
+
+        let data_hora = resposta
+            .text("DataHora")?
+            .ok_or(Error::xml("missing DataHora in response"))?;
+
+        Ok(Self { sucesso, data_hora })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::algorithms::CanonicalizationAlgorithm;
+    use crate::algorithms::DigestAlgorithm;
+    use crate::algorithms::SignatureAlgorithm;
+    use crate::resposta::Resposta;
+    use crate::signature::XmlSignature;
+    use crate::utils;
+
+    use super::ConfirmacaoCancelamento;
+    use super::PedidoCancelamento;
+
+    #[test]
+    fn should_create_signed_pedido_cancelamento() {
+        let signature = XmlSignature::new(
+            CanonicalizationAlgorithm::NoOp,
+            SignatureAlgorithm::Echo(String::from("the_signature")),
+            DigestAlgorithm::Echo(String::from("the_digest")),
+            String::from("the_certificate"),
+        );
+
+        let yaml: serde_yaml::Mapping = serde_yaml::from_str(
+            "
+cnpj: cnpj_prestador
+inscricao_municipal: inscricao_municipal_prestador
+codigo_municipio: 3106200
+",
+        )
+        .unwrap();
+
+        let mut pedido = PedidoCancelamento::from_yaml(&yaml)
+            .unwrap()
+            .set_numero(String::from("202400000000123"))
+            .set_codigo_cancelamento(String::from("2"))
+            .unwrap();
+
+        pedido.sign(signature).unwrap();
+
+        assert_eq!(
+            utils::xml_events_to_xml_string(&pedido.cancelar_nfse_envio_xml_events()),
+            String::from(
+                r##"<CancelarNfseEnvio xmlns="http://www.abrasf.org.br/nfse.xsd"><Pedido><InfPedidoCancelamento Id="cancelamento202400000000123"><IdentificacaoNfse><Numero>202400000000123</Numero><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal><CodigoMunicipio>3106200</CodigoMunicipio></IdentificacaoNfse><CodigoCancelamento>2</CodigoCancelamento></InfPedidoCancelamento><Signature xmlns="http://www.w3.org/2000/09/xmldsig#"><SignedInfo><CanonicalizationMethod Algorithm="noop-c14n"></CanonicalizationMethod><SignatureMethod Algorithm="echo-signature"></SignatureMethod><Reference URI="#cancelamento202400000000123"><Transforms><Transform Algorithm="noop-c14n"></Transform></Transforms><DigestMethod Algorithm="echo-digest"></DigestMethod><DigestValue>the_digest</DigestValue></Reference></SignedInfo><SignatureValue>the_signature</SignatureValue><KeyInfo><X509Data><X509Certificate>the_certificate</X509Certificate></X509Data></KeyInfo></Signature></Pedido></CancelarNfseEnvio>"##
+            )
+        );
+
+        assert!(PedidoCancelamento::from_yaml(&yaml)
+            .unwrap()
+            .set_codigo_cancelamento(String::from("9"))
+            .is_err());
+
+        let body = r##"<S:Envelope xmlns:S="http://schemas.xmlsoap.org/soap/envelope/"><S:Body><ns2:CancelarNfseResponse xmlns:ns2="http://ws.bhiss.pbh.gov.br"><outputXML>&lt;CancelarNfseResposta xmlns="http://www.abrasf.org.br/nfse.xsd"&gt;&lt;Cancelamento&gt;&lt;Confirmacao Id="confirmacao"&gt;&lt;Pedido&gt;&lt;InfPedidoCancelamento Id="cancelamento202400000000123"&gt;&lt;/InfPedidoCancelamento&gt;&lt;/Pedido&gt;&lt;InfConfirmacaoCancelamento&gt;&lt;Sucesso&gt;true&lt;/Sucesso&gt;&lt;DataHora&gt;2024-03-01T10:20:30&lt;/DataHora&gt;&lt;/InfConfirmacaoCancelamento&gt;&lt;/Confirmacao&gt;&lt;/Cancelamento&gt;&lt;/CancelarNfseResposta&gt;</outputXML></ns2:CancelarNfseResponse></S:Body></S:Envelope>"##;
+
+        assert_eq!(
+            ConfirmacaoCancelamento::from_resposta(&Resposta::from_soap(200, body).unwrap())
+                .unwrap(),
+            ConfirmacaoCancelamento {
+                sucesso: true,
+                data_hora: String::from("2024-03-01T10:20:30"),
+            }
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use base64ct::{Base64, Encoding, LineEnding};
+use p12_keystore::KeyStore;
+use rsa::pkcs8::{DecodePrivateKey, EncodePrivateKey};
+use rsa::{RsaPrivateKey, RsaPublicKey};
+
+use crate::certificate::Certificate;
+use crate::curl::ClientCertificate;
+use crate::error::Error;
+
+pub struct Pkcs12 {
+    private_key: RsaPrivateKey,
+    certificate: String,
+    chain: Vec<String>,
+}
+
+impl Pkcs12 {
+    pub fn from_file(path: &str, password: &str) -> Result<Self, Error> {
+        let der = std::fs::read(path).map_err(|e| {
+            Error::certificate(format!("could not read file at {path}")).with_source(e)
+        })?;
+        Self::from_der(&der, password)
+    }
+}
+
+impl Pkcs12 {
+    pub fn from_der(der: &[u8], password: &str) -> Result<Self, Error> {
+        let key_store = KeyStore::from_pkcs12(der, password)
+            .map_err(|e| Error::certificate(format!("could not open pkcs12: {e}")))?;
+
+        let (_, key_chain) = key_store
+            .private_key_chain()
+            .ok_or(Error::certificate("pkcs12 has no private key"))?;
+
+        let private_key = RsaPrivateKey::from_pkcs8_der(key_chain.key()).map_err(|e| {
+            Error::certificate("pkcs12 private key is not a valid rsa key").with_source(e)
+        })?;
+
+        let public_key = RsaPublicKey::from(&private_key);
+
+        let chain = key_chain
+            .chain()
+            .iter()
+            .map(|certificate| Base64::encode_string(certificate.as_der()))
+            .collect::<Vec<_>>();
+
+        let mut certificate = None;
+        for it in &chain {
+            if Certificate::from_base64(it)?.public_key()? == public_key {
+                certificate = Some(it.clone());
+            }
+        }
+        let certificate = certificate.ok_or(Error::certificate(
+            "pkcs12 has no certificate for its private key",
+        ))?;
+
+        let chain = chain.into_iter().filter(|it| *it != certificate).collect();
+
+        Ok(Self {
+            private_key,
+            certificate,
+            chain,
+        })
+    }
+}
+
+impl Pkcs12 {
+    pub fn private_key(&self) -> RsaPrivateKey {
+        self.private_key.clone()
+    }
+}
+
+impl Pkcs12 {
+    pub fn certificate(&self) -> String {
+        self.certificate.clone()
+    }
+}
+
+impl Pkcs12 {
+    pub fn client_certificate(&self) -> Result<ClientCertificate, Error> {
+        let mut certificate_pem = String::new();
+        for certificate in std::iter::once(&self.certificate).chain(self.chain.iter()) {
+            certificate_pem.push_str("-----BEGIN CERTIFICATE-----\n");
+            certificate.as_bytes().chunks(64).for_each(|line| {
+                certificate_pem.push_str(&String::from_utf8_lossy(line));
+                certificate_pem.push('\n');
+            });
+            certificate_pem.push_str("-----END CERTIFICATE-----\n");
+        }
+
+        let private_key_pem = self
+            .private_key
+            .to_pkcs8_pem(LineEnding::LF)
+            .map_err(|e| Error::certificate("could not encode private key").with_source(e))?
+            .to_string();
+
+        Ok(ClientCertificate {
+            certificate_pem,
+            private_key_pem,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils;
+
+    const CERTIFICATE: &str = include_str!("../tests/fixtures/certificado.cer");
+    const PRIVATE_KEY: &str = include_str!("../tests/fixtures/certificado.key");
+    const PKCS12_LEGACY: &[u8] = include_bytes!("../tests/fixtures/certificado.pfx");
+    const PKCS12_AES: &[u8] = include_bytes!("../tests/fixtures/certificado_aes.pfx");
+
+    #[test]
+    fn should_load_identity_from_pkcs12() {
+        let private_key =
+            <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_pem(PRIVATE_KEY)
+                .unwrap();
+
+        for der in [PKCS12_LEGACY, PKCS12_AES] {
+            let pkcs12 = super::Pkcs12::from_der(der, "senha").unwrap();
+            assert!(pkcs12.private_key() == private_key);
+            assert_eq!(
+                pkcs12.certificate(),
+                utils::trim_x509_certificate(CERTIFICATE)
+            );
+
+            let client_certificate = pkcs12.client_certificate().unwrap();
+            assert_eq!(client_certificate.certificate_pem, CERTIFICATE);
+            assert_eq!(client_certificate.private_key_pem, PRIVATE_KEY);
+        }
+
+        assert!(super::Pkcs12::from_der(PKCS12_LEGACY, "errada").is_err());
+    }
+}
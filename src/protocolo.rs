@@ -3,6 +3,9 @@ use quick_xml::events::BytesStart;
 use quick_xml::events::BytesText;
 use quick_xml::events::Event;
 
+use crate::error::Error;
+use crate::resposta::Resposta;
+
 pub struct Protocolo {
     pub protocolo: String,
     cnpj: String,
@@ -20,23 +23,23 @@ impl Protocolo {
 }
 
 impl Protocolo {
-    pub fn from_yaml(yaml: &serde_yaml::Mapping) -> Result<Self, String> {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping) -> Result<Self, Error> {
         let cnpj = match yaml.get("cnpj") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: cnpj"),
+                _ => Err(Error::input("cnpj", "invalid value")),
             },
-            None => Err("bad yaml input: cnpj"),
+            None => Err(Error::input("cnpj", "required")),
         }?;
 
         let inscricao_municipal = match yaml.get("inscricao_municipal") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: inscricao_municipal"),
+                _ => Err(Error::input("inscricao_municipal", "invalid value")),
             },
-            None => Err("bad yaml input: inscricao_municipal"),
+            None => Err(Error::input("inscricao_municipal", "required")),
         }?;
 
         let protocolo = String::new();
@@ -53,10 +56,22 @@ impl Protocolo {
 }
 
 impl Protocolo {
-    pub fn protocolo_xml_events(&self) -> Vec<Event> {
+    pub fn protocolo_xml_events(&self) -> Vec<Event<'_>> {
+        self.consulta_xml_events("ConsultarLoteRpsEnvio")
+    }
+}
+
+impl Protocolo {
+    pub fn situacao_lote_rps_xml_events(&self) -> Vec<Event<'_>> {
+        self.consulta_xml_events("ConsultarSituacaoLoteRpsEnvio")
+    }
+}
+
+impl Protocolo {
+    fn consulta_xml_events(&self, name: &'static str) -> Vec<Event<'_>> {
         let mut events = Vec::new();
 
-        let mut elem = BytesStart::new("ConsultarLoteRpsEnvio");
+        let mut elem = BytesStart::new(name);
         elem.push_attribute(("xmlns", "http://www.abrasf.org.br/nfse.xsd"));
         elem.push_attribute(("versao", "1.00"));
         events.push(Event::Start(elem));
@@ -94,16 +109,49 @@ impl Protocolo {
         let elem = BytesEnd::new("Protocolo");
         events.push(Event::End(elem));
 
-        let elem = BytesEnd::new("ConsultarLoteRpsEnvio");
+        let elem = BytesEnd::new(name);
         events.push(Event::End(elem));
 
         events
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SituacaoLoteRps {
+    NaoRecebido,
+    NaoProcessado,
+    ProcessadoComErro,
+    ProcessadoComSucesso,
+}
+
+impl SituacaoLoteRps {
+    pub fn from_resposta(resposta: &Resposta) -> Result<Self, Error> {
+        match resposta.text("Situacao")?.as_deref() {
+            Some("1") => Ok(SituacaoLoteRps::NaoRecebido),
+            Some("2") => Ok(SituacaoLoteRps::NaoProcessado),
+            Some("3") => Ok(SituacaoLoteRps::ProcessadoComErro),
+            Some("4") => Ok(SituacaoLoteRps::ProcessadoComSucesso),
+            Some(it) => Err(Error::xml(format!("unknown Situacao: {it}"))),
+            None => Err(Error::xml("missing Situacao in response")),
+        }
+    }
+}
+
+impl SituacaoLoteRps {
+    // once processed the situation does not change anymore, so polling can stop
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            SituacaoLoteRps::ProcessadoComErro | SituacaoLoteRps::ProcessadoComSucesso
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::protocolo::Protocolo;
+    use crate::protocolo::SituacaoLoteRps;
+    use crate::resposta::Resposta;
     use crate::utils;
 
     #[test]
@@ -127,4 +175,35 @@ inscricao_municipal: inscricao_municipal_prestador
             )
         );
     }
+
+    #[test]
+    fn should_consult_situacao_lote_rps() {
+        let yaml: serde_yaml::Mapping = serde_yaml::from_str(
+            "
+cnpj: cnpj_prestador
+inscricao_municipal: inscricao_municipal_prestador
+",
+        )
+        .unwrap();
+
+        let protocolo = Protocolo::from_yaml(&yaml)
+            .unwrap()
+            .set_protocolo(String::from("protocolo_123"));
+
+        assert_eq!(
+            utils::xml_events_to_xml_string(&protocolo.situacao_lote_rps_xml_events()),
+            String::from(
+                r##"<ConsultarSituacaoLoteRpsEnvio xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><Prestador><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal></Prestador><Protocolo>protocolo_123</Protocolo></ConsultarSituacaoLoteRpsEnvio>"##
+            )
+        );
+
+        let body = r##"<S:Envelope xmlns:S="http://schemas.xmlsoap.org/soap/envelope/"><S:Body><ns2:ConsultarSituacaoLoteRpsResponse xmlns:ns2="http://ws.bhiss.pbh.gov.br"><outputXML>&lt;?xml version='1.0' encoding='UTF-8'?&gt;&lt;ConsultarSituacaoLoteRpsResposta xmlns="http://www.abrasf.org.br/nfse.xsd"&gt;&lt;NumeroLote&gt;1&lt;/NumeroLote&gt;&lt;Situacao&gt;2&lt;/Situacao&gt;&lt;/ConsultarSituacaoLoteRpsResposta&gt;</outputXML></ns2:ConsultarSituacaoLoteRpsResponse></S:Body></S:Envelope>"##;
+
+        let situacao =
+            SituacaoLoteRps::from_resposta(&Resposta::from_soap(200, body).unwrap()).unwrap();
+
+        assert_eq!(situacao, SituacaoLoteRps::NaoProcessado);
+        assert!(!situacao.is_final());
+        assert!(SituacaoLoteRps::ProcessadoComErro.is_final());
+    }
 }
@@ -0,0 +1,329 @@
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+
+use crate::error::Error;
+use crate::rps::IdentificacaoRps;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct MensagemRetorno {
+    pub codigo: String,
+    pub mensagem: String,
+    pub correcao: Option<String>,
+    pub identificacao_rps: Option<IdentificacaoRps>,
+}
+
+impl MensagemRetorno {
+    pub fn explicacao(&self) -> Option<&'static str> {
+        explicacao(&self.codigo)
+    }
+}
+
+// codes of the ABRASF 1.00 table of returned messages used by BHISS, the webservice message is always kept alongside
+pub fn explicacao(codigo: &str) -> Option<&'static str> {
+    match codigo {
+        // schema and xml
+        "E160" => Some("O XML enviado não segue o schema ABRASF. Confira campos obrigatórios, formatos e a ordem dos elementos."),
+        "E171" => Some("O arquivo enviado não é um XML bem formado. Confira a codificação e se todos os elementos estão fechados."),
+        // signature and certificate
+        "E172" => Some("A assinatura digital não confere com o conteúdo assinado. O XML não pode ser alterado depois de assinado."),
+        "E173" => Some("O certificado usado na assinatura não é válido, está vencido ou não é ICP-Brasil."),
+        "E174" => Some("O CNPJ do certificado não é o do prestador nem de um procurador autorizado por ele."),
+        // prestador
+        "E44" => Some("O CNPJ do prestador não foi informado."),
+        "E45" => Some("O CNPJ do prestador é inválido. Confira os dígitos verificadores."),
+        "E46" => Some("A inscrição municipal do prestador não foi informada."),
+        "E47" => Some("A inscrição municipal do prestador é inválida ou não pertence ao CNPJ informado."),
+        "E48" => Some("O prestador não está cadastrado ou não está habilitado a emitir NFS-e no município."),
+        // rps
+        "E4" => Some("O RPS consultado não consta na base da prefeitura. Confira número, série e tipo, e se o lote que o continha já foi processado."),
+        "E10" => Some("Já existe um RPS com esse número, série e tipo para o prestador. Cada RPS precisa de um número ainda não utilizado."),
+        "E11" => Some("O mesmo RPS aparece mais de uma vez no lote. Cada RPS do lote precisa de número, série e tipo distintos."),
+        "E92" => Some("O RPS foi recebido mas o lote ainda não foi processado. Consulte novamente mais tarde."),
+        // lote
+        "E69" => Some("A quantidade de RPS informada no lote é diferente da quantidade de RPS enviada."),
+        "E86" => Some("O número do lote já foi utilizado pelo prestador. Cada lote precisa de um número ainda não utilizado."),
+        "E87" => Some("O CNPJ informado no lote é diferente do CNPJ dos prestadores dos RPS."),
+        "E88" => Some("O lote excede a quantidade máxima de RPS ou o tamanho máximo aceito pelo webservice."),
+        "E178" => Some("Não existe lote com o protocolo informado para o prestador. Confira o protocolo devolvido no envio."),
+        // cancelamento
+        "E78" => Some("Não existe NFS-e com esse número para o prestador."),
+        "E79" => Some("A NFS-e já está cancelada, não há o que cancelar."),
+        "E80" => Some("O código de cancelamento informado é inválido."),
+        "E81" => Some("A NFS-e não pode mais ser cancelada pelo webservice. Solicite o cancelamento à prefeitura."),
+        "E82" => Some("A NFS-e foi substituída por outra e não pode ser cancelada."),
+        _ => None,
+    }
+}
+
+// faultcode and faultstring
+type Fault = (String, String);
+
+// the ABRASF document returned inside the SOAP envelope, after checking for faults and rejections
+pub struct Resposta {
+    xml: String,
+}
+
+impl Resposta {
+    pub fn from_soap(status_code: u32, body: &str) -> Result<Self, Error> {
+        let (fault, output) = match scan_envelope(body) {
+            Ok(it) => it,
+            // proxies answer errors with html pages
+            Err(_) if status_code != 200 => (None, None),
+            Err(e) => return Err(e),
+        };
+
+        if let Some((code, message)) = fault {
+            return Err(Error::Fault { code, message });
+        }
+
+        let xml = match output {
+            Some(it) if status_code == 200 => it,
+            _ => {
+                return Err(Error::Webservice {
+                    status_code,
+                    body: body.to_owned(),
+                })
+            }
+        };
+
+        let mensagens = mensagens_retorno(&xml)?;
+        if !mensagens.is_empty() {
+            return Err(Error::Rejected { mensagens });
+        }
+
+        Ok(Self { xml })
+    }
+}
+
+impl Resposta {
+    pub fn xml(&self) -> &str {
+        self.xml.as_str()
+    }
+}
+
+impl Resposta {
+    // text of the first element with the given local name, e.g. Protocolo
+    pub fn text(&self, local_name: &str) -> Result<Option<String>, Error> {
+        let mut reader = Reader::from_str(&self.xml);
+
+        let mut inside = false;
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) => inside = e.local_name().as_ref() == local_name.as_bytes(),
+                Event::Text(e) if inside => return Ok(Some(e.unescape()?.trim().to_owned())),
+                Event::End(_) if inside => return Ok(Some(String::new())),
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn scan_envelope(body: &str) -> Result<(Option<Fault>, Option<String>), Error> {
+    let mut reader = Reader::from_str(body);
+
+    let mut names: Vec<String> = Vec::new();
+    let mut fault: Option<Fault> = None;
+    let mut output: Option<String> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = std::str::from_utf8(e.local_name().as_ref())?.to_owned();
+                if name == "Fault" {
+                    fault = Some((String::new(), String::new()));
+                }
+                names.push(name);
+            }
+            Event::End(_) => {
+                names.pop();
+            }
+            Event::Text(e) => {
+                let text = e.unescape()?;
+                collect(&names, &text, &mut fault, &mut output);
+            }
+            Event::CData(e) => {
+                let text = std::str::from_utf8(&e)?;
+                collect(&names, text, &mut fault, &mut output);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok((fault, output))
+}
+
+fn collect(names: &[String], text: &str, fault: &mut Option<Fault>, output: &mut Option<String>) {
+    let text = text.trim();
+    if let Some((code, message)) = fault {
+        // SOAP 1.1 uses faultcode/faultstring, SOAP 1.2 Code/Value and Reason/Text
+        match names.last().map(String::as_str) {
+            Some("faultcode") => code.push_str(text),
+            Some("faultstring") => message.push_str(text),
+            Some("Value") if names.iter().any(|n| n == "Code") => code.push_str(text),
+            Some("Text") if names.iter().any(|n| n == "Reason") => message.push_str(text),
+            _ => {}
+        }
+    } else if names.last().map(String::as_str) == Some("outputXML") {
+        output.get_or_insert_with(String::new).push_str(text);
+    }
+}
+
+fn mensagens_retorno(xml: &str) -> Result<Vec<MensagemRetorno>, Error> {
+    let mut reader = Reader::from_str(xml);
+
+    let mut names: Vec<String> = Vec::new();
+    let mut mensagens = Vec::new();
+    let mut current: Option<MensagemRetorno> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = std::str::from_utf8(e.local_name().as_ref())?.to_owned();
+                if name == "MensagemRetorno" || name == "MensagemRetornoLote" {
+                    current = Some(MensagemRetorno {
+                        codigo: String::new(),
+                        mensagem: String::new(),
+                        correcao: None,
+                        identificacao_rps: None,
+                    });
+                }
+                names.push(name);
+            }
+            Event::End(_) => {
+                if let Some(name) = names.pop() {
+                    if name == "MensagemRetorno" || name == "MensagemRetornoLote" {
+                        mensagens.extend(current.take());
+                    }
+                }
+            }
+            Event::Text(e) => {
+                if let Some(mensagem) = &mut current {
+                    let text = e.unescape()?.trim().to_owned();
+                    let within_rps = names.iter().any(|n| n == "IdentificacaoRps");
+                    match names.last().map(String::as_str) {
+                        Some("Codigo") => mensagem.codigo = text,
+                        Some("Mensagem") => mensagem.mensagem = text,
+                        Some("Correcao") => mensagem.correcao = Some(text),
+                        Some(field @ ("Numero" | "Serie" | "Tipo")) if within_rps => {
+                            let identificacao =
+                                mensagem.identificacao_rps.get_or_insert_with(|| {
+                                    IdentificacaoRps {
+                                        numero: String::new(),
+                                        serie: String::new(),
+                                        tipo: String::new(),
+                                    }
+                                });
+                            match field {
+                                "Numero" => identificacao.numero = text,
+                                "Serie" => identificacao.serie = text,
+                                _ => identificacao.tipo = text,
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(mensagens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::explicacao;
+    use super::Resposta;
+    use crate::error::Error;
+    use crate::rps::IdentificacaoRps;
+
+    fn envelope(output: &str) -> String {
+        format!(
+            r##"<?xml version='1.0' encoding='UTF-8'?><S:Envelope xmlns:S="http://schemas.xmlsoap.org/soap/envelope/"><S:Body><ns2:RecepcionarLoteRpsResponse xmlns:ns2="http://ws.bhiss.pbh.gov.br"><outputXML>{}</outputXML></ns2:RecepcionarLoteRpsResponse></S:Body></S:Envelope>"##,
+            output
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        )
+    }
+
+    #[test]
+    fn should_parse_protocolo() {
+        let body = envelope(
+            r##"<?xml version='1.0' encoding='UTF-8'?><EnviarLoteRpsResposta xmlns="http://www.abrasf.org.br/nfse.xsd"><NumeroLote>1</NumeroLote><DataRecebimento>2024-03-01T10:00:00</DataRecebimento><Protocolo>ABC123</Protocolo></EnviarLoteRpsResposta>"##,
+        );
+
+        let resposta = Resposta::from_soap(200, &body).unwrap();
+
+        assert_eq!(
+            resposta.text("Protocolo").unwrap(),
+            Some(String::from("ABC123"))
+        );
+        assert_eq!(resposta.text("Situacao").unwrap(), None);
+    }
+
+    #[test]
+    fn should_explain_abrasf_codes() {
+        for codigo in ["E160", "E172", "E45", "E10", "E86", "E79"] {
+            assert!(explicacao(codigo).is_some(), "{codigo}");
+        }
+
+        assert_eq!(
+            explicacao("E79"),
+            Some("A NFS-e já está cancelada, não há o que cancelar.")
+        );
+        assert_eq!(explicacao("E999"), None);
+        assert_eq!(explicacao("e160"), None);
+    }
+
+    #[test]
+    fn should_parse_rejections_and_faults() {
+        let body = envelope(
+            r##"<?xml version='1.0' encoding='UTF-8'?><EnviarLoteRpsResposta xmlns="http://www.abrasf.org.br/nfse.xsd"><ListaMensagemRetorno><MensagemRetorno><Codigo>E160</Codigo><Mensagem>Arquivo em desacordo com o XML Schema.</Mensagem><Correcao>Verifique o XML.</Correcao></MensagemRetorno><MensagemRetorno><IdentificacaoRps><Numero>42</Numero><Serie>1</Serie><Tipo>1</Tipo></IdentificacaoRps><Codigo>E10</Codigo><Mensagem>RPS já informado.</Mensagem></MensagemRetorno></ListaMensagemRetorno></EnviarLoteRpsResposta>"##,
+        );
+
+        match Resposta::from_soap(200, &body) {
+            Err(Error::Rejected { mensagens }) => {
+                assert_eq!(mensagens.len(), 2);
+                assert_eq!(mensagens[0].codigo, "E160");
+                assert_eq!(
+                    mensagens[0].correcao,
+                    Some(String::from("Verifique o XML."))
+                );
+                assert_eq!(mensagens[0].identificacao_rps, None);
+                assert!(mensagens[0].explicacao().is_some());
+                assert_eq!(mensagens[1].mensagem, "RPS já informado.");
+                assert_eq!(
+                    mensagens[1].identificacao_rps,
+                    Some(IdentificacaoRps {
+                        numero: String::from("42"),
+                        serie: String::from("1"),
+                        tipo: String::from("1"),
+                    })
+                );
+            }
+            _ => panic!("expected rejection"),
+        }
+
+        let body = r##"<S:Envelope xmlns:S="http://schemas.xmlsoap.org/soap/envelope/"><S:Body><S:Fault><faultcode>S:Client</faultcode><faultstring>Cannot find dispatch method</faultstring></S:Fault></S:Body></S:Envelope>"##;
+
+        match Resposta::from_soap(500, body) {
+            Err(Error::Fault { code, message }) => {
+                assert_eq!(code, "S:Client");
+                assert_eq!(message, "Cannot find dispatch method");
+            }
+            _ => panic!("expected fault"),
+        }
+
+        assert!(matches!(
+            Resposta::from_soap(502, "<html><body><hr></body></html>"),
+            Err(Error::Webservice {
+                status_code: 502,
+                ..
+            })
+        ));
+    }
+}
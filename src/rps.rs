@@ -3,8 +3,581 @@ use quick_xml::events::BytesStart;
 use quick_xml::events::BytesText;
 use quick_xml::events::Event;
 
+use crate::error::Error;
 use crate::signature::XmlSignature;
 use crate::utils;
+use crate::valor::Aliquota;
+use crate::valor::Valor;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct IdentificacaoRps {
+    pub numero: String,
+    pub serie: String,
+    pub tipo: String,
+}
+
+impl IdentificacaoRps {
+    // serie and tipo default to 1, the values used by the rps we emit
+    pub fn from_yaml(yaml: &serde_yaml::Value) -> Result<Self, Error> {
+        let yaml = match yaml {
+            serde_yaml::Value::Mapping(it) => Ok(it),
+            _ => Err(Error::input("", "expected a mapping")),
+        }?;
+
+        let numero = match yaml.get("numero") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("numero", "invalid value")),
+            },
+            None => Err(Error::input("numero", "required")),
+        }?;
+
+        let serie = match yaml.get("serie") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("serie", "invalid value")),
+            },
+            None => Ok(String::from("1")),
+        }?;
+
+        let tipo = match yaml.get("tipo") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("tipo", "invalid value")),
+            },
+            None => Ok(String::from("1")),
+        }?;
+
+        validar_serie(&serie)?;
+        validar_tipo(&tipo)?;
+
+        Ok(Self {
+            numero,
+            serie,
+            tipo,
+        })
+    }
+}
+
+impl IdentificacaoRps {
+    // Numero, Serie and Tipo inside the given element, IdentificacaoRps or RpsSubstituido
+    pub fn xml_events(&self, name: &'static str) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+
+        let elem = BytesStart::new(name);
+        events.push(Event::Start(elem));
+
+        let elem = BytesStart::new("Numero");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.numero.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("Numero");
+        events.push(Event::End(elem));
+
+        let elem = BytesStart::new("Serie");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.serie.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("Serie");
+        events.push(Event::End(elem));
+
+        let elem = BytesStart::new("Tipo");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.tipo.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("Tipo");
+        events.push(Event::End(elem));
+
+        let elem = BytesEnd::new(name);
+        events.push(Event::End(elem));
+
+        events
+    }
+}
+
+// tsNumeroRps and tsNumeroLote: non-negative integers of up to 15 digits
+pub(crate) fn validar_numero(field: &str, numero: &str) -> Result<(), Error> {
+    if numero.is_empty() || numero.len() > 15 || !numero.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(Error::input(field, "expected up to 15 digits"));
+    }
+    Ok(())
+}
+
+// tsSerieRps: up to 5 characters, kept to letters and digits since it is part of the InfRps Id
+fn validar_serie(serie: &str) -> Result<(), Error> {
+    if serie.is_empty() || serie.len() > 5 || !serie.bytes().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(Error::input("serie", "expected 1 to 5 letters or digits"));
+    }
+    Ok(())
+}
+
+// tsTipoRps: 1 RPS, 2 nota fiscal conjugada (mista), 3 cupom
+fn validar_tipo(tipo: &str) -> Result<(), Error> {
+    if !["1", "2", "3"].contains(&tipo) {
+        return Err(Error::input("tipo", "expected a value from 1 to 3"));
+    }
+    Ok(())
+}
+
+// tsStatusRps: 1 normal, 2 cancelado
+fn validar_status(status: &str) -> Result<(), Error> {
+    if !["1", "2"].contains(&status) {
+        return Err(Error::input("status", "expected 1 or 2"));
+    }
+    Ok(())
+}
+
+// serie, tipo and status of the rps; the ones given for the prestador are the defaults of its notes
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConfiguracaoRps {
+    pub serie: String,
+    pub tipo: String,
+    pub status: String,
+}
+
+impl Default for ConfiguracaoRps {
+    fn default() -> Self {
+        Self {
+            serie: String::from("1"),
+            tipo: String::from("1"),
+            status: String::from("1"),
+        }
+    }
+}
+
+impl ConfiguracaoRps {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping, padrao: &Self) -> Result<Self, Error> {
+        let serie = match yaml.get("serie") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("serie", "invalid value")),
+            },
+            None => Ok(padrao.serie.clone()),
+        }?;
+
+        let tipo = match yaml.get("tipo") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("tipo", "invalid value")),
+            },
+            None => Ok(padrao.tipo.clone()),
+        }?;
+
+        let status = match yaml.get("status") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("status", "invalid value")),
+            },
+            None => Ok(padrao.status.clone()),
+        }?;
+
+        validar_serie(&serie)?;
+        validar_tipo(&tipo)?;
+        validar_status(&status)?;
+
+        Ok(Self {
+            serie,
+            tipo,
+            status,
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum CpfCnpj {
+    Cpf(String),
+    Cnpj(String),
+}
+
+impl CpfCnpj {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping) -> Result<Option<Self>, Error> {
+        let cpf = match yaml.get("cpf") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("cpf", "invalid value")),
+            },
+            None => Ok(None),
+        }?;
+
+        let cnpj = match yaml.get("cnpj") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("cnpj", "invalid value")),
+            },
+            None => Ok(None),
+        }?;
+
+        match (cpf, cnpj) {
+            (Some(_), Some(_)) => Err(Error::input("cpf", "cannot be used together with cnpj")),
+            (Some(cpf), None) => Ok(Some(CpfCnpj::Cpf(cpf))),
+            (None, Some(cnpj)) => Ok(Some(CpfCnpj::Cnpj(cnpj))),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+impl CpfCnpj {
+    pub fn xml_events(&self) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+
+        let (name, value) = match self {
+            CpfCnpj::Cpf(it) => ("Cpf", it),
+            CpfCnpj::Cnpj(it) => ("Cnpj", it),
+        };
+
+        let elem = BytesStart::new("CpfCnpj");
+        events.push(Event::Start(elem));
+
+        let elem = BytesStart::new(name);
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(value.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new(name);
+        events.push(Event::End(elem));
+
+        let elem = BytesEnd::new("CpfCnpj");
+        events.push(Event::End(elem));
+
+        events
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Endereco {
+    pub logradouro: Option<String>,
+    pub numero: Option<String>,
+    pub complemento: Option<String>,
+    pub bairro: Option<String>,
+    pub codigo_municipio: Option<String>,
+    pub uf: Option<String>,
+    pub cep: Option<String>,
+}
+
+impl Endereco {
+    // tcEndereco: every field is optional, e.g. foreign tomadores have no bairro or uf
+    pub fn from_yaml(yaml: &serde_yaml::Mapping) -> Result<Option<Self>, Error> {
+        let logradouro = match yaml.get("logradouro") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("logradouro", "invalid value")),
+            },
+            None => Ok(None),
+        }?;
+
+        let numero = match yaml.get("numero") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("numero", "invalid value")),
+            },
+            None => Ok(None),
+        }?;
+
+        let complemento = match yaml.get("complemento") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("complemento", "invalid value")),
+            },
+            None => Ok(None),
+        }?;
+
+        let bairro = match yaml.get("bairro") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("bairro", "invalid value")),
+            },
+            None => Ok(None),
+        }?;
+
+        let codigo_municipio = match yaml.get("codigo_municipio") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("codigo_municipio", "invalid value")),
+            },
+            None => Ok(None),
+        }?;
+
+        let uf = match yaml.get("uf") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("uf", "invalid value")),
+            },
+            None => Ok(None),
+        }?;
+
+        let cep = match yaml.get("cep") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("cep", "invalid value")),
+            },
+            None => Ok(None),
+        }?;
+
+        let endereco = Self {
+            logradouro,
+            numero,
+            complemento,
+            bairro,
+            codigo_municipio,
+            uf,
+            cep,
+        };
+
+        if endereco == Self::default() {
+            return Ok(None);
+        }
+
+        Ok(Some(endereco))
+    }
+}
+
+impl Endereco {
+    pub fn xml_events(&self) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+
+        let elem = BytesStart::new("Endereco");
+        events.push(Event::Start(elem));
+
+        for (name, value) in [
+            ("Endereco", &self.logradouro),
+            ("Numero", &self.numero),
+            ("Complemento", &self.complemento),
+            ("Bairro", &self.bairro),
+            ("CodigoMunicipio", &self.codigo_municipio),
+            ("Uf", &self.uf),
+            ("Cep", &self.cep),
+        ] {
+            if let Some(value) = value {
+                let elem = BytesStart::new(name);
+                events.push(Event::Start(elem));
+
+                let elem = BytesText::new(value.as_str());
+                events.push(Event::Text(elem));
+
+                let elem = BytesEnd::new(name);
+                events.push(Event::End(elem));
+            }
+        }
+
+        let elem = BytesEnd::new("Endereco");
+        events.push(Event::End(elem));
+
+        events
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct IntermediarioServico {
+    pub razao_social: String,
+    pub cpf_cnpj: CpfCnpj,
+    pub inscricao_municipal: Option<String>,
+}
+
+impl IntermediarioServico {
+    pub fn from_yaml(yaml: &serde_yaml::Value) -> Result<Self, Error> {
+        let yaml = match yaml {
+            serde_yaml::Value::Mapping(it) => Ok(it),
+            _ => Err(Error::input("", "expected a mapping")),
+        }?;
+
+        let razao_social = match yaml.get("razao_social") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("razao_social", "invalid value")),
+            },
+            None => Err(Error::input("razao_social", "required")),
+        }?;
+
+        let cpf_cnpj = CpfCnpj::from_yaml(yaml)?.ok_or(Error::input("cnpj", "required"))?;
+
+        let inscricao_municipal = match yaml.get("inscricao_municipal") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("inscricao_municipal", "invalid value")),
+            },
+            None => Ok(None),
+        }?;
+
+        Ok(Self {
+            razao_social,
+            cpf_cnpj,
+            inscricao_municipal,
+        })
+    }
+}
+
+impl IntermediarioServico {
+    pub fn xml_events(&self) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+
+        let elem = BytesStart::new("IntermediarioServico");
+        events.push(Event::Start(elem));
+
+        let elem = BytesStart::new("RazaoSocial");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.razao_social.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("RazaoSocial");
+        events.push(Event::End(elem));
+
+        self.cpf_cnpj
+            .xml_events()
+            .into_iter()
+            .for_each(|e| events.push(e));
+
+        if let Some(inscricao_municipal) = &self.inscricao_municipal {
+            let elem = BytesStart::new("InscricaoMunicipal");
+            events.push(Event::Start(elem));
+
+            let elem = BytesText::new(inscricao_municipal.as_str());
+            events.push(Event::Text(elem));
+
+            let elem = BytesEnd::new("InscricaoMunicipal");
+            events.push(Event::End(elem));
+        }
+
+        let elem = BytesEnd::new("IntermediarioServico");
+        events.push(Event::End(elem));
+
+        events
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConstrucaoCivil {
+    pub codigo_obra: String,
+    pub art: String,
+}
+
+impl ConstrucaoCivil {
+    pub fn from_yaml(yaml: &serde_yaml::Value) -> Result<Self, Error> {
+        let yaml = match yaml {
+            serde_yaml::Value::Mapping(it) => Ok(it),
+            _ => Err(Error::input("", "expected a mapping")),
+        }?;
+
+        let codigo_obra = match yaml.get("codigo_obra") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("codigo_obra", "invalid value")),
+            },
+            None => Err(Error::input("codigo_obra", "required")),
+        }?;
+
+        let art = match yaml.get("art") {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => Ok(it.clone()),
+                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
+                _ => Err(Error::input("art", "invalid value")),
+            },
+            None => Err(Error::input("art", "required")),
+        }?;
+
+        Ok(Self { codigo_obra, art })
+    }
+}
+
+impl ConstrucaoCivil {
+    pub fn xml_events(&self) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+
+        let elem = BytesStart::new("ConstrucaoCivil");
+        events.push(Event::Start(elem));
+
+        let elem = BytesStart::new("CodigoObra");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.codigo_obra.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("CodigoObra");
+        events.push(Event::End(elem));
+
+        let elem = BytesStart::new("Art");
+        events.push(Event::Start(elem));
+
+        let elem = BytesText::new(self.art.as_str());
+        events.push(Event::Text(elem));
+
+        let elem = BytesEnd::new("Art");
+        events.push(Event::End(elem));
+
+        let elem = BytesEnd::new("ConstrucaoCivil");
+        events.push(Event::End(elem));
+
+        events
+    }
+}
+
+// deductions, discounts and federal retentions, all optional
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Retencoes {
+    pub valor_deducoes: Option<Valor>,
+    pub valor_pis: Option<Valor>,
+    pub valor_cofins: Option<Valor>,
+    pub valor_inss: Option<Valor>,
+    pub valor_ir: Option<Valor>,
+    pub valor_csll: Option<Valor>,
+    pub outras_retencoes: Option<Valor>,
+    pub desconto_incondicionado: Option<Valor>,
+    pub desconto_condicionado: Option<Valor>,
+}
+
+impl Retencoes {
+    pub fn from_yaml(yaml: &serde_yaml::Mapping) -> Result<Self, Error> {
+        let get = |key: &str| match yaml.get(key) {
+            Some(it) => match it {
+                serde_yaml::Value::String(it) => it.parse().map(Some),
+                serde_yaml::Value::Number(it) => format!("{}", it).parse().map(Some),
+                _ => Err(Error::input("", "invalid value")),
+            }
+            .map_err(|e: Error| e.within(key)),
+            None => Ok(None),
+        };
+
+        Ok(Self {
+            valor_deducoes: get("valor_deducoes")?,
+            valor_pis: get("valor_pis")?,
+            valor_cofins: get("valor_cofins")?,
+            valor_inss: get("valor_inss")?,
+            valor_ir: get("valor_ir")?,
+            valor_csll: get("valor_csll")?,
+            outras_retencoes: get("outras_retencoes")?,
+            desconto_incondicionado: get("desconto_incondicionado")?,
+            desconto_condicionado: get("desconto_condicionado")?,
+        })
+    }
+}
+
+// the number of a note without id, given the cnpj of the prestador, the serie and the nome_arquivo
+pub type Numerador<'a> = &'a dyn Fn(&str, &str, &str) -> Result<String, Error>;
 
 pub struct Rps {
     id: String,
@@ -18,26 +591,31 @@ pub struct Rps {
     codigo_tributacao_municipio: String,
     discriminacao: String,
     codigo_municipio: String,
-    valor_servicos: String,
-    aliquota_iss: Option<String>,
-    valor_iss: Option<String>,
-    valor_liquido: String,
+    valor_servicos: Valor,
+    aliquota_iss: Option<Aliquota>,
+    valor_iss: Option<Valor>,
+    iss_retido: bool,
+    valor_iss_retido: Option<Valor>,
+    retencoes: Retencoes,
+    base_calculo: Valor,
+    valor_liquido: Valor,
     cnpj_prestador: String,
     inscricao_municipal_prestador: String,
-    cnpj_tomador: Option<String>,
+    cpf_cnpj_tomador: Option<CpfCnpj>,
     inscricao_municipal_tomador: Option<String>,
     razao_social_tomador: String,
-    logradouro_tomador: String,
-    numero_tomador: String,
-    complemento_tomador: Option<String>,
-    bairro_tomador: String,
-    codigo_municipio_tomador: String,
-    uf_tomador: String,
-    cep_tomador: Option<String>,
+    endereco_tomador: Option<Endereco>,
+    telefone_tomador: Option<String>,
+    email_tomador: Option<String>,
+    configuracao: ConfiguracaoRps,
+    rps_substituido: Option<IdentificacaoRps>,
+    intermediario_servico: Option<IntermediarioServico>,
+    construcao_civil: Option<ConstrucaoCivil>,
     signature: Option<XmlSignature>,
 }
 
 impl Rps {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: String,
         nome_arquivo: String,
@@ -50,42 +628,63 @@ impl Rps {
         codigo_tributacao_municipio: String,
         discriminacao: String,
         codigo_municipio: String,
-        valor_servicos: String,
-        aliquota_iss: Option<String>,
+        valor_servicos: Valor,
+        aliquota_iss: Option<Aliquota>,
+        iss_retido: Option<bool>,
+        retencoes: Retencoes,
         cnpj_prestador: String,
         inscricao_municipal_prestador: String,
-        cnpj_tomador: Option<String>,
+        cpf_cnpj_tomador: Option<CpfCnpj>,
         inscricao_municipal_tomador: Option<String>,
         razao_social_tomador: String,
-        logradouro_tomador: String,
-        numero_tomador: String,
-        complemento_tomador: Option<String>,
-        bairro_tomador: String,
-        codigo_municipio_tomador: String,
-        uf_tomador: String,
-        cep_tomador: Option<String>,
-    ) -> Self {
-        let (valor_servicos, valor_iss, valor_liquido) = match &aliquota_iss {
-            Some(aliquota) => {
-                let valor_total = valor_servicos.parse::<f64>().unwrap();
-                let aliquota = aliquota.parse::<f64>().unwrap();
-                let valor_iss = (100.0 * valor_total * aliquota).round() / 100.0;
-                let valor_liquido = valor_total - valor_iss;
-                let valor_total = format!("{:.2}", valor_total);
-                let valor_iss = format!("{:.2}", valor_iss);
-                let valor_liquido = format!("{:.2}", valor_liquido);
-                (valor_total, Some(valor_iss), valor_liquido)
-            }
-            None => {
-                let valor_total = valor_servicos.parse::<f64>().unwrap();
-                let valor_liquido = valor_total - 0.0;
-                let valor_total = format!("{:.2}", valor_total);
-                let valor_liquido = format!("{:.2}", valor_liquido);
-                (valor_total, None, valor_liquido)
-            }
-        };
+        endereco_tomador: Option<Endereco>,
+        telefone_tomador: Option<String>,
+        email_tomador: Option<String>,
+    ) -> Result<Self, Error> {
+        // ABRASF: BaseCalculo = ValorServicos - ValorDeducoes - DescontoIncondicionado
+        let base_calculo = valor_servicos
+            - retencoes.valor_deducoes.unwrap_or_default()
+            - retencoes.desconto_incondicionado.unwrap_or_default();
+        if base_calculo < Valor::default() {
+            return Err(Error::input(
+                "valor_deducoes",
+                "deductions and discounts exceed valor_servicos",
+            ));
+        }
 
-        Self {
+        let valor_iss = aliquota_iss.map(|aliquota| base_calculo.multiplicar(aliquota));
+
+        // without an explicit iss_retido the ISS is withheld whenever there is an aliquota
+        let iss_retido = iss_retido.unwrap_or(aliquota_iss.is_some());
+        if iss_retido && aliquota_iss.is_none() {
+            return Err(Error::input("aliquota_iss", "required when iss_retido"));
+        }
+        let valor_iss_retido = if iss_retido { valor_iss } else { None };
+
+        // ABRASF: ValorLiquidoNfse = ValorServicos - retentions - ValorIssRetido - discounts
+        let valor_liquido = valor_servicos
+            - [
+                retencoes.valor_pis,
+                retencoes.valor_cofins,
+                retencoes.valor_inss,
+                retencoes.valor_ir,
+                retencoes.valor_csll,
+                retencoes.outras_retencoes,
+                valor_iss_retido,
+                retencoes.desconto_incondicionado,
+                retencoes.desconto_condicionado,
+            ]
+            .iter()
+            .flatten()
+            .sum::<Valor>();
+        if valor_liquido < Valor::default() {
+            return Err(Error::input(
+                "valor_servicos",
+                "retentions and discounts exceed valor_servicos",
+            ));
+        }
+
+        Ok(Self {
             id,
             nome_arquivo,
             data_emissao,
@@ -100,21 +699,25 @@ impl Rps {
             valor_servicos,
             aliquota_iss,
             valor_iss,
+            iss_retido,
+            valor_iss_retido,
+            retencoes,
+            base_calculo,
             valor_liquido,
             cnpj_prestador,
             inscricao_municipal_prestador,
-            cnpj_tomador,
+            cpf_cnpj_tomador,
             inscricao_municipal_tomador,
             razao_social_tomador,
-            logradouro_tomador,
-            numero_tomador,
-            complemento_tomador,
-            bairro_tomador,
-            codigo_municipio_tomador,
-            uf_tomador,
-            cep_tomador,
+            endereco_tomador,
+            telefone_tomador,
+            email_tomador,
+            configuracao: ConfiguracaoRps::default(),
+            rps_substituido: None,
+            intermediario_servico: None,
+            construcao_civil: None,
             signature: None,
-        }
+        })
     }
 }
 
@@ -124,213 +727,201 @@ impl Rps {
         cnpj: String,
         inscricao_municipal: String,
         codigo_municipio: String,
-    ) -> Result<Self, String> {
+        padrao: &ConfiguracaoRps,
+        numerador: Numerador,
+    ) -> Result<Self, Error> {
         let yaml = match yaml {
             serde_yaml::Value::Mapping(it) => Ok(it),
-            _ => Err("bad yaml input"),
+            _ => Err(Error::input("", "expected a mapping")),
         }?;
 
         let id = match yaml.get("id") {
             Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(it.clone()),
-                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: id"),
+                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
+                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
+                _ => Err(Error::input("id", "invalid value")),
             },
-            None => Ok(utils::generate_random_rps()),
+            None => Ok(None),
         }?;
+        if let Some(id) = &id {
+            validar_numero("id", id)?;
+        }
 
         let nome_arquivo = match yaml.get("nome_arquivo") {
-            Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(it.clone()),
-                _ => Err("bad yaml input: nome_arquivo"),
-            },
-            None => Err("bad yaml input: nome_arquivo"),
+            Some(serde_yaml::Value::String(it)) => Ok(it.clone()),
+            _ => Err(Error::input("nome_arquivo", "invalid value")),
         }?;
 
+        // TODO
+        // "start_of_current_month" =>
+        // "start_of_last_month" =>
         let data_emissao = match yaml.get("competencia") {
-            Some(it) => match it {
-                serde_yaml::Value::String(it) => match it {
-                    // TODO
-                    // "start_of_current_month" =>
-                    // "start_of_last_month" =>
-                    _ => Ok(it.clone()),
-                },
-                _ => Err("bad yaml input: competencia"),
-            },
-            None => Err("bad yaml input: competencia"),
+            Some(serde_yaml::Value::String(it)) => Ok(it.clone()),
+            _ => Err(Error::input("competencia", "invalid value")),
         }?;
 
         let natureza_operacao = match yaml.get("natureza_operacao") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: natureza_operacao"),
+                _ => Err(Error::input("natureza_operacao", "invalid value")),
             },
-            None => Err("bad yaml input: natureza_operacao"),
+            None => Err(Error::input("natureza_operacao", "required")),
         }?;
 
         let regime_especial_tributacao = match yaml.get("regime_especial_tributacao") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: regime_especial_tributacao"),
+                _ => Err(Error::input("regime_especial_tributacao", "invalid value")),
             },
-            None => Err("bad yaml input: regime_especial_tributacao"),
+            None => Err(Error::input("regime_especial_tributacao", "required")),
         }?;
 
         let optante_simples_nacional = match yaml.get("optante_simples_nacional") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: optante_simples_nacional"),
+                _ => Err(Error::input("optante_simples_nacional", "invalid value")),
             },
-            None => Err("bad yaml input: optante_simples_nacional"),
+            None => Err(Error::input("optante_simples_nacional", "required")),
         }?;
 
         let incentivador_cultural = match yaml.get("incentivador_cultural") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: incentivador_cultural"),
+                _ => Err(Error::input("incentivador_cultural", "invalid value")),
             },
-            None => Err("bad yaml input: incentivador_cultural"),
+            None => Err(Error::input("incentivador_cultural", "required")),
         }?;
 
         let item_lista_servico = match yaml.get("item_lista_servico") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: item_lista_servico"),
+                _ => Err(Error::input("item_lista_servico", "invalid value")),
             },
-            None => Err("bad yaml input: item_lista_servico"),
+            None => Err(Error::input("item_lista_servico", "required")),
         }?;
 
         let codigo_tributacao_municipio = match yaml.get("codigo_tributacao_municipio") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: codigo_tributacao_municipio"),
+                _ => Err(Error::input("codigo_tributacao_municipio", "invalid value")),
             },
-            None => Err("bad yaml input: codigo_tributacao_municipio"),
+            None => Err(Error::input("codigo_tributacao_municipio", "required")),
         }?;
 
         let discriminacao = match yaml.get("discriminacao") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: discriminacao"),
+                _ => Err(Error::input("discriminacao", "invalid value")),
             },
-            None => Err("bad yaml input: discriminacao"),
+            None => Err(Error::input("discriminacao", "required")),
         }?;
 
         let valor_servicos = match yaml.get("valor_servicos") {
             Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(it.clone()),
-                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: valor_servicos"),
-            },
-            None => Err("bad yaml input: valor_servicos"),
+                serde_yaml::Value::String(it) => it.parse(),
+                serde_yaml::Value::Number(it) => format!("{}", it).parse(),
+                _ => Err(Error::input("", "invalid value")),
+            }
+            .map_err(|e| e.within("valor_servicos")),
+            None => Err(Error::input("valor_servicos", "required")),
         }?;
 
         let aliquota_iss = match yaml.get("aliquota_iss") {
             Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
-                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
-                _ => Err("bad yaml input: aliquota_iss"),
-            },
+                serde_yaml::Value::String(it) => it.parse().map(Some),
+                serde_yaml::Value::Number(it) => format!("{}", it).parse().map(Some),
+                _ => Err(Error::input("", "invalid value")),
+            }
+            .map_err(|e: Error| e.within("aliquota_iss")),
             None => Ok(None),
         }?;
 
-        let cnpj_tomador = match yaml.get("cnpj") {
+        let iss_retido = match yaml.get("iss_retido") {
             Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
-                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
-                _ => Err("bad yaml input: cnpj"),
+                serde_yaml::Value::Bool(it) => Ok(Some(*it)),
+                serde_yaml::Value::Number(it) if it.as_u64() == Some(1) => Ok(Some(true)),
+                serde_yaml::Value::Number(it) if it.as_u64() == Some(2) => Ok(Some(false)),
+                _ => Err(Error::input("iss_retido", "invalid value")),
             },
             None => Ok(None),
         }?;
 
-        let inscricao_municipal_tomador = match yaml.get("inscricao_municipal") {
-            Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(Some(it.clone())),
-                serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
-                _ => Err("bad yaml input: inscricao_municipal"),
-            },
-            None => Ok(None),
-        }?;
+        let retencoes = Retencoes::from_yaml(&yaml)?;
 
-        let razao_social_tomador = match yaml.get("razao_social") {
-            Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(it.clone()),
-                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: razao_social"),
-            },
-            None => Err("bad yaml input: razao_social"),
-        }?;
+        let cpf_cnpj_tomador = CpfCnpj::from_yaml(&yaml)?;
 
-        let logradouro_tomador = match yaml.get("logradouro") {
-            Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(it.clone()),
-                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: logradouro"),
-            },
-            None => Err("bad yaml input: logradouro"),
-        }?;
-
-        let numero_tomador = match yaml.get("numero") {
-            Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(it.clone()),
-                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: numero"),
-            },
-            None => Err("bad yaml input: numero"),
-        }?;
-
-        let complemento_tomador = match yaml.get("complemento") {
+        let inscricao_municipal_tomador = match yaml.get("inscricao_municipal") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(Some(it.clone())),
                 serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
-                _ => Err("bad yaml input: complemento"),
-            },
-            None => Ok(None),
-        }?;
-
-        let bairro_tomador = match yaml.get("bairro") {
-            Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(it.clone()),
-                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: bairro"),
-            },
-            None => Err("bad yaml input: bairro"),
-        }?;
-
-        let codigo_municipio_tomador = match yaml.get("codigo_municipio") {
-            Some(it) => match it {
-                serde_yaml::Value::String(it) => Ok(it.clone()),
-                serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: codigo_municipio"),
+                _ => Err(Error::input("inscricao_municipal", "invalid value")),
             },
-            None => Err("bad yaml input: codigo_municipio"),
+            None => Ok(None),
         }?;
 
-        let uf_tomador = match yaml.get("uf") {
+        let razao_social_tomador = match yaml.get("razao_social") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(it.clone()),
                 serde_yaml::Value::Number(it) => Ok(format!("{}", it)),
-                _ => Err("bad yaml input: uf"),
+                _ => Err(Error::input("razao_social", "invalid value")),
             },
-            None => Err("bad yaml input: uf"),
+            None => Err(Error::input("razao_social", "required")),
         }?;
 
-        let cep_tomador = match yaml.get("cep") {
+        let endereco_tomador = Endereco::from_yaml(&yaml)?;
+
+        let telefone_tomador = match yaml.get("telefone") {
             Some(it) => match it {
                 serde_yaml::Value::String(it) => Ok(Some(it.clone())),
                 serde_yaml::Value::Number(it) => Ok(Some(format!("{}", it))),
-                _ => Err("bad yaml input: cep"),
+                _ => Err(Error::input("telefone", "invalid value")),
             },
             None => Ok(None),
         }?;
 
+        let email_tomador = match yaml.get("email") {
+            Some(serde_yaml::Value::String(it)) => Ok(Some(it.clone())),
+            Some(_) => Err(Error::input("email", "invalid value")),
+            None => Ok(None),
+        }?;
+
+        let configuracao = ConfiguracaoRps::from_yaml(&yaml, padrao)?;
+
+        let rps_substituido = match yaml.get("rps_substituido") {
+            Some(it) => {
+                Some(IdentificacaoRps::from_yaml(it).map_err(|e| e.within("rps_substituido"))?)
+            }
+            None => None,
+        };
+
+        let intermediario_servico = match yaml.get("intermediario_servico") {
+            Some(it) => Some(
+                IntermediarioServico::from_yaml(it)
+                    .map_err(|e| e.within("intermediario_servico"))?,
+            ),
+            None => None,
+        };
+
+        let construcao_civil = match yaml.get("construcao_civil") {
+            Some(it) => {
+                Some(ConstrucaoCivil::from_yaml(it).map_err(|e| e.within("construcao_civil"))?)
+            }
+            None => None,
+        };
+
+        // numbers are only allocated to notes without errors
+        let id = match id {
+            Some(id) => id,
+            None => numerador(&cnpj, &configuracao.serie, &nome_arquivo)?,
+        };
+
         Ok(Self::new(
             id,
             nome_arquivo,
@@ -345,79 +936,97 @@ impl Rps {
             codigo_municipio,
             valor_servicos,
             aliquota_iss,
+            iss_retido,
+            retencoes,
             cnpj,
             inscricao_municipal,
-            cnpj_tomador,
+            cpf_cnpj_tomador,
             inscricao_municipal_tomador,
             razao_social_tomador,
-            logradouro_tomador,
-            numero_tomador,
-            complemento_tomador,
-            bairro_tomador,
-            codigo_municipio_tomador,
-            uf_tomador,
-            cep_tomador,
-        ))
+            endereco_tomador,
+            telefone_tomador,
+            email_tomador,
+        )?
+        .set_configuracao(configuracao)
+        .set_rps_substituido(rps_substituido)
+        .set_intermediario_servico(intermediario_servico)
+        .set_construcao_civil(construcao_civil))
+    }
+}
+
+impl Rps {
+    pub fn set_configuracao(mut self, configuracao: ConfiguracaoRps) -> Self {
+        self.configuracao = configuracao;
+        self
+    }
+}
+
+impl Rps {
+    pub fn set_rps_substituido(mut self, rps_substituido: Option<IdentificacaoRps>) -> Self {
+        self.rps_substituido = rps_substituido;
+        self
+    }
+}
+
+impl Rps {
+    pub fn set_intermediario_servico(
+        mut self,
+        intermediario_servico: Option<IntermediarioServico>,
+    ) -> Self {
+        self.intermediario_servico = intermediario_servico;
+        self
+    }
+}
+
+impl Rps {
+    pub fn set_construcao_civil(mut self, construcao_civil: Option<ConstrucaoCivil>) -> Self {
+        self.construcao_civil = construcao_civil;
+        self
     }
 }
 
 impl Rps {
-    pub fn sign(&mut self, mut signature: XmlSignature) {
+    pub fn sign(&mut self, mut signature: XmlSignature) -> Result<(), Error> {
         signature.load(
-            format!("#{}", self.id),
+            format!("#{}", self.inf_rps_id()),
             utils::xml_events_to_xml_string(&self.inf_rps_xml_events(true)),
         );
 
-        signature.sign();
+        signature.sign()?;
 
         self.signature = Some(signature);
+
+        Ok(())
+    }
+}
+
+impl Rps {
+    // the número alone repeats across séries and tipos of the same lote
+    fn inf_rps_id(&self) -> String {
+        format!(
+            "rps{}_{}_{}",
+            self.configuracao.serie, self.configuracao.tipo, self.id
+        )
     }
 }
 
 impl Rps {
-    fn inf_rps_xml_events(&self, xmlns: bool) -> Vec<Event> {
+    fn inf_rps_xml_events(&self, xmlns: bool) -> Vec<Event<'_>> {
         let mut events = Vec::new();
 
         let mut elem = BytesStart::new("InfRps");
         if xmlns {
             elem.push_attribute(("xmlns", "http://www.abrasf.org.br/nfse.xsd"));
         }
-        elem.push_attribute(("Id", self.id.as_str()));
+        elem.push_attribute(("Id", self.inf_rps_id().as_str()));
         elem.push_attribute(("versao", "1.00"));
         events.push(Event::Start(elem));
 
-        let elem = BytesStart::new("IdentificacaoRps");
-        events.push(Event::Start(elem));
-
-        let elem = BytesStart::new("Numero");
-        events.push(Event::Start(elem));
-
-        let elem = BytesText::new(&self.id);
-        events.push(Event::Text(elem));
-
-        let elem = BytesEnd::new("Numero");
-        events.push(Event::End(elem));
-
-        let elem = BytesStart::new("Serie");
-        events.push(Event::Start(elem));
-
-        let elem = BytesText::new("1");
-        events.push(Event::Text(elem));
-
-        let elem = BytesEnd::new("Serie");
-        events.push(Event::End(elem));
-
-        let elem = BytesStart::new("Tipo");
-        events.push(Event::Start(elem));
-
-        let elem = BytesText::new("1");
-        events.push(Event::Text(elem));
-
-        let elem = BytesEnd::new("Tipo");
-        events.push(Event::End(elem));
-
-        let elem = BytesEnd::new("IdentificacaoRps");
-        events.push(Event::End(elem));
+        // owned, the identificacao is built on the fly
+        self.identificacao()
+            .xml_events("IdentificacaoRps")
+            .into_iter()
+            .for_each(|e| events.push(e.into_owned()));
 
         let elem = BytesStart::new("DataEmissao");
         events.push(Event::Start(elem));
@@ -467,12 +1076,19 @@ impl Rps {
         let elem = BytesStart::new("Status");
         events.push(Event::Start(elem));
 
-        let elem = BytesText::new("1");
+        let elem = BytesText::new(self.configuracao.status.as_str());
         events.push(Event::Text(elem));
 
         let elem = BytesEnd::new("Status");
         events.push(Event::End(elem));
 
+        if let Some(rps_substituido) = &self.rps_substituido {
+            rps_substituido
+                .xml_events("RpsSubstituido")
+                .into_iter()
+                .for_each(|e| events.push(e));
+        }
+
         let elem = BytesStart::new("Servico");
         events.push(Event::Start(elem));
 
@@ -482,19 +1098,36 @@ impl Rps {
         let elem = BytesStart::new("ValorServicos");
         events.push(Event::Start(elem));
 
-        let elem = BytesText::new(self.valor_servicos.as_str());
+        let elem = BytesText::from_escaped(self.valor_servicos.to_string());
         events.push(Event::Text(elem));
 
         let elem = BytesEnd::new("ValorServicos");
         events.push(Event::End(elem));
 
+        for (name, value) in [
+            ("ValorDeducoes", &self.retencoes.valor_deducoes),
+            ("ValorPis", &self.retencoes.valor_pis),
+            ("ValorCofins", &self.retencoes.valor_cofins),
+            ("ValorInss", &self.retencoes.valor_inss),
+            ("ValorIr", &self.retencoes.valor_ir),
+            ("ValorCsll", &self.retencoes.valor_csll),
+        ] {
+            if let Some(value) = value {
+                let elem = BytesStart::new(name);
+                events.push(Event::Start(elem));
+
+                let elem = BytesText::from_escaped(value.to_string());
+                events.push(Event::Text(elem));
+
+                let elem = BytesEnd::new(name);
+                events.push(Event::End(elem));
+            }
+        }
+
         let elem = BytesStart::new("IssRetido");
         events.push(Event::Start(elem));
 
-        let elem = BytesText::new(match self.aliquota_iss {
-            Some(_) => "1",
-            None => "2",
-        });
+        let elem = BytesText::new(if self.iss_retido { "1" } else { "2" });
         events.push(Event::Text(elem));
 
         let elem = BytesEnd::new("IssRetido");
@@ -504,26 +1137,39 @@ impl Rps {
             let elem = BytesStart::new("ValorIss");
             events.push(Event::Start(elem));
 
-            let elem = BytesText::new(valor_iss.as_str());
+            let elem = BytesText::from_escaped(valor_iss.to_string());
             events.push(Event::Text(elem));
 
             let elem = BytesEnd::new("ValorIss");
             events.push(Event::End(elem));
+        }
 
+        if let Some(valor_iss_retido) = &self.valor_iss_retido {
             let elem = BytesStart::new("ValorIssRetido");
             events.push(Event::Start(elem));
 
-            let elem = BytesText::new(valor_iss.as_str());
+            let elem = BytesText::from_escaped(valor_iss_retido.to_string());
             events.push(Event::Text(elem));
 
             let elem = BytesEnd::new("ValorIssRetido");
             events.push(Event::End(elem));
         }
 
+        if let Some(outras_retencoes) = &self.retencoes.outras_retencoes {
+            let elem = BytesStart::new("OutrasRetencoes");
+            events.push(Event::Start(elem));
+
+            let elem = BytesText::from_escaped(outras_retencoes.to_string());
+            events.push(Event::Text(elem));
+
+            let elem = BytesEnd::new("OutrasRetencoes");
+            events.push(Event::End(elem));
+        }
+
         let elem = BytesStart::new("BaseCalculo");
         events.push(Event::Start(elem));
 
-        let elem = BytesText::new(self.valor_servicos.as_str());
+        let elem = BytesText::from_escaped(self.base_calculo.to_string());
         events.push(Event::Text(elem));
 
         let elem = BytesEnd::new("BaseCalculo");
@@ -533,7 +1179,7 @@ impl Rps {
             let elem = BytesStart::new("Aliquota");
             events.push(Event::Start(elem));
 
-            let elem = BytesText::new(aliquota_iss.as_str());
+            let elem = BytesText::from_escaped(aliquota_iss.to_string());
             events.push(Event::Text(elem));
 
             let elem = BytesEnd::new("Aliquota");
@@ -543,12 +1189,34 @@ impl Rps {
         let elem = BytesStart::new("ValorLiquidoNfse");
         events.push(Event::Start(elem));
 
-        let elem = BytesText::new(self.valor_liquido.as_str());
+        let elem = BytesText::from_escaped(self.valor_liquido.to_string());
         events.push(Event::Text(elem));
 
         let elem = BytesEnd::new("ValorLiquidoNfse");
         events.push(Event::End(elem));
 
+        for (name, value) in [
+            (
+                "DescontoIncondicionado",
+                &self.retencoes.desconto_incondicionado,
+            ),
+            (
+                "DescontoCondicionado",
+                &self.retencoes.desconto_condicionado,
+            ),
+        ] {
+            if let Some(value) = value {
+                let elem = BytesStart::new(name);
+                events.push(Event::Start(elem));
+
+                let elem = BytesText::from_escaped(value.to_string());
+                events.push(Event::Text(elem));
+
+                let elem = BytesEnd::new(name);
+                events.push(Event::End(elem));
+            }
+        }
+
         let elem = BytesEnd::new("Valores");
         events.push(Event::End(elem));
 
@@ -618,24 +1286,16 @@ impl Rps {
         let elem = BytesStart::new("Tomador");
         events.push(Event::Start(elem));
 
-        if let Some(cnpj_tomador) = &self.cnpj_tomador {
+        if self.cpf_cnpj_tomador.is_some() || self.inscricao_municipal_tomador.is_some() {
             let elem = BytesStart::new("IdentificacaoTomador");
             events.push(Event::Start(elem));
 
-            let elem = BytesStart::new("CpfCnpj");
-            events.push(Event::Start(elem));
-
-            let elem = BytesStart::new("Cnpj");
-            events.push(Event::Start(elem));
-
-            let elem = BytesText::new(cnpj_tomador.as_str());
-            events.push(Event::Text(elem));
-
-            let elem = BytesEnd::new("Cnpj");
-            events.push(Event::End(elem));
-
-            let elem = BytesEnd::new("CpfCnpj");
-            events.push(Event::End(elem));
+            if let Some(cpf_cnpj_tomador) = &self.cpf_cnpj_tomador {
+                cpf_cnpj_tomador
+                    .xml_events()
+                    .into_iter()
+                    .for_each(|e| events.push(e));
+            }
 
             if let Some(inscricao_municipal_tomador) = &self.inscricao_municipal_tomador {
                 let elem = BytesStart::new("InscricaoMunicipal");
@@ -661,81 +1321,59 @@ impl Rps {
         let elem = BytesEnd::new("RazaoSocial");
         events.push(Event::End(elem));
 
-        let elem = BytesStart::new("Endereco");
-        events.push(Event::Start(elem));
-
-        let elem = BytesStart::new("Endereco");
-        events.push(Event::Start(elem));
+        if let Some(endereco_tomador) = &self.endereco_tomador {
+            endereco_tomador
+                .xml_events()
+                .into_iter()
+                .for_each(|e| events.push(e));
+        }
 
-        let elem = BytesText::new(self.logradouro_tomador.as_str());
-        events.push(Event::Text(elem));
+        if self.telefone_tomador.is_some() || self.email_tomador.is_some() {
+            let elem = BytesStart::new("Contato");
+            events.push(Event::Start(elem));
 
-        let elem = BytesEnd::new("Endereco");
-        events.push(Event::End(elem));
+            if let Some(telefone_tomador) = &self.telefone_tomador {
+                let elem = BytesStart::new("Telefone");
+                events.push(Event::Start(elem));
 
-        let elem = BytesStart::new("Numero");
-        events.push(Event::Start(elem));
+                let elem = BytesText::new(telefone_tomador.as_str());
+                events.push(Event::Text(elem));
 
-        let elem = BytesText::new(self.numero_tomador.as_str());
-        events.push(Event::Text(elem));
+                let elem = BytesEnd::new("Telefone");
+                events.push(Event::End(elem));
+            }
 
-        let elem = BytesEnd::new("Numero");
-        events.push(Event::End(elem));
+            if let Some(email_tomador) = &self.email_tomador {
+                let elem = BytesStart::new("Email");
+                events.push(Event::Start(elem));
 
-        if let Some(complemento_tomador) = &self.complemento_tomador {
-            let elem = BytesStart::new("Complemento");
-            events.push(Event::Start(elem));
+                let elem = BytesText::new(email_tomador.as_str());
+                events.push(Event::Text(elem));
 
-            let elem = BytesText::new(complemento_tomador.as_str());
-            events.push(Event::Text(elem));
+                let elem = BytesEnd::new("Email");
+                events.push(Event::End(elem));
+            }
 
-            let elem = BytesEnd::new("Complemento");
+            let elem = BytesEnd::new("Contato");
             events.push(Event::End(elem));
         }
 
-        let elem = BytesStart::new("Bairro");
-        events.push(Event::Start(elem));
-
-        let elem = BytesText::new(self.bairro_tomador.as_str());
-        events.push(Event::Text(elem));
-
-        let elem = BytesEnd::new("Bairro");
-        events.push(Event::End(elem));
-
-        let elem = BytesStart::new("CodigoMunicipio");
-        events.push(Event::Start(elem));
-
-        let elem = BytesText::new(self.codigo_municipio_tomador.as_str());
-        events.push(Event::Text(elem));
-
-        let elem = BytesEnd::new("CodigoMunicipio");
-        events.push(Event::End(elem));
-
-        let elem = BytesStart::new("Uf");
-        events.push(Event::Start(elem));
-
-        let elem = BytesText::new(self.uf_tomador.as_str());
-        events.push(Event::Text(elem));
-
-        let elem = BytesEnd::new("Uf");
+        let elem = BytesEnd::new("Tomador");
         events.push(Event::End(elem));
 
-        if let Some(cep_tomador) = &self.cep_tomador {
-            let elem = BytesStart::new("Cep");
-            events.push(Event::Start(elem));
-
-            let elem = BytesText::new(cep_tomador.as_str());
-            events.push(Event::Text(elem));
-
-            let elem = BytesEnd::new("Cep");
-            events.push(Event::End(elem));
+        if let Some(intermediario_servico) = &self.intermediario_servico {
+            intermediario_servico
+                .xml_events()
+                .into_iter()
+                .for_each(|e| events.push(e));
         }
 
-        let elem = BytesEnd::new("Endereco");
-        events.push(Event::End(elem));
-
-        let elem = BytesEnd::new("Tomador");
-        events.push(Event::End(elem));
+        if let Some(construcao_civil) = &self.construcao_civil {
+            construcao_civil
+                .xml_events()
+                .into_iter()
+                .for_each(|e| events.push(e));
+        }
 
         let elem = BytesEnd::new("InfRps");
         events.push(Event::End(elem));
@@ -745,7 +1383,7 @@ impl Rps {
 }
 
 impl Rps {
-    pub fn rps_xml_events(&self, xmlns: bool) -> Vec<Event> {
+    pub fn rps_xml_events(&self, xmlns: bool) -> Vec<Event<'_>> {
         let mut events = Vec::new();
 
         let mut elem = BytesStart::new("Rps");
@@ -773,24 +1411,67 @@ impl Rps {
     }
 }
 
+impl Rps {
+    pub fn identificacao(&self) -> IdentificacaoRps {
+        IdentificacaoRps {
+            numero: self.id.clone(),
+            serie: self.configuracao.serie.clone(),
+            tipo: self.configuracao.tipo.clone(),
+        }
+    }
+}
+
 impl Rps {
     pub fn uniquely_identify(&self) -> String {
         format!(
             "{}|{}|{}",
             self.razao_social_tomador.clone(),
             self.discriminacao.clone(),
-            self.valor_servicos.clone()
+            self.valor_servicos
         )
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use crate::algorithms::CanonicalizationAlgorithm;
     use crate::algorithms::DigestAlgorithm;
     use crate::algorithms::SignatureAlgorithm;
+    use crate::error::Error;
     use crate::utils;
+    use crate::valor::Aliquota;
+    use crate::valor::Valor;
+
+    // the fields every nota needs, followed by the ones the test is about
+    fn rps(extra: &str, padrao: &super::ConfiguracaoRps) -> Result<super::Rps, Error> {
+        let yaml = serde_yaml::from_str(&format!(
+            "
+id: 1234
+nome_arquivo: ACME_1234
+competencia: data_emissao
+natureza_operacao: natureza_operacao
+regime_especial_tributacao: regime_especial_tributacao
+optante_simples_nacional: optante_simples_nacional
+incentivador_cultural: incentivador_cultural
+item_lista_servico: item_lista_servico
+codigo_tributacao_municipio: codigo_tributacao_municipio
+discriminacao: discriminacao
+valor_servicos: 1000.00
+razao_social: razao_social_tomador
+{extra}
+"
+        ))
+        .unwrap();
+
+        super::Rps::from_yaml(
+            yaml,
+            String::from("cnpj_prestador"),
+            String::from("inscricao_municipal_prestador"),
+            String::from("codigo_municipio_prestador"),
+            padrao,
+            &|_, _, _| unreachable!(),
+        )
+    }
 
     #[test]
     fn should_create_signed_rps() {
@@ -834,16 +1515,285 @@ cep: cep_tomador
             String::from("cnpj_prestador"),
             String::from("inscricao_municipal_prestador"),
             String::from("codigo_municipio_prestador"),
+            &super::ConfiguracaoRps::default(),
+            &|_, _, _| unreachable!(),
         )
         .unwrap();
 
-        rps.sign(signature);
+        rps.sign(signature).unwrap();
 
         assert_eq!(
             utils::xml_events_to_xml_string(&rps.rps_xml_events(true)),
             String::from(
-                r##"<Rps xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><InfRps Id="1234" versao="1.00"><IdentificacaoRps><Numero>1234</Numero><Serie>1</Serie><Tipo>1</Tipo></IdentificacaoRps><DataEmissao>data_emissao</DataEmissao><NaturezaOperacao>natureza_operacao</NaturezaOperacao><RegimeEspecialTributacao>regime_especial_tributacao</RegimeEspecialTributacao><OptanteSimplesNacional>optante_simples_nacional</OptanteSimplesNacional><IncentivadorCultural>incentivador_cultural</IncentivadorCultural><Status>1</Status><Servico><Valores><ValorServicos>1000.00</ValorServicos><IssRetido>1</IssRetido><ValorIss>20.00</ValorIss><ValorIssRetido>20.00</ValorIssRetido><BaseCalculo>1000.00</BaseCalculo><Aliquota>0.02</Aliquota><ValorLiquidoNfse>980.00</ValorLiquidoNfse></Valores><ItemListaServico>item_lista_servico</ItemListaServico><CodigoTributacaoMunicipio>codigo_tributacao_municipio</CodigoTributacaoMunicipio><Discriminacao>discriminacao</Discriminacao><CodigoMunicipio>codigo_municipio_prestador</CodigoMunicipio></Servico><Prestador><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal></Prestador><Tomador><IdentificacaoTomador><CpfCnpj><Cnpj>cnpj_tomador</Cnpj></CpfCnpj><InscricaoMunicipal>inscricao_municipal_tomador</InscricaoMunicipal></IdentificacaoTomador><RazaoSocial>razao_social_tomador</RazaoSocial><Endereco><Endereco>logradouro_tomador</Endereco><Numero>numero_tomador</Numero><Complemento>complemento_tomador</Complemento><Bairro>bairro_tomador</Bairro><CodigoMunicipio>codigo_municipio_tomador</CodigoMunicipio><Uf>uf_tomador</Uf><Cep>cep_tomador</Cep></Endereco></Tomador></InfRps><Signature xmlns="http://www.w3.org/2000/09/xmldsig#"><SignedInfo><CanonicalizationMethod Algorithm="noop-c14n"></CanonicalizationMethod><SignatureMethod Algorithm="echo-signature"></SignatureMethod><Reference URI="#1234"><Transforms><Transform Algorithm="noop-c14n"></Transform></Transforms><DigestMethod Algorithm="echo-digest"></DigestMethod><DigestValue>the_digest</DigestValue></Reference></SignedInfo><SignatureValue>the_signature</SignatureValue><KeyInfo><X509Data><X509Certificate>the_certificate</X509Certificate></X509Data></KeyInfo></Signature></Rps>"##
+                r##"<Rps xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><InfRps Id="rps1_1_1234" versao="1.00"><IdentificacaoRps><Numero>1234</Numero><Serie>1</Serie><Tipo>1</Tipo></IdentificacaoRps><DataEmissao>data_emissao</DataEmissao><NaturezaOperacao>natureza_operacao</NaturezaOperacao><RegimeEspecialTributacao>regime_especial_tributacao</RegimeEspecialTributacao><OptanteSimplesNacional>optante_simples_nacional</OptanteSimplesNacional><IncentivadorCultural>incentivador_cultural</IncentivadorCultural><Status>1</Status><Servico><Valores><ValorServicos>1000.00</ValorServicos><IssRetido>1</IssRetido><ValorIss>20.00</ValorIss><ValorIssRetido>20.00</ValorIssRetido><BaseCalculo>1000.00</BaseCalculo><Aliquota>0.02</Aliquota><ValorLiquidoNfse>980.00</ValorLiquidoNfse></Valores><ItemListaServico>item_lista_servico</ItemListaServico><CodigoTributacaoMunicipio>codigo_tributacao_municipio</CodigoTributacaoMunicipio><Discriminacao>discriminacao</Discriminacao><CodigoMunicipio>codigo_municipio_prestador</CodigoMunicipio></Servico><Prestador><Cnpj>cnpj_prestador</Cnpj><InscricaoMunicipal>inscricao_municipal_prestador</InscricaoMunicipal></Prestador><Tomador><IdentificacaoTomador><CpfCnpj><Cnpj>cnpj_tomador</Cnpj></CpfCnpj><InscricaoMunicipal>inscricao_municipal_tomador</InscricaoMunicipal></IdentificacaoTomador><RazaoSocial>razao_social_tomador</RazaoSocial><Endereco><Endereco>logradouro_tomador</Endereco><Numero>numero_tomador</Numero><Complemento>complemento_tomador</Complemento><Bairro>bairro_tomador</Bairro><CodigoMunicipio>codigo_municipio_tomador</CodigoMunicipio><Uf>uf_tomador</Uf><Cep>cep_tomador</Cep></Endereco></Tomador></InfRps><Signature xmlns="http://www.w3.org/2000/09/xmldsig#"><SignedInfo><CanonicalizationMethod Algorithm="noop-c14n"></CanonicalizationMethod><SignatureMethod Algorithm="echo-signature"></SignatureMethod><Reference URI="#rps1_1_1234"><Transforms><Transform Algorithm="noop-c14n"></Transform></Transforms><DigestMethod Algorithm="echo-digest"></DigestMethod><DigestValue>the_digest</DigestValue></Reference></SignedInfo><SignatureValue>the_signature</SignatureValue><KeyInfo><X509Data><X509Certificate>the_certificate</X509Certificate></X509Data></KeyInfo></Signature></Rps>"##
+            )
+        );
+    }
+
+    #[test]
+    fn should_emit_rps_substituido() {
+        let padrao = super::ConfiguracaoRps::default();
+
+        let xml = utils::xml_events_to_xml_string(
+            &rps("rps_substituido:\n  numero: 1233", &padrao)
+                .unwrap()
+                .rps_xml_events(false),
+        );
+
+        assert!(xml.contains(
+            "<Status>1</Status><RpsSubstituido><Numero>1233</Numero><Serie>1</Serie><Tipo>1</Tipo></RpsSubstituido><Servico>"
+        ));
+
+        assert_eq!(
+            rps("rps_substituido:\n  serie: 1", &padrao)
+                .err()
+                .map(|e| e.to_string()),
+            Some(String::from("bad input: rps_substituido.numero: required"))
+        );
+    }
+
+    #[test]
+    fn should_emit_tomador_pessoa_fisica_without_endereco() {
+        let padrao = super::ConfiguracaoRps::default();
+
+        let xml = utils::xml_events_to_xml_string(
+            &rps(
+                "cpf: cpf_tomador\ntelefone: telefone_tomador\nemail: email_tomador",
+                &padrao,
+            )
+            .unwrap()
+            .rps_xml_events(false),
+        );
+
+        assert!(xml.contains(
+            "<Tomador><IdentificacaoTomador><CpfCnpj><Cpf>cpf_tomador</Cpf></CpfCnpj></IdentificacaoTomador><RazaoSocial>razao_social_tomador</RazaoSocial><Contato><Telefone>telefone_tomador</Telefone><Email>email_tomador</Email></Contato></Tomador>"
+        ));
+
+        // a foreign tomador gives only part of the address
+        let xml = utils::xml_events_to_xml_string(
+            &rps(
+                "cpf: cpf_tomador\nlogradouro: logradouro_tomador\ncep: cep_tomador",
+                &padrao,
             )
+            .unwrap()
+            .rps_xml_events(false),
+        );
+
+        assert!(xml.contains(
+            "<RazaoSocial>razao_social_tomador</RazaoSocial><Endereco><Endereco>logradouro_tomador</Endereco><Cep>cep_tomador</Cep></Endereco></Tomador>"
+        ));
+    }
+
+    #[test]
+    fn should_compute_base_calculo_and_valor_liquido_with_retencoes() {
+        let rps = rps(
+            "
+aliquota_iss: 0.02
+valor_deducoes: 100
+valor_pis: 6.5
+valor_cofins: 30
+valor_inss: 110
+valor_ir: 15
+valor_csll: 10
+outras_retencoes: 5
+desconto_incondicionado: 50
+desconto_condicionado: 20
+cnpj: cnpj_tomador",
+            &super::ConfiguracaoRps::default(),
+        )
+        .unwrap();
+
+        assert!(utils::xml_events_to_xml_string(&rps.rps_xml_events(false)).contains(
+            "<Valores><ValorServicos>1000.00</ValorServicos><ValorDeducoes>100.00</ValorDeducoes><ValorPis>6.50</ValorPis><ValorCofins>30.00</ValorCofins><ValorInss>110.00</ValorInss><ValorIr>15.00</ValorIr><ValorCsll>10.00</ValorCsll><IssRetido>1</IssRetido><ValorIss>17.00</ValorIss><ValorIssRetido>17.00</ValorIssRetido><OutrasRetencoes>5.00</OutrasRetencoes><BaseCalculo>850.00</BaseCalculo><Aliquota>0.02</Aliquota><ValorLiquidoNfse>736.50</ValorLiquidoNfse><DescontoIncondicionado>50.00</DescontoIncondicionado><DescontoCondicionado>20.00</DescontoCondicionado></Valores>"
+        ));
+    }
+
+    #[test]
+    fn should_only_withhold_iss_when_iss_retido() {
+        let padrao = super::ConfiguracaoRps::default();
+
+        assert!(utils::xml_events_to_xml_string(
+            &rps("aliquota_iss: 0.02\niss_retido: false", &padrao)
+                .unwrap()
+                .rps_xml_events(false)
+        )
+        .contains("<Valores><ValorServicos>1000.00</ValorServicos><IssRetido>2</IssRetido><ValorIss>20.00</ValorIss><BaseCalculo>1000.00</BaseCalculo><Aliquota>0.02</Aliquota><ValorLiquidoNfse>1000.00</ValorLiquidoNfse></Valores>"));
+
+        assert!(utils::xml_events_to_xml_string(
+            &rps("aliquota_iss: 0.02\niss_retido: 1", &padrao)
+                .unwrap()
+                .rps_xml_events(false)
+        )
+        .contains("<Valores><ValorServicos>1000.00</ValorServicos><IssRetido>1</IssRetido><ValorIss>20.00</ValorIss><ValorIssRetido>20.00</ValorIssRetido><BaseCalculo>1000.00</BaseCalculo><Aliquota>0.02</Aliquota><ValorLiquidoNfse>980.00</ValorLiquidoNfse></Valores>"));
+
+        assert_eq!(
+            rps("iss_retido: true", &padrao)
+                .err()
+                .map(|e| e.to_string()),
+            Some(String::from(
+                "bad input: aliquota_iss: required when iss_retido"
+            ))
+        );
+    }
+
+    #[test]
+    fn should_emit_intermediario_servico_and_construcao_civil_after_tomador() {
+        let padrao = super::ConfiguracaoRps::default();
+
+        assert!(utils::xml_events_to_xml_string(
+            &rps("intermediario_servico:\n  razao_social: razao_social_intermediario\n  cpf: cpf_intermediario\n  inscricao_municipal: inscricao_municipal_intermediario\nconstrucao_civil:\n  codigo_obra: codigo_obra\n  art: art", &padrao)
+                .unwrap()
+                .rps_xml_events(false)
+        )
+        .contains("<RazaoSocial>razao_social_tomador</RazaoSocial></Tomador><IntermediarioServico><RazaoSocial>razao_social_intermediario</RazaoSocial><CpfCnpj><Cpf>cpf_intermediario</Cpf></CpfCnpj><InscricaoMunicipal>inscricao_municipal_intermediario</InscricaoMunicipal></IntermediarioServico><ConstrucaoCivil><CodigoObra>codigo_obra</CodigoObra><Art>art</Art></ConstrucaoCivil></InfRps>"));
+
+        assert_eq!(
+            rps("construcao_civil:\n  codigo_obra: codigo_obra", &padrao)
+                .err()
+                .map(|e| e.to_string()),
+            Some(String::from("bad input: construcao_civil.art: required"))
+        );
+    }
+
+    #[test]
+    fn should_use_serie_tipo_and_status_of_the_nota_or_of_the_prestador() {
+        let padrao = super::ConfiguracaoRps::from_yaml(
+            &serde_yaml::from_str("serie: FIL2\ntipo: 2").unwrap(),
+            &super::ConfiguracaoRps::default(),
+        )
+        .unwrap();
+
+        let xml = utils::xml_events_to_xml_string(
+            &rps("status: 2", &padrao).unwrap().rps_xml_events(false),
+        );
+        assert!(xml.contains("<IdentificacaoRps><Numero>1234</Numero><Serie>FIL2</Serie><Tipo>2</Tipo></IdentificacaoRps>"));
+        assert!(xml.contains("<Status>2</Status>"));
+
+        let xml = utils::xml_events_to_xml_string(
+            &rps("tipo: 3", &padrao).unwrap().rps_xml_events(false),
+        );
+        assert!(xml.contains("<Serie>FIL2</Serie><Tipo>3</Tipo>"));
+        assert!(xml.contains("<Status>1</Status>"));
+
+        assert_eq!(
+            rps("tipo: 4", &padrao).err().map(|e| e.to_string()),
+            Some(String::from(
+                "bad input: tipo: expected a value from 1 to 3"
+            ))
+        );
+        assert_eq!(
+            rps("serie: FILIAL", &padrao).err().map(|e| e.to_string()),
+            Some(String::from(
+                "bad input: serie: expected 1 to 5 letters or digits"
+            ))
+        );
+        assert_eq!(
+            rps("serie: \"A:1\"", &padrao).err().map(|e| e.to_string()),
+            Some(String::from(
+                "bad input: serie: expected 1 to 5 letters or digits"
+            ))
         );
     }
+
+    proptest::proptest! {
+        #[test]
+        fn valor_liquido_should_equal_valor_servicos_minus_its_parts(
+            valor_servicos in 0_i64..100_000_000,
+            aliquota in proptest::option::of(0_i64..=10_000),
+            iss_retido in proptest::option::of(proptest::bool::ANY),
+            retencoes in proptest::collection::vec(proptest::option::of(0_i64..1_000_000), 9),
+        ) {
+            let valor = |i: usize| retencoes[i].map(Valor::from_centavos);
+
+            let rps = super::Rps::new(
+                String::from("1"),
+                String::from("ACME_1"),
+                String::from("data_emissao"),
+                String::from("natureza_operacao"),
+                String::from("regime_especial_tributacao"),
+                String::from("optante_simples_nacional"),
+                String::from("incentivador_cultural"),
+                String::from("item_lista_servico"),
+                String::from("codigo_tributacao_municipio"),
+                String::from("discriminacao"),
+                String::from("codigo_municipio"),
+                Valor::from_centavos(valor_servicos),
+                aliquota.map(Aliquota::from_decimos_milesimos),
+                iss_retido,
+                super::Retencoes {
+                    valor_deducoes: valor(0),
+                    valor_pis: valor(1),
+                    valor_cofins: valor(2),
+                    valor_inss: valor(3),
+                    valor_ir: valor(4),
+                    valor_csll: valor(5),
+                    outras_retencoes: valor(6),
+                    desconto_incondicionado: valor(7),
+                    desconto_condicionado: valor(8),
+                },
+                String::from("cnpj_prestador"),
+                String::from("inscricao_municipal_prestador"),
+                None,
+                None,
+                String::from("razao_social_tomador"),
+                None,
+                None,
+                None,
+            );
+
+            let centavos = |i: usize| retencoes[i].unwrap_or(0);
+            let base_calculo = valor_servicos - centavos(0) - centavos(7);
+            let iss_retido = iss_retido.unwrap_or(aliquota.is_some());
+            let valor_iss_retido = match aliquota {
+                Some(aliquota) if iss_retido && base_calculo >= 0 => Valor::from_centavos(base_calculo)
+                    .multiplicar(Aliquota::from_decimos_milesimos(aliquota))
+                    .centavos(),
+                _ => 0,
+            };
+            if base_calculo < 0
+                || (iss_retido && aliquota.is_none())
+                || valor_servicos < (1..9).map(centavos).sum::<i64>() + valor_iss_retido
+            {
+                proptest::prop_assert!(rps.is_err());
+                return Ok(());
+            }
+            let rps = rps.unwrap();
+
+            // the values as emitted, so formatting is covered as well
+            let xml = utils::xml_events_to_xml_string(&rps.rps_xml_events(false));
+            let emitido = |name: &str| {
+                xml.split_once(&format!("<{name}>"))
+                    .and_then(|(_, rest)| rest.split_once(&format!("</{name}>")))
+                    .map(|(it, _)| it.parse::<Valor>().unwrap())
+                    .unwrap_or_default()
+            };
+
+            proptest::prop_assert_eq!(
+                emitido("BaseCalculo"),
+                emitido("ValorServicos") - emitido("ValorDeducoes") - emitido("DescontoIncondicionado")
+            );
+            proptest::prop_assert_eq!(
+                emitido("ValorLiquidoNfse"),
+                emitido("ValorServicos")
+                    - [
+                        "ValorPis",
+                        "ValorCofins",
+                        "ValorInss",
+                        "ValorIr",
+                        "ValorCsll",
+                        "OutrasRetencoes",
+                        "ValorIssRetido",
+                        "DescontoIncondicionado",
+                        "DescontoCondicionado",
+                    ]
+                    .iter()
+                    .map(|name| emitido(name))
+                    .sum::<Valor>()
+            );
+            proptest::prop_assert_eq!(
+                emitido("ValorLiquidoNfse").centavos(),
+                valor_servicos - (1..9).map(centavos).sum::<i64>() - valor_iss_retido
+            );
+        }
+    }
 }
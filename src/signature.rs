@@ -2,12 +2,19 @@ use quick_xml::events::BytesEnd;
 use quick_xml::events::BytesStart;
 use quick_xml::events::BytesText;
 use quick_xml::events::Event;
+use quick_xml::reader::Reader;
 
 use crate::algorithms::CanonicalizationAlgorithm;
 use crate::algorithms::DigestAlgorithm;
 use crate::algorithms::SignatureAlgorithm;
+use crate::c14n::Namespaces;
+use crate::certificate::Certificate;
+use crate::error::Error;
 use crate::utils;
 
+const XMLDSIG_NAMESPACE: &str = "http://www.w3.org/2000/09/xmldsig#";
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
 #[derive(Clone)]
 pub struct XmlSignature {
     canonicalization_method: CanonicalizationAlgorithm,
@@ -48,10 +55,13 @@ impl XmlSignature {
 }
 
 impl XmlSignature {
-    pub fn sign(&mut self) {
-        let payload = self.payload.clone().expect("cannot sign without payload");
+    pub fn sign(&mut self) -> Result<(), Error> {
+        let payload = self
+            .payload
+            .clone()
+            .ok_or(Error::signing("cannot sign without payload"))?;
 
-        let payload = self.canonicalization_method.run(payload.to_owned());
+        let payload = self.canonicalization_method.run(payload)?;
 
         let digest_value = self.digest_method.run(payload);
 
@@ -59,16 +69,20 @@ impl XmlSignature {
 
         let signed_info_xml_events = self.signed_info_xml_events(true);
 
-        let signed_info_xml_string = utils::xml_events_to_xml_string(&signed_info_xml_events);
+        let signed_info_xml_string = self
+            .canonicalization_method
+            .run(utils::xml_events_to_xml_string(&signed_info_xml_events))?;
 
-        let signature_value = self.signature_method.run(signed_info_xml_string);
+        let signature_value = self.signature_method.run(signed_info_xml_string)?;
 
         self.signature_value = Some(signature_value);
+
+        Ok(())
     }
 }
 
 impl XmlSignature {
-    fn signed_info_xml_events(&self, xmlns: bool) -> Vec<Event> {
+    fn signed_info_xml_events(&self, xmlns: bool) -> Vec<Event<'_>> {
         let digest_value = self.digest_value.as_ref().expect("need to sign first");
 
         let mut events = Vec::new();
@@ -137,7 +151,7 @@ impl XmlSignature {
 }
 
 impl XmlSignature {
-    pub fn signature_xml_events(&self) -> Vec<Event> {
+    pub fn signature_xml_events(&self) -> Vec<Event<'_>> {
         let signature_value = self.signature_value.as_ref().expect("need to sign first");
 
         let mut events = Vec::new();
@@ -187,6 +201,342 @@ impl XmlSignature {
     }
 }
 
+#[derive(PartialEq, Debug)]
+pub struct SignatureVerification {
+    pub reference_uri: String,
+    pub certificate: String,
+    pub digest_valid: bool,
+    pub signature_valid: bool,
+}
+
+impl SignatureVerification {
+    pub fn is_valid(&self) -> bool {
+        self.digest_valid && self.signature_valid
+    }
+}
+
+impl SignatureVerification {
+    pub fn from_xml_string(xml: &str) -> Result<Vec<Self>, Error> {
+        let elements = XmlElement::scan(xml)?;
+
+        let signatures = elements
+            .iter()
+            .filter(|e| e.local_name() == "Signature" && e.namespace() == Some(XMLDSIG_NAMESPACE))
+            .collect::<Vec<_>>();
+
+        if signatures.is_empty() {
+            return Err(Error::signing("no signature found"));
+        }
+
+        signatures
+            .into_iter()
+            .map(|signature| Self::verify(xml, &elements, signature))
+            .collect()
+    }
+}
+
+impl SignatureVerification {
+    fn verify(xml: &str, elements: &[XmlElement], signature: &XmlElement) -> Result<Self, Error> {
+        let signed_info = signature.child(elements, "SignedInfo")?;
+
+        let canonicalization_method = signed_info
+            .child(elements, "CanonicalizationMethod")?
+            .attribute("Algorithm")?;
+        let canonicalization_method = CanonicalizationAlgorithm::from_uri(canonicalization_method)
+            .ok_or(Error::signing(format!(
+                "unsupported canonicalization algorithm: {canonicalization_method}"
+            )))?;
+
+        let signature_method = signed_info
+            .child(elements, "SignatureMethod")?
+            .attribute("Algorithm")?;
+
+        // a single Reference, so no element outside of it can pass as signed
+        let reference = signed_info.child(elements, "Reference")?;
+        let reference_uri = reference.attribute("URI")?;
+
+        let digest_method = reference
+            .child(elements, "DigestMethod")?
+            .attribute("Algorithm")?;
+        let digest_method = DigestAlgorithm::from_uri(digest_method).ok_or(Error::signing(
+            format!("unsupported digest algorithm: {digest_method}"),
+        ))?;
+
+        let digest_value = reference.child(elements, "DigestValue")?.text.trim();
+        let signature_value = signature.child(elements, "SignatureValue")?.text.as_str();
+        let certificate = signature
+            .child(elements, "KeyInfo")?
+            .child(elements, "X509Data")?
+            .child(elements, "X509Certificate")?
+            .text
+            .as_str();
+
+        let referenced = match reference_uri.strip_prefix('#') {
+            // a second element with the same Id could be verified in place of the one read
+            Some(id) => {
+                let mut referenced = elements
+                    .iter()
+                    .filter(|e| e.attribute("Id").ok() == Some(id));
+                match (referenced.next(), referenced.next()) {
+                    (Some(referenced), None) => referenced,
+                    (Some(_), Some(_)) => {
+                        return Err(Error::signing(format!(
+                            "more than one element with Id {id}"
+                        )))
+                    }
+                    (None, _) => {
+                        return Err(Error::signing(format!(
+                            "referenced element not found: {reference_uri}"
+                        )))
+                    }
+                }
+            }
+            None if reference_uri.is_empty() => elements
+                .first()
+                .ok_or(Error::signing("referenced element not found"))?,
+            None => {
+                return Err(Error::signing(format!(
+                    "unsupported reference: {reference_uri}"
+                )))
+            }
+        };
+
+        let mut payload_canonicalization = CanonicalizationAlgorithm::InclusiveXMLCanonicalization;
+        let mut enveloped = false;
+        for transform in reference
+            .children(elements, "Transforms")
+            .flat_map(|transforms| transforms.children(elements, "Transform"))
+        {
+            let algorithm = transform.attribute("Algorithm")?;
+            if algorithm == ENVELOPED_SIGNATURE {
+                enveloped = true;
+            } else {
+                payload_canonicalization = CanonicalizationAlgorithm::from_uri(algorithm).ok_or(
+                    Error::signing(format!("unsupported transform: {algorithm}")),
+                )?;
+            }
+        }
+
+        let payload = if enveloped && signature.is_within(referenced) {
+            format!(
+                "{}{}",
+                &xml[referenced.start..signature.start],
+                &xml[signature.end..referenced.end]
+            )
+        } else {
+            xml[referenced.start..referenced.end].to_owned()
+        };
+
+        let payload =
+            payload_canonicalization.run_in_context(payload, &referenced.inherited_namespaces)?;
+
+        let digest_valid = digest_method.run(payload) == digest_value;
+
+        let signed_info_xml_string = canonicalization_method.run_in_context(
+            xml[signed_info.start..signed_info.end].to_owned(),
+            &signed_info.inherited_namespaces,
+        )?;
+
+        let public_key = Certificate::from_base64(certificate)?.public_key()?;
+
+        let signature_value = signature_value
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>();
+
+        let signature_valid = SignatureAlgorithm::verify(
+            signature_method,
+            &public_key,
+            signed_info_xml_string,
+            &signature_value,
+        )?;
+
+        Ok(Self {
+            reference_uri: reference_uri.to_owned(),
+            certificate: certificate.trim().to_owned(),
+            digest_valid,
+            signature_valid,
+        })
+    }
+}
+
+struct XmlElement {
+    index: usize,
+    parent: Option<usize>,
+    name: String,
+    attributes: Vec<(String, String)>,
+    namespaces: Namespaces,
+    inherited_namespaces: Namespaces,
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+impl XmlElement {
+    fn scan(xml: &str) -> Result<Vec<Self>, Error> {
+        let mut reader = Reader::from_str(xml);
+
+        let mut elements: Vec<XmlElement> = Vec::new();
+        let mut open: Vec<usize> = Vec::new();
+
+        loop {
+            let position = reader.buffer_position();
+            match reader.read_event() {
+                Ok(Event::Start(e)) => {
+                    elements.push(Self::open(&e, &elements, &open, position)?);
+                    open.push(elements.len() - 1);
+                }
+                Ok(Event::Empty(e)) => {
+                    let mut element = Self::open(&e, &elements, &open, position)?;
+                    element.end = reader.buffer_position();
+                    elements.push(element);
+                }
+                Ok(Event::End(_)) => {
+                    let index = open.pop().ok_or(Error::xml("unbalanced end tag"))?;
+                    elements[index].end = reader.buffer_position();
+                }
+                Ok(Event::Text(e)) => {
+                    if let Some(index) = open.last() {
+                        let text = e.unescape()?;
+                        elements[*index].text.push_str(&text);
+                    }
+                }
+                Ok(Event::CData(e)) => {
+                    if let Some(index) = open.last() {
+                        let text = std::str::from_utf8(&e)?;
+                        elements[*index].text.push_str(text);
+                    }
+                }
+                Ok(Event::Eof) => break,
+                Ok(_) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(elements)
+    }
+}
+
+impl XmlElement {
+    fn open(
+        e: &BytesStart,
+        elements: &[XmlElement],
+        open: &[usize],
+        start: usize,
+    ) -> Result<Self, Error> {
+        let name = std::str::from_utf8(e.name().as_ref())?.to_owned();
+
+        let mut attributes = Vec::new();
+        let mut namespaces = Vec::new();
+        for attr in e.attributes() {
+            let attr = attr?;
+            let key = std::str::from_utf8(attr.key.as_ref())?.to_owned();
+            let value = attr.unescape_value()?.into_owned();
+            if key == "xmlns" {
+                namespaces.push((String::new(), value));
+            } else if let Some(prefix) = key.strip_prefix("xmlns:") {
+                namespaces.push((prefix.to_owned(), value));
+            } else {
+                attributes.push((key, value));
+            }
+        }
+
+        let inherited_namespaces = match open.last() {
+            Some(parent) => {
+                let parent = &elements[*parent];
+                let mut inherited = parent
+                    .inherited_namespaces
+                    .iter()
+                    .filter(|(prefix, _)| !parent.namespaces.iter().any(|(p, _)| p == prefix))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                inherited.extend(parent.namespaces.iter().cloned());
+                inherited
+            }
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            index: elements.len(),
+            parent: open.last().copied(),
+            name,
+            attributes,
+            namespaces,
+            inherited_namespaces,
+            text: String::new(),
+            start,
+            end: start,
+        })
+    }
+}
+
+impl XmlElement {
+    fn local_name(&self) -> &str {
+        match self.name.split_once(':') {
+            Some((_, local_name)) => local_name,
+            None => self.name.as_str(),
+        }
+    }
+
+    fn namespace(&self) -> Option<&str> {
+        let prefix = match self.name.split_once(':') {
+            Some((prefix, _)) => prefix,
+            None => "",
+        };
+        self.namespaces
+            .iter()
+            .chain(self.inherited_namespaces.iter())
+            .find(|(p, _)| p == prefix)
+            .map(|(_, uri)| uri.as_str())
+    }
+
+    fn attribute(&self, key: &str) -> Result<&str, Error> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .ok_or(Error::xml(format!(
+                "missing attribute {key} in {}",
+                self.name
+            )))
+    }
+
+    fn is_within(&self, other: &XmlElement) -> bool {
+        self.start > other.start && self.end <= other.end
+    }
+
+    // direct XMLDSig children named local_name
+    fn children<'a>(
+        &self,
+        elements: &'a [XmlElement],
+        local_name: &'a str,
+    ) -> impl Iterator<Item = &'a XmlElement> + 'a {
+        let index = self.index;
+        elements.iter().filter(move |e| {
+            e.parent == Some(index)
+                && e.local_name() == local_name
+                && e.namespace() == Some(XMLDSIG_NAMESPACE)
+        })
+    }
+
+    // the only direct XMLDSig child named local_name
+    fn child<'a>(
+        &self,
+        elements: &'a [XmlElement],
+        local_name: &'a str,
+    ) -> Result<&'a XmlElement, Error> {
+        let mut children = self.children(elements, local_name);
+        match (children.next(), children.next()) {
+            (Some(child), None) => Ok(child),
+            (Some(_), Some(_)) => Err(Error::signing(format!(
+                "more than one {local_name} in {}",
+                self.name
+            ))),
+            (None, _) => Err(Error::xml(format!("missing {local_name} in {}", self.name))),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::algorithms::CanonicalizationAlgorithm;
@@ -194,6 +544,9 @@ mod tests {
     use crate::algorithms::SignatureAlgorithm;
     use crate::utils;
 
+    const CERTIFICATE: &str = include_str!("../tests/fixtures/certificado.cer");
+    const PRIVATE_KEY: &str = include_str!("../tests/fixtures/certificado.key");
+
     #[test]
     fn should_sign_payload() {
         let mut signature = super::XmlSignature::new(
@@ -206,7 +559,7 @@ mod tests {
             String::from("#URI"),
             String::from("<Payload>To Sign</Payload>"),
         );
-        signature.sign();
+        signature.sign().unwrap();
         assert_eq!(
             utils::xml_events_to_xml_string(&signature.signature_xml_events()),
             String::from(
@@ -214,4 +567,77 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn should_verify_signed_payload() {
+        let private_key =
+            <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_pem(PRIVATE_KEY)
+                .unwrap();
+
+        let mut signature = super::XmlSignature::new(
+            CanonicalizationAlgorithm::ExclusiveXMLCanonicalization,
+            SignatureAlgorithm::RsaSha1(std::sync::Arc::new(private_key)),
+            DigestAlgorithm::Sha1,
+            utils::trim_x509_certificate(CERTIFICATE),
+        );
+        signature.load(
+            String::from("#payload"),
+            String::from(r##"<Payload xmlns="http://www.abrasf.org.br/nfse.xsd" Id="payload">To Sign</Payload>"##),
+        );
+        signature.sign().unwrap();
+
+        let signed = format!(
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="http://www.abrasf.org.br/nfse.xsd">
+  <Payload Id="payload">To Sign</Payload>
+  {}
+</Document>"##,
+            utils::xml_events_to_xml_string(&signature.signature_xml_events())
+        );
+
+        let verifications = super::SignatureVerification::from_xml_string(&signed).unwrap();
+        assert_eq!(
+            verifications,
+            vec![super::SignatureVerification {
+                reference_uri: String::from("#payload"),
+                certificate: utils::trim_x509_certificate(CERTIFICATE),
+                digest_valid: true,
+                signature_valid: true,
+            }]
+        );
+
+        let tampered = signed.replace("To Sign", "To Forge");
+        let verifications = super::SignatureVerification::from_xml_string(&tampered).unwrap();
+        assert!(!verifications[0].digest_valid);
+        assert!(verifications[0].signature_valid);
+        assert!(!verifications[0].is_valid());
+
+        // signature wrapping: a forged element with the same Id next to the signed one
+        let wrapped = signed.replace(
+            "<Payload Id=\"payload\">To Sign</Payload>",
+            "<Payload Id=\"payload\">To Forge</Payload><Wrapper><Payload Id=\"payload\">To Sign</Payload></Wrapper>",
+        );
+        assert_eq!(
+            super::SignatureVerification::from_xml_string(&wrapped)
+                .err()
+                .map(|e| e.to_string()),
+            Some(String::from(
+                "signing error: more than one element with Id payload"
+            ))
+        );
+
+        // a second Reference is not verified, so it is refused
+        let (before, after) = signed.split_once("<Reference ").unwrap();
+        let (reference, after) = after.split_once("</Reference>").unwrap();
+        let two_references = format!(
+            "{before}<Reference {reference}</Reference><Reference {reference}</Reference>{after}"
+        );
+        assert!(super::SignatureVerification::from_xml_string(&two_references).is_err());
+
+        // only direct children count, a Reference nested elsewhere is not the one of SignedInfo
+        let nested = signed
+            .replace("<Reference ", "<Object><Reference ")
+            .replace("</Reference>", "</Reference></Object>");
+        assert!(super::SignatureVerification::from_xml_string(&nested).is_err());
+    }
 }
@@ -0,0 +1,340 @@
+use base64ct::{Base64, Encoding};
+use rsa::signature::{SignatureEncoding, Signer as _};
+use rsa::RsaPrivateKey;
+use sha1::Sha1;
+use sha2::Sha256;
+
+use crate::error::Error;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub fn as_str(&self) -> &str {
+        match &self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+// Produces RSASSA-PKCS1-v1_5 signatures over the payload, hashing it with the given algorithm.
+pub trait Signer: Send + Sync {
+    fn sign(&self, hash_algorithm: HashAlgorithm, payload: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+impl Signer for RsaPrivateKey {
+    fn sign(&self, hash_algorithm: HashAlgorithm, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        match hash_algorithm {
+            HashAlgorithm::Sha1 => {
+                let signing_key = rsa::pkcs1v15::SigningKey::<Sha1>::new(self.to_owned());
+                Ok(signing_key.sign(payload).to_vec())
+            }
+            HashAlgorithm::Sha256 => {
+                let signing_key = rsa::pkcs1v15::SigningKey::<Sha256>::new(self.to_owned());
+                Ok(signing_key.sign(payload).to_vec())
+            }
+        }
+    }
+}
+
+// Talks to a signing daemon with a line based protocol: the request is the hash algorithm and
+// the base64 payload separated by a space, the response is either the base64 signature or
+// "ERROR" followed by a message.
+#[cfg(unix)]
+pub struct UnixSocketSigner {
+    path: String,
+}
+
+#[cfg(unix)]
+impl UnixSocketSigner {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+}
+
+#[cfg(unix)]
+impl Signer for UnixSocketSigner {
+    fn sign(&self, hash_algorithm: HashAlgorithm, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        use std::io::{BufRead, Write};
+
+        let mut stream = std::os::unix::net::UnixStream::connect(&self.path).map_err(|e| {
+            Error::signing(format!("could not connect to signer at {}", self.path)).with_source(e)
+        })?;
+
+        let request = format!(
+            "{} {}\n",
+            hash_algorithm.as_str(),
+            Base64::encode_string(payload)
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| Error::signing("could not write to signer").with_source(e))?;
+
+        let mut response = String::new();
+        std::io::BufReader::new(stream)
+            .read_line(&mut response)
+            .map_err(|e| Error::signing("could not read from signer").with_source(e))?;
+
+        match response.trim_end().split_once(' ') {
+            Some(("ERROR", message)) => Err(Error::signing(format!("signer refused: {message}"))),
+            _ => Base64::decode_vec(response.trim_end())
+                .map_err(|_| Error::signing("bad signature from signer")),
+        }
+    }
+}
+
+#[cfg(feature = "pkcs11")]
+pub struct Pkcs11Signer {
+    session: std::sync::Mutex<cryptoki::session::Session>,
+    key: cryptoki::object::ObjectHandle,
+}
+
+#[cfg(feature = "pkcs11")]
+impl Pkcs11Signer {
+    pub fn new(
+        module: &str,
+        token_label: &str,
+        key_label: &str,
+        pin: String,
+    ) -> Result<Self, Error> {
+        use cryptoki::context::{CInitializeArgs, Pkcs11};
+        use cryptoki::object::{Attribute, ObjectClass};
+        use cryptoki::session::UserType;
+        use cryptoki::types::AuthPin;
+
+        let pkcs11 = Pkcs11::new(module).map_err(|e| {
+            Error::signing(format!("could not load pkcs11 module at {module}")).with_source(e)
+        })?;
+        pkcs11
+            .initialize(CInitializeArgs::OsThreads)
+            .map_err(|e| Error::signing("could not initialize pkcs11 module").with_source(e))?;
+
+        let slot = pkcs11
+            .get_slots_with_token()
+            .map_err(|e| Error::signing("could not list pkcs11 slots").with_source(e))?
+            .into_iter()
+            .find(|slot| {
+                pkcs11
+                    .get_token_info(*slot)
+                    .map(|info| info.label() == token_label)
+                    .unwrap_or(false)
+            })
+            .ok_or(Error::signing(format!(
+                "pkcs11 token not found: {token_label}"
+            )))?;
+
+        let session = pkcs11
+            .open_ro_session(slot)
+            .map_err(|e| Error::signing("could not open pkcs11 session").with_source(e))?;
+        session
+            .login(UserType::User, Some(&AuthPin::new(pin)))
+            .map_err(|e| Error::signing("could not login to pkcs11 token").with_source(e))?;
+
+        let key = session
+            .find_objects(&[
+                Attribute::Class(ObjectClass::PRIVATE_KEY),
+                Attribute::Label(key_label.as_bytes().to_vec()),
+            ])
+            .map_err(|e| Error::signing("could not search pkcs11 token").with_source(e))?
+            .into_iter()
+            .next()
+            .ok_or(Error::signing(format!(
+                "pkcs11 private key not found: {key_label}"
+            )))?;
+
+        Ok(Self {
+            session: std::sync::Mutex::new(session),
+            key,
+        })
+    }
+}
+
+#[cfg(feature = "pkcs11")]
+impl Signer for Pkcs11Signer {
+    fn sign(&self, hash_algorithm: HashAlgorithm, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        use cryptoki::mechanism::Mechanism;
+
+        let mechanism = match hash_algorithm {
+            HashAlgorithm::Sha1 => Mechanism::Sha1RsaPkcs,
+            HashAlgorithm::Sha256 => Mechanism::Sha256RsaPkcs,
+        };
+
+        self.session
+            .lock()
+            .map_err(|_| Error::signing("pkcs11 session is poisoned"))?
+            .sign(&mechanism, self.key, payload)
+            .map_err(|e| Error::signing("could not sign with pkcs11 token").with_source(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashAlgorithm;
+    use super::Signer;
+
+    const PRIVATE_KEY: &str = include_str!("../tests/fixtures/certificado.key");
+
+    #[cfg(unix)]
+    #[test]
+    fn should_sign_through_unix_socket() {
+        use base64ct::{Base64, Encoding};
+        use std::io::{BufRead, Write};
+
+        let private_key =
+            <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_pem(PRIVATE_KEY)
+                .unwrap();
+
+        let path = std::env::temp_dir().join(format!("nfse-signer-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+        let daemon_key = private_key.clone();
+        let daemon = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                std::io::BufReader::new(&stream)
+                    .read_line(&mut request)
+                    .unwrap();
+                let response = match request.trim_end().split_once(' ') {
+                    Some(("sha256", payload)) => {
+                        let payload = Base64::decode_vec(payload).unwrap();
+                        let signature =
+                            Signer::sign(&daemon_key, HashAlgorithm::Sha256, &payload).unwrap();
+                        format!("{}\n", Base64::encode_string(&signature))
+                    }
+                    _ => String::from("ERROR unsupported\n"),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let signer = super::UnixSocketSigner::new(path.to_string_lossy().to_string());
+
+        assert_eq!(
+            signer.sign(HashAlgorithm::Sha256, b"abc").unwrap(),
+            Signer::sign(&private_key, HashAlgorithm::Sha256, b"abc").unwrap()
+        );
+        assert_eq!(
+            signer
+                .sign(HashAlgorithm::Sha1, b"abc")
+                .unwrap_err()
+                .to_string(),
+            "signing error: signer refused: unsupported"
+        );
+
+        daemon.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // needs SoftHSM, with SOFTHSM2_CONF pointing to its configuration and SOFTHSM2_MODULE to its
+    // library, e.g. SOFTHSM2_MODULE=/usr/lib/softhsm/libsofthsm2.so cargo test --features pkcs11 -- --ignored
+    #[cfg(feature = "pkcs11")]
+    #[test]
+    #[ignore]
+    fn should_sign_through_softhsm() {
+        use cryptoki::context::{CInitializeArgs, Pkcs11};
+        use cryptoki::object::{Attribute, KeyType, ObjectClass};
+        use cryptoki::session::UserType;
+        use cryptoki::types::AuthPin;
+        use rsa::traits::{PrivateKeyParts, PublicKeyParts};
+
+        use crate::algorithms::CanonicalizationAlgorithm;
+        use crate::algorithms::DigestAlgorithm;
+        use crate::algorithms::SignatureAlgorithm;
+        use crate::signature::SignatureVerification;
+        use crate::signature::XmlSignature;
+        use crate::utils;
+
+        const CERTIFICATE: &str = include_str!("../tests/fixtures/certificado.cer");
+
+        let module = std::env::var("SOFTHSM2_MODULE")
+            .expect("SOFTHSM2_MODULE must point to the SoftHSM library");
+        let token_label = format!("nfse-{}", std::process::id());
+
+        let private_key =
+            <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_pem(PRIVATE_KEY)
+                .unwrap();
+
+        // the context is finalized at the end of the block, before the signer loads the module
+        {
+            let pkcs11 = Pkcs11::new(&module).unwrap();
+            pkcs11.initialize(CInitializeArgs::OsThreads).unwrap();
+
+            // a free slot, so existing tokens are left alone
+            let slot = pkcs11
+                .get_all_slots()
+                .unwrap()
+                .into_iter()
+                .find(|slot| !pkcs11.get_token_info(*slot).unwrap().token_initialized())
+                .unwrap();
+            let so_pin = AuthPin::new(String::from("so-pin"));
+            pkcs11.init_token(slot, &so_pin, &token_label).unwrap();
+
+            let session = pkcs11.open_rw_session(slot).unwrap();
+            session.login(UserType::So, Some(&so_pin)).unwrap();
+            session
+                .init_pin(&AuthPin::new(String::from("1234")))
+                .unwrap();
+            session.logout().unwrap();
+            session
+                .login(UserType::User, Some(&AuthPin::new(String::from("1234"))))
+                .unwrap();
+
+            let primes = private_key.primes();
+            session
+                .create_object(&[
+                    Attribute::Class(ObjectClass::PRIVATE_KEY),
+                    Attribute::KeyType(KeyType::RSA),
+                    Attribute::Token(true),
+                    Attribute::Private(true),
+                    Attribute::Sign(true),
+                    Attribute::Label(b"nfse".to_vec()),
+                    Attribute::Modulus(private_key.n().to_bytes_be()),
+                    Attribute::PublicExponent(private_key.e().to_bytes_be()),
+                    Attribute::PrivateExponent(private_key.d().to_bytes_be()),
+                    Attribute::Prime1(primes[0].to_bytes_be()),
+                    Attribute::Prime2(primes[1].to_bytes_be()),
+                    Attribute::Exponent1(private_key.dp().unwrap().to_bytes_be()),
+                    Attribute::Exponent2(private_key.dq().unwrap().to_bytes_be()),
+                    Attribute::Coefficient(private_key.crt_coefficient().unwrap().to_bytes_be()),
+                ])
+                .unwrap();
+        }
+
+        let signer =
+            super::Pkcs11Signer::new(&module, &token_label, "nfse", String::from("1234")).unwrap();
+
+        assert_eq!(
+            signer.sign(HashAlgorithm::Sha256, b"abc").unwrap(),
+            Signer::sign(&private_key, HashAlgorithm::Sha256, b"abc").unwrap()
+        );
+
+        let mut signature = XmlSignature::new(
+            CanonicalizationAlgorithm::ExclusiveXMLCanonicalization,
+            SignatureAlgorithm::RsaSha256(std::sync::Arc::new(signer)),
+            DigestAlgorithm::Sha256,
+            utils::trim_x509_certificate(CERTIFICATE),
+        );
+        signature.load(
+            String::from("#payload"),
+            String::from(
+                r##"<Payload xmlns="http://www.abrasf.org.br/nfse.xsd" Id="payload">To Sign</Payload>"##,
+            ),
+        );
+        signature.sign().unwrap();
+
+        let signed = format!(
+            r##"<Document xmlns="http://www.abrasf.org.br/nfse.xsd"><Payload Id="payload">To Sign</Payload>{}</Document>"##,
+            utils::xml_events_to_xml_string(&signature.signature_xml_events())
+        );
+
+        let verifications = SignatureVerification::from_xml_string(&signed).unwrap();
+        assert_eq!(verifications.len(), 1);
+        assert!(verifications[0].is_valid());
+    }
+}
@@ -1,8 +1,8 @@
 use std::io::Cursor;
-use std::iter;
 
 use quick_xml::{events::Event, Writer};
-use rand::Rng;
+
+use crate::curl::{ClientCertificate, Request, RequestMethod};
 
 pub fn trim_x509_certificate(cert: &str) -> String {
     cert.lines().fold(String::new(), |mut result, line| {
@@ -21,15 +21,36 @@ pub fn xml_events_to_xml_string(events: &[Event]) -> String {
     String::from_utf8(writer.into_inner().into_inner()).unwrap()
 }
 
-pub fn generate_random_rps() -> String {
-    const CHARSET: &[u8] = b"0123456789";
-    let mut rng = rand::thread_rng();
-    let one_char = || CHARSET[rng.gen_range(0..CHARSET.len())] as char;
-    iter::repeat_with(one_char).take(12).collect()
+pub fn recepcionar_lote_rps_request_wrapper(content: &str) -> String {
+    request_wrapper("RecepcionarLoteRps", content)
 }
 
-pub fn recepcionar_lote_rps_request_wrapper(content: &str) -> String {
+pub fn request_wrapper(operation: &str, content: &str) -> String {
     format!(
-        r##"<soapenv:Envelope xmlns:soapenv="http://schemas.xmlsoap.org/soap/envelope/" xmlns:ws="http://ws.bhiss.pbh.gov.br"><soapenv:Body><ws:RecepcionarLoteRpsRequest><nfseCabecMsg><![CDATA[<?xml version="1.0" encoding="UTF-8"?><cabecalho xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><versaoDados>1.00</versaoDados></cabecalho>]]></nfseCabecMsg><nfseDadosMsg><![CDATA[<?xml version="1.0" encoding="UTF-8"?>{content}]]></nfseDadosMsg></ws:RecepcionarLoteRpsRequest></soapenv:Body></soapenv:Envelope>"##
+        r##"<soapenv:Envelope xmlns:soapenv="http://schemas.xmlsoap.org/soap/envelope/" xmlns:ws="http://ws.bhiss.pbh.gov.br"><soapenv:Body><ws:{operation}Request><nfseCabecMsg><![CDATA[<?xml version="1.0" encoding="UTF-8"?><cabecalho xmlns="http://www.abrasf.org.br/nfse.xsd" versao="1.00"><versaoDados>1.00</versaoDados></cabecalho>]]></nfseCabecMsg><nfseDadosMsg><![CDATA[<?xml version="1.0" encoding="UTF-8"?>{content}]]></nfseDadosMsg></ws:{operation}Request></soapenv:Body></soapenv:Envelope>"##
     )
 }
+
+pub fn webservice_request(
+    production: bool,
+    certificado_pem_file: Option<String>,
+    client_certificate: Option<ClientCertificate>,
+    soap_action: &str,
+    request_data: String,
+) -> Request {
+    Request::new()
+        .set_certificate_path(certificado_pem_file)
+        .set_client_certificate(client_certificate)
+        .set_url(if production {
+            String::from("https://bhissdigitalws.pbh.gov.br/bhiss-ws/nfse")
+        } else {
+            String::from("https://bhisshomologaws.pbh.gov.br/bhiss-ws/nfse")
+        })
+        .set_header(
+            String::from("Accept"),
+            Some(String::from("application/xml")),
+        )
+        .set_header(String::from("Content-Type"), Some(String::from("text/xml")))
+        .set_header(String::from("SOAPAction"), Some(String::from(soap_action)))
+        .set_method(RequestMethod::POST(request_data))
+}
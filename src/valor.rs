@@ -0,0 +1,216 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::Add;
+use std::ops::Sub;
+use std::str::FromStr;
+
+use crate::error::Error;
+
+// amount of money in centavos, so sums and differences are exact
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Valor(i64);
+
+// aliquota in ten-thousandths, the precision of tsAliquota
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Aliquota(i64);
+
+impl Valor {
+    pub fn from_centavos(centavos: i64) -> Self {
+        Self(centavos)
+    }
+}
+
+impl Valor {
+    pub fn centavos(&self) -> i64 {
+        self.0
+    }
+}
+
+impl Valor {
+    // the product is rounded to centavos following ABNT NBR 5891
+    pub fn multiplicar(&self, aliquota: Aliquota) -> Self {
+        Self(arredondar(self.0 as i128 * aliquota.0 as i128, 10_000) as i64)
+    }
+}
+
+impl Aliquota {
+    pub fn from_decimos_milesimos(decimos_milesimos: i64) -> Self {
+        Self(decimos_milesimos)
+    }
+}
+
+// ABNT NBR 5891: below half rounds down, above half rounds up, and exactly half rounds to the even
+// digit, so 0.125 becomes 0.12 and 0.135 becomes 0.14
+fn arredondar(value: i128, divisor: i128) -> i128 {
+    let quociente = value.div_euclid(divisor);
+    let resto = value.rem_euclid(divisor);
+
+    match (2 * resto).cmp(&divisor) {
+        std::cmp::Ordering::Less => quociente,
+        std::cmp::Ordering::Greater => quociente + 1,
+        std::cmp::Ordering::Equal => quociente + quociente.rem_euclid(2),
+    }
+}
+
+// unsigned decimal such as 1000, 95.3 or 0.0217, as digits and the number of decimal places
+fn decimal(s: &str) -> Result<(i128, u32), Error> {
+    let s = s.trim();
+    let (inteiro, fracao) = s.split_once('.').unwrap_or((s, ""));
+
+    if inteiro.is_empty()
+        || inteiro.len() + fracao.len() > 30
+        || !inteiro
+            .bytes()
+            .chain(fracao.bytes())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(Error::input("", "not a number"));
+    }
+
+    let digits = format!("{inteiro}{fracao}")
+        .parse::<i128>()
+        .map_err(|_| Error::input("", "not a number"))?;
+
+    Ok((digits, fracao.len() as u32))
+}
+
+impl FromStr for Valor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, casas) = decimal(s)?;
+
+        let centavos = match casas {
+            0..=2 => digits * 10_i128.pow(2 - casas),
+            _ => arredondar(digits, 10_i128.pow(casas - 2)),
+        };
+
+        i64::try_from(centavos)
+            .map(Self)
+            .map_err(|_| Error::input("", "too large"))
+    }
+}
+
+impl FromStr for Aliquota {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, casas) = decimal(s)?;
+
+        if casas > 4 {
+            return Err(Error::input("", "more than 4 decimal places"));
+        }
+
+        i64::try_from(digits * 10_i128.pow(4 - casas))
+            .map(Self)
+            .map_err(|_| Error::input("", "too large"))
+    }
+}
+
+impl fmt::Display for Valor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sinal = if self.0 < 0 { "-" } else { "" };
+        let centavos = self.0.unsigned_abs();
+        write!(f, "{sinal}{}.{:02}", centavos / 100, centavos % 100)
+    }
+}
+
+impl fmt::Display for Aliquota {
+    // as few decimal places as needed, but at least two, e.g. 0.02 and 0.0217
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimal = format!("{}.{:04}", self.0 / 10_000, self.0 % 10_000);
+        let decimal = decimal.trim_end_matches('0');
+        let casas = decimal.len() - decimal.find('.').unwrap_or(decimal.len()) - 1;
+        write!(f, "{decimal}{}", "0".repeat(2_usize.saturating_sub(casas)))
+    }
+}
+
+impl Add for Valor {
+    type Output = Valor;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Valor {
+    type Output = Valor;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Sum for Valor {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Valor> for Valor {
+    fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Aliquota;
+    use super::Valor;
+
+    #[test]
+    fn should_parse_format_and_round_values() {
+        assert_eq!("1000".parse::<Valor>().unwrap().to_string(), "1000.00");
+        assert_eq!("95.3".parse::<Valor>().unwrap().to_string(), "95.30");
+        assert_eq!("0.125".parse::<Valor>().unwrap().to_string(), "0.12");
+        assert_eq!("0.135".parse::<Valor>().unwrap().to_string(), "0.14");
+        assert_eq!("0.1251".parse::<Valor>().unwrap().to_string(), "0.13");
+        assert!("1,00".parse::<Valor>().is_err());
+        assert!("-1".parse::<Valor>().is_err());
+        assert!("".parse::<Valor>().is_err());
+
+        assert_eq!("0.0217".parse::<Aliquota>().unwrap().to_string(), "0.0217");
+        assert_eq!("0.02".parse::<Aliquota>().unwrap().to_string(), "0.02");
+        assert_eq!("1".parse::<Aliquota>().unwrap().to_string(), "1.00");
+        assert!("0.02175".parse::<Aliquota>().is_err());
+
+        // 95.31 * 0.0217 = 2.068227
+        assert_eq!(
+            Valor::from_centavos(9531)
+                .multiplicar("0.0217".parse().unwrap())
+                .to_string(),
+            "2.07"
+        );
+        // 12.50 * 0.01 = 0.125 and 13.50 * 0.01 = 0.135
+        assert_eq!(
+            Valor::from_centavos(1250).multiplicar(Aliquota::from_decimos_milesimos(100)),
+            Valor::from_centavos(12)
+        );
+        assert_eq!(
+            Valor::from_centavos(1350).multiplicar(Aliquota::from_decimos_milesimos(100)),
+            Valor::from_centavos(14)
+        );
+        assert_eq!(Valor::from_centavos(-5).to_string(), "-0.05");
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn should_round_products_to_the_nearest_centavo_with_ties_to_even(
+            centavos in 0_i64..1_000_000_000_000,
+            aliquota in 0_i64..=10_000,
+        ) {
+            let valor = Valor::from_centavos(centavos);
+            proptest::prop_assert_eq!(valor.to_string().parse::<Valor>().unwrap(), valor);
+
+            // exact product in millionths against the rounded one in centavos
+            let exato = centavos as i128 * aliquota as i128;
+            let arredondado = valor.multiplicar(Aliquota::from_decimos_milesimos(aliquota)).centavos() as i128;
+            let diferenca = (exato - arredondado * 10_000).abs();
+
+            proptest::prop_assert!(diferenca <= 5_000);
+            if diferenca == 5_000 {
+                proptest::prop_assert_eq!(arredondado % 2, 0);
+            }
+        }
+    }
+}